@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Context, Result};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
@@ -11,6 +12,10 @@ use crate::ui::colors::AppColors;
 // bottom of the screen in the UI.
 pub const MESSAGE_TIME: u64 = 5000;
 
+// How far, in seconds, the playback position needs to move before it
+// is saved to the database again while an episode is playing.
+pub const POSITION_SAVE_INTERVAL: i64 = 10;
+
 // How many columns we need, minimum, before we display the
 // (unplayed/total) after the podcast title
 pub const PODCAST_UNPLAYED_TOTALS_LENGTH: usize = 25;
@@ -31,6 +36,26 @@ pub const DETAILS_PANEL_LENGTH: u16 = 135;
 // in relation to the rows eg: 4 = 1/4 of the screen
 pub const BIG_SCROLL_AMOUNT: u16 = 4;
 
+// How much the playback speed changes with each speed up/down, when
+// using the mpv integration
+pub const SPEED_STEP: f64 = 0.1;
+
+// Range of playback speeds that can be set for a podcast
+pub const MIN_SPEED: f64 = 0.25;
+pub const MAX_SPEED: f64 = 4.0;
+
+// Maximum number of episodes shown when searching
+pub const SEARCH_LIMIT: usize = 100;
+
+// How long, in milliseconds, to wait for another process (e.g., a
+// `shellcaster sync` run from cron) to finish writing to the database
+// before giving up
+pub const DB_BUSY_TIMEOUT: u64 = 10000;
+
+// How often, in milliseconds, the UI checks whether another process has
+// changed the database, so that it can reload
+pub const DB_CHECK_INTERVAL: u64 = 2000;
+
 
 /// Identifies the user's selection for what to do with new episodes
 /// when syncing.
@@ -42,14 +67,70 @@ pub enum DownloadNewEpisodes {
     Never,
 }
 
+/// Identifies whether shellcaster should control the media player
+/// directly, and if so, which one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerIntegration {
+    None,
+    Mpv,
+}
+
+/// Identifies when an episode should be marked as played after the
+/// user starts playing it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MarkPlayed {
+    Launch,
+    Exit,
+    Position,
+}
+
+/// Identifies what the sleep timer does to the player when it runs out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SleepAction {
+    Pause,
+    Stop,
+}
+
+/// Identifies which API a sync server speaks: that of gpodder.net,
+/// which self-hosted servers such as opodsync also speak, or that of
+/// the Nextcloud gpoddersync app.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncApi {
+    Gpodder,
+    Nextcloud,
+}
+
+/// Holds the user's account on a gpodder.net-compatible server, used
+/// to sync subscriptions and played episodes with other devices.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpodderConfig {
+    pub server: String,
+    pub username: String,
+    pub password: String,
+    pub device: String,
+    pub api: SyncApi,
+}
+
 /// Holds information about user configuration of program.
 #[derive(Debug, Clone)]
 pub struct Config {
     pub download_path: PathBuf,
     pub play_command: String,
+    pub play_command_shell: bool,
+    pub play_commands: HashMap<String, String>,
+    pub podcast_play_commands: HashMap<String, String>,
+    pub player_integration: PlayerIntegration,
+    pub seek_length: i64,
+    pub mark_as_played: MarkPlayed,
+    pub played_threshold: i64,
+    pub sleep_timer_presets: Vec<u64>,
+    pub sleep_timer_action: SleepAction,
     pub download_new_episodes: DownloadNewEpisodes,
     pub simultaneous_downloads: usize,
     pub max_retries: usize,
+    pub prioritize_queued_downloads: bool,
+    pub auto_backups: usize,
+    pub gpodder: Option<GpodderConfig>,
     pub keybindings: Keybindings,
     pub colors: AppColors,
 }
@@ -60,13 +141,36 @@ pub struct Config {
 struct ConfigFromToml {
     download_path: Option<String>,
     play_command: Option<String>,
+    play_command_shell: Option<bool>,
+    play_commands: Option<HashMap<String, String>>,
+    podcast_play_commands: Option<HashMap<String, String>>,
+    player_integration: Option<String>,
+    seek_length: Option<i64>,
+    mark_as_played: Option<String>,
+    played_threshold: Option<i64>,
+    sleep_timer_presets: Option<Vec<u64>>,
+    sleep_timer_action: Option<String>,
     download_new_episodes: Option<String>,
     simultaneous_downloads: Option<usize>,
     max_retries: Option<usize>,
+    prioritize_queued_downloads: Option<bool>,
+    auto_backups: Option<usize>,
+    gpodder: Option<GpodderFromToml>,
     keybindings: Option<KeybindingsFromToml>,
     colors: Option<AppColorsFromToml>,
 }
 
+/// A temporary struct used to deserialize the gpodder.net account from
+/// the TOML configuration file.
+#[derive(Debug, Deserialize)]
+struct GpodderFromToml {
+    server: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    device: Option<String>,
+    api: Option<String>,
+}
+
 /// A temporary struct used to deserialize keybinding data from the TOML
 /// configuration file.
 #[derive(Debug, Deserialize)]
@@ -87,14 +191,41 @@ pub struct KeybindingsFromToml {
     pub play: Option<Vec<String>>,
     pub mark_played: Option<Vec<String>>,
     pub mark_all_played: Option<Vec<String>>,
+    pub star: Option<Vec<String>>,
     pub download: Option<Vec<String>>,
     pub download_all: Option<Vec<String>>,
     pub delete: Option<Vec<String>>,
     pub delete_all: Option<Vec<String>>,
     pub remove: Option<Vec<String>>,
     pub remove_all: Option<Vec<String>>,
+    pub archive: Option<Vec<String>>,
+    pub undo: Option<Vec<String>>,
+    pub unhide: Option<Vec<String>>,
     pub filter_played: Option<Vec<String>>,
     pub filter_downloaded: Option<Vec<String>>,
+    pub filter_tag: Option<Vec<String>>,
+    pub filter_starred: Option<Vec<String>>,
+    pub filter_archived: Option<Vec<String>>,
+    pub filter_hidden: Option<Vec<String>>,
+    pub play_pause: Option<Vec<String>>,
+    pub seek_forward: Option<Vec<String>>,
+    pub seek_backward: Option<Vec<String>>,
+    pub speed_up: Option<Vec<String>>,
+    pub speed_down: Option<Vec<String>>,
+    pub stop_playback: Option<Vec<String>>,
+    pub enqueue: Option<Vec<String>>,
+    pub enqueue_next: Option<Vec<String>>,
+    pub show_queue: Option<Vec<String>>,
+    pub move_up: Option<Vec<String>>,
+    pub move_down: Option<Vec<String>>,
+    pub podcast_settings: Option<Vec<String>>,
+    pub edit_tags: Option<Vec<String>>,
+    pub show_stats: Option<Vec<String>>,
+    pub search: Option<Vec<String>>,
+    pub add_note: Option<Vec<String>>,
+    pub show_notes: Option<Vec<String>>,
+    pub sleep_timer: Option<Vec<String>>,
+    pub sleep_timer_custom: Option<Vec<String>>,
     pub help: Option<Vec<String>>,
     pub quit: Option<Vec<String>>,
 }
@@ -152,14 +283,41 @@ impl Config {
                     play: None,
                     mark_played: None,
                     mark_all_played: None,
+                    star: None,
                     download: None,
                     download_all: None,
                     delete: None,
                     delete_all: None,
                     remove: None,
                     remove_all: None,
+                    archive: None,
+                    undo: None,
+                    unhide: None,
                     filter_played: None,
                     filter_downloaded: None,
+                    filter_tag: None,
+                    filter_starred: None,
+                    filter_archived: None,
+                    filter_hidden: None,
+                    play_pause: None,
+                    seek_forward: None,
+                    seek_backward: None,
+                    speed_up: None,
+                    speed_down: None,
+                    stop_playback: None,
+                    enqueue: None,
+                    enqueue_next: None,
+                    show_queue: None,
+                    move_up: None,
+                    move_down: None,
+                    podcast_settings: None,
+                    edit_tags: None,
+                    show_stats: None,
+                    search: None,
+                    add_note: None,
+                    show_notes: None,
+                    sleep_timer: None,
+                    sleep_timer_custom: None,
                     help: None,
                     quit: None,
                 };
@@ -179,9 +337,21 @@ impl Config {
                 ConfigFromToml {
                     download_path: None,
                     play_command: None,
+                    play_command_shell: None,
+                    play_commands: None,
+                    podcast_play_commands: None,
+                    player_integration: None,
+                    seek_length: None,
+                    mark_as_played: None,
+                    played_threshold: None,
+                    sleep_timer_presets: None,
+                    sleep_timer_action: None,
                     download_new_episodes: None,
                     simultaneous_downloads: None,
                     max_retries: None,
+                    prioritize_queued_downloads: None,
+                    auto_backups: None,
+                    gpodder: None,
                     keybindings: Some(keybindings),
                     colors: Some(colors),
                 }
@@ -222,6 +392,50 @@ fn config_with_defaults(config_toml: ConfigFromToml) -> Result<Config> {
         None => "vlc %s".to_string(),
     };
 
+    // file types are matched case-insensitively, and extensions may be
+    // given with or without the leading dot
+    let play_commands = config_toml
+        .play_commands
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(key, cmd)| (key.trim_start_matches('.').to_lowercase(), cmd))
+        .collect();
+
+    let player_integration = match config_toml.player_integration.as_deref() {
+        Some("mpv") => PlayerIntegration::Mpv,
+        Some(_) | None => PlayerIntegration::None,
+    };
+
+    let seek_length = match config_toml.seek_length {
+        Some(num) if num > 0 => num,
+        Some(_) => 30,
+        None => 30,
+    };
+
+    let mark_as_played = match config_toml.mark_as_played.as_deref() {
+        Some("launch") => MarkPlayed::Launch,
+        Some("exit") => MarkPlayed::Exit,
+        Some("position") => MarkPlayed::Position,
+        Some(_) | None => MarkPlayed::Launch,
+    };
+
+    let played_threshold = match config_toml.played_threshold {
+        Some(num) if num > 0 && num <= 100 => num,
+        Some(_) => 95,
+        None => 95,
+    };
+
+    let mut sleep_timer_presets = config_toml
+        .sleep_timer_presets
+        .unwrap_or_else(|| vec![15, 30, 45, 60]);
+    sleep_timer_presets.retain(|mins| *mins > 0);
+
+    let sleep_timer_action = match config_toml.sleep_timer_action.as_deref() {
+        Some("pause") => SleepAction::Pause,
+        Some("stop") => SleepAction::Stop,
+        Some(_) | None => SleepAction::Pause,
+    };
+
     let download_new_episodes = match config_toml.download_new_episodes.as_deref() {
         Some("always") => DownloadNewEpisodes::Always,
         Some("ask-selected") => DownloadNewEpisodes::AskSelected,
@@ -242,12 +456,60 @@ fn config_with_defaults(config_toml: ConfigFromToml) -> Result<Config> {
         None => 3,
     };
 
+    // syncing with a gpodder.net-compatible server is only set up once
+    // there is an account to sync with
+    let gpodder = match config_toml.gpodder {
+        Some(GpodderFromToml {
+            username: None,
+            password: None,
+            ..
+        })
+        | None => None,
+        Some(gp) => {
+            let (username, password) = match (gp.username, gp.password) {
+                (Some(username), Some(password)) => (username, password),
+                _ => {
+                    return Err(anyhow!(
+                        "Syncing with gpodder needs both a username and a password in config.toml."
+                    ))
+                }
+            };
+            let api = match gp.api.as_deref() {
+                Some("nextcloud") => SyncApi::Nextcloud,
+                Some(_) | None => SyncApi::Gpodder,
+            };
+            Some(GpodderConfig {
+                server: gp
+                    .server
+                    .unwrap_or_else(|| "https://gpodder.net".to_string())
+                    .trim_end_matches('/')
+                    .to_string(),
+                username: username,
+                password: password,
+                device: gp.device.unwrap_or_else(|| "shellcaster".to_string()),
+                api: api,
+            })
+        }
+    };
+
     return Ok(Config {
         download_path: download_path,
         play_command: play_command,
+        play_command_shell: config_toml.play_command_shell.unwrap_or(false),
+        play_commands: play_commands,
+        podcast_play_commands: config_toml.podcast_play_commands.unwrap_or_default(),
+        player_integration: player_integration,
+        seek_length: seek_length,
+        mark_as_played: mark_as_played,
+        played_threshold: played_threshold,
+        sleep_timer_presets: sleep_timer_presets,
+        sleep_timer_action: sleep_timer_action,
         download_new_episodes: download_new_episodes,
         simultaneous_downloads: simultaneous_downloads,
         max_retries: max_retries,
+        prioritize_queued_downloads: config_toml.prioritize_queued_downloads.unwrap_or(false),
+        auto_backups: config_toml.auto_backups.unwrap_or(5),
+        gpodder: gpodder,
         keybindings: keymap,
         colors: colors,
     });
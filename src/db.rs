@@ -1,15 +1,88 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use ahash::AHashMap;
-use chrono::{DateTime, NaiveDateTime, Utc};
+use ahash::{AHashMap, AHashSet};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
 use lazy_static::lazy_static;
 use regex::Regex;
-use rusqlite::{params, Connection};
-use semver::Version;
+use rusqlite::backup::Progress;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{
+    params, params_from_iter, Connection, DatabaseName, OpenFlags, Row, Transaction,
+    TransactionBehavior,
+};
+use serde_json::Value as JsonValue;
 
+use crate::gpodder::{self, ActionKind, EpisodeAction, GpodderState};
+use crate::json::{EpisodeState, ImportReport, NoteState, PlayState, PodcastState};
 use crate::types::*;
 
+/// Lock held while the UI is running (or while the database is being
+/// replaced), so that only one process at a time does so with the same
+/// database. It is released when this is dropped, and by the operating
+/// system if shellcaster exits for any other reason.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Takes the lock for the database in the directory `path`. Returns
+    /// an error if another process already holds it.
+    pub fn acquire(path: &Path) -> Result<InstanceLock> {
+        std::fs::create_dir_all(path)
+            .with_context(|| "Unable to create subdirectory for database.")?;
+        let lock_path = path.join("shellcaster.lock");
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+            .with_context(|| format!("Could not open {}", lock_path.to_string_lossy()))?;
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => {
+                // the lock file holds the id of the process that has it
+                let mut pid = String::new();
+                let _ = file.read_to_string(&mut pid);
+                let pid = match pid.trim() {
+                    "" => "".to_string(),
+                    pid => format!(" (process {pid})"),
+                };
+                return Err(anyhow!(
+                    "shellcaster is already running{pid} with the database at {}",
+                    path.to_string_lossy()
+                ));
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(err)
+                    .with_context(|| format!("Could not lock {}", lock_path.to_string_lossy()));
+            }
+        }
+        file.set_len(0)?;
+        write!(file, "{}", std::process::id())?;
+        return Ok(InstanceLock { _file: file });
+    }
+}
+
+/// Tables holding a podcast's data, with the condition that selects the
+/// podcast's rows, in an order in which the rows can be put back without
+/// breaking foreign keys.
+const PODCAST_TABLES: [(&str, &str); 8] = [
+    ("podcasts", "id = ?1"),
+    ("episodes", "podcast_id = ?1"),
+    ("files", "episode_id IN (SELECT id FROM episodes WHERE podcast_id = ?1)"),
+    ("device_files", "episode_id IN (SELECT id FROM episodes WHERE podcast_id = ?1)"),
+    ("queue", "episode_id IN (SELECT id FROM episodes WHERE podcast_id = ?1)"),
+    ("history", "podcast_id = ?1"),
+    ("tags", "podcast_id = ?1"),
+    ("notes", "episode_id IN (SELECT id FROM episodes WHERE podcast_id = ?1)"),
+];
+
 lazy_static! {
     /// Regex for removing "A", "An", and "The" from the beginning of
     /// podcast titles
@@ -17,7 +90,10 @@ lazy_static! {
 }
 
 
+/// What changed when a podcast was added or synced: the podcast's id,
+/// the episodes that were new, and the ids of those that were updated.
 pub struct SyncResult {
+    pub pod_id: i64,
     pub added: Vec<NewEpisode>,
     pub updated: Vec<i64>,
 }
@@ -32,151 +108,157 @@ pub struct Database {
 
 impl Database {
     /// Creates a new connection to the database (and creates database if
-    /// it does not already exist). Panics if database cannot be accessed.
+    /// it does not already exist), bringing its schema up to date. A
+    /// copy of an existing database is saved before it is migrated.
+    /// Returns an error if the database cannot be accessed, if it was
+    /// created by a newer version of shellcaster, or if a migration
+    /// fails, in which case the database is left unchanged.
     pub fn connect(path: &Path) -> Result<Database> {
         let mut db_path = path.to_path_buf();
         std::fs::create_dir_all(&db_path)
             .with_context(|| "Unable to create subdirectory for database.")?;
         db_path.push("data.db");
-        let conn = Connection::open(&db_path)?;
+        let mut conn = Connection::open(&db_path)?;
+
+        // SQLite defaults to foreign key support off
+        conn.execute("PRAGMA foreign_keys=ON;", params![])
+            .with_context(|| "Could not set database parameters.")?;
+        // the UI and `shellcaster sync` may use the database at the same
+        // time: with write-ahead logging, readers don't block writers,
+        // and writers wait for each other rather than failing
+        conn.busy_timeout(Duration::from_millis(crate::config::DB_BUSY_TIMEOUT))
+            .with_context(|| "Could not set database parameters.")?;
+        conn.query_row("PRAGMA journal_mode=WAL;", params![], |_| Ok(()))
+            .with_context(|| "Could not set database parameters.")?;
+
+        let version: usize = conn.query_row("PRAGMA user_version;", params![], |row| row.get(0))?;
+        if version > MIGRATIONS.len() {
+            return Err(anyhow!(
+                "The database at {} was created by a newer version of shellcaster (schema version {version}, but this version only understands up to {}). Please upgrade shellcaster.",
+                db_path.to_string_lossy(),
+                MIGRATIONS.len()
+            ));
+        }
+        if version < MIGRATIONS.len() {
+            let n_tables: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table';",
+                params![],
+                |row| row.get(0),
+            )?;
+            let backup = if n_tables > 0 {
+                Some(backup_before_migration(&db_path, version)?)
+            } else {
+                None
+            };
+            migrate(&mut conn, version).map_err(|err| match &backup {
+                Some(backup) => err.context(format!(
+                    "Could not update the database. It has not been changed; a copy was also saved to {}",
+                    backup.to_string_lossy()
+                )),
+                None => err.context("Could not create the database."),
+            })?;
+        }
+
         let db_conn = Database {
             path: db_path,
             conn: Some(conn),
         };
-        db_conn.create()?;
+        db_conn.update_version()?;
+        return Ok(db_conn);
+    }
 
-        {
-            let conn = db_conn
-                .conn
-                .as_ref()
-                .expect("Error connecting to database.");
-
-            // SQLite defaults to foreign key support off
-            conn.execute("PRAGMA foreign_keys=ON;", params![])
-                .expect("Could not set database parameters.");
-
-            // get version number stored in database
-            let mut stmt = conn.prepare("SELECT version FROM version WHERE id = 1;")?;
-            let vstr: Result<String, rusqlite::Error> =
-                stmt.query_row(params![], |row| row.get("version"));
-
-            // compare to current app version
-            let curr_ver = Version::parse(crate::VERSION)?;
-
-            match vstr {
-                Ok(vstr) => {
-                    let db_version = Version::parse(&vstr)?;
-                    if db_version < curr_ver {
-                        // any version checks for DB migrations should
-                        // go here first, before we update the version
-
-                        // adding a column to capture episode guids
-                        if db_version <= Version::parse("1.2.1")? {
-                            conn.execute("ALTER TABLE episodes ADD COLUMN guid TEXT;", params![])
-                                .expect("Could not run database migrations.");
-                        }
+    /// Replaces the database in the directory `path` with a backup of
+    /// it, then brings the restored database's schema up to date. The
+    /// backup is checked first, and the current database is backed up
+    /// automatically (see `auto_backup()`) before it is overwritten.
+    /// Returns the path of that automatic backup, if one was taken.
+    pub fn restore(path: &Path, backup: &Path, keep: usize) -> Result<Option<PathBuf>> {
+        check_backup(backup)?;
 
-                        db_conn.update_version(curr_ver, true)?;
-                    }
-                }
-                Err(_) => db_conn.update_version(curr_ver, false)?,
-            }
-        }
+        let mut db_path = path.to_path_buf();
+        std::fs::create_dir_all(&db_path)
+            .with_context(|| "Unable to create subdirectory for database.")?;
+        db_path.push("data.db");
+        let saved = {
+            let mut conn = Connection::open(&db_path)?;
+            let n_tables: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table';",
+                params![],
+                |row| row.get(0),
+            )?;
+            let saved = if n_tables > 0 {
+                auto_backup(&conn, &db_path, keep)?
+            } else {
+                None
+            };
+            conn.restore(DatabaseName::Main, backup, None::<fn(Progress)>)
+                .with_context(|| format!("Could not restore from {}", backup.to_string_lossy()))?;
+            saved
+        };
 
-        return Ok(db_conn);
+        // the backup may be from before the latest migrations
+        Database::connect(path)?;
+        return Ok(saved);
     }
 
-    /// Creates the necessary database tables, if they do not already
-    /// exist. Panics if database cannot be accessed, or if tables cannot
-    /// be created.
-    pub fn create(&self) -> Result<()> {
+    /// Starts a transaction on the connection for making changes. It
+    /// takes the write lock straight away, so that if another process
+    /// is writing at the same time, it waits its turn (up to the busy
+    /// timeout) rather than failing halfway through.
+    fn write_transaction(&self) -> Result<Transaction<'_>> {
         let conn = self.conn.as_ref().expect("Error connecting to database.");
+        return Ok(Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?);
+    }
 
-        // create podcasts table
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS podcasts (
-                id INTEGER PRIMARY KEY NOT NULL,
-                title TEXT NOT NULL,
-                url TEXT NOT NULL UNIQUE,
-                description TEXT,
-                author TEXT,
-                explicit INTEGER,
-                last_checked INTEGER
-            );",
-            params![],
-        )
-        .with_context(|| "Could not create podcasts database table")?;
-
-        // create episodes table
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS episodes (
-                id INTEGER PRIMARY KEY NOT NULL,
-                podcast_id INTEGER NOT NULL,
-                title TEXT NOT NULL,
-                url TEXT NOT NULL,
-                guid TEXT,
-                description TEXT,
-                pubdate INTEGER,
-                duration INTEGER,
-                played INTEGER,
-                hidden INTEGER,
-                FOREIGN KEY(podcast_id) REFERENCES podcasts(id) ON DELETE CASCADE
-            );",
-            params![],
-        )
-        .with_context(|| "Could not create episodes database table")?;
+    /// Returns a number that changes whenever another connection (e.g.,
+    /// another shellcaster process) commits changes to the database.
+    /// Changes made through this connection leave it as it is.
+    pub fn data_version(&self) -> Result<i64> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        return Ok(conn.query_row("PRAGMA data_version;", params![], |row| row.get(0))?);
+    }
 
-        // create files table
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS files (
-                id INTEGER PRIMARY KEY NOT NULL,
-                episode_id INTEGER NOT NULL,
-                path TEXT NOT NULL UNIQUE,
-                FOREIGN KEY (episode_id) REFERENCES episodes(id) ON DELETE CASCADE
-            );",
-            params![],
-        )
-        .with_context(|| "Could not create files database table")?;
+    /// Returns where a backup taken now is saved by default: a file
+    /// named with the current time, in the `backups` directory next to
+    /// the database.
+    pub fn backup_path(&self) -> PathBuf {
+        return timestamped_backup(&self.path, "data");
+    }
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS version (
-                id INTEGER PRIMARY KEY NOT NULL,
-                version TEXT NOT NULL
-            );",
-            params![],
-        )
-        .with_context(|| "Could not create version database table")?;
-        return Ok(());
+    /// Copies the database to `dest`, using SQLite's online backup, so
+    /// that the copy is consistent even if shellcaster is running.
+    /// Will not overwrite an existing file.
+    pub fn backup(&self, dest: &Path) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        return backup_to(conn, dest);
     }
 
-    /// If version stored in database is less than the current version
-    /// of the app, this updates the value stored in the database to
-    /// match.
-    fn update_version(&self, current_version: Version, update: bool) -> Result<()> {
+    /// Backs up the database before something that wipes out a lot of
+    /// data, keeping the `keep` most recent automatic backups and
+    /// deleting older ones. Returns the path of the backup, or None if
+    /// `keep` is 0, i.e., automatic backups are turned off.
+    pub fn auto_backup(&self, keep: usize) -> Result<Option<PathBuf>> {
         let conn = self.conn.as_ref().expect("Error connecting to database.");
+        return auto_backup(conn, &self.path, keep);
+    }
 
-        if update {
-            conn.execute(
-                "UPDATE version SET version = ?
-                WHERE id = ?;",
-                params![current_version.to_string(), 1],
-            )?;
-        } else {
-            conn.execute(
-                "INSERT INTO version (id, version)
-                VALUES (?, ?)",
-                params![1, current_version.to_string()],
-            )?;
-        }
+    /// Records the version of the app that last opened the database.
+    /// This is only informational; the schema version is tracked with
+    /// `PRAGMA user_version`.
+    fn update_version(&self) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        conn.execute(
+            "INSERT OR REPLACE INTO version (id, version)
+            VALUES (1, ?);",
+            params![crate::VERSION],
+        )?;
         return Ok(());
     }
 
     /// Inserts a new podcast and list of podcast episodes into the
     /// database.
     pub fn insert_podcast(&self, podcast: PodcastNoId) -> Result<SyncResult> {
-        let mut conn = Connection::open(&self.path).expect("Error connecting to database.");
-        let tx = conn.transaction()?;
-        // let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let tx = self.write_transaction()?;
         {
             let mut stmt = tx.prepare_cached(
                 "INSERT INTO podcasts (title, url, description, author,
@@ -200,7 +282,7 @@ impl Database {
         }
         let mut ep_ids = Vec::new();
         for ep in podcast.episodes.iter().rev() {
-            let id = self.insert_episode(&tx, pod_id, ep)?;
+            let id = self.insert_episode(&tx, pod_id, &podcast.title, ep)?;
             let new_ep = NewEpisode {
                 id: id,
                 pod_id: pod_id,
@@ -213,24 +295,28 @@ impl Database {
         tx.commit()?;
 
         return Ok(SyncResult {
+            pod_id: pod_id,
             added: ep_ids,
             updated: Vec::new(),
         });
     }
 
-    /// Inserts a podcast episode into the database.
+    /// Inserts a podcast episode into the database, and adds it to the
+    /// search index.
     pub fn insert_episode(
         &self,
         conn: &Connection,
         podcast_id: i64,
+        podcast_title: &str,
         episode: &EpisodeNoId,
     ) -> Result<i64> {
         let pubdate = episode.pubdate.map(|dt| dt.timestamp());
 
         let mut stmt = conn.prepare_cached(
             "INSERT INTO episodes (podcast_id, title, url, guid,
-                description, pubdate, duration, played, hidden)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);",
+                description, pubdate, duration, played, hidden, position,
+                mime_type)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
         )?;
         stmt.execute(params![
             podcast_id,
@@ -242,8 +328,12 @@ impl Database {
             episode.duration,
             false,
             false,
+            0,
+            episode.mime_type,
         ])?;
-        return Ok(conn.last_insert_rowid());
+        let id = conn.last_insert_rowid();
+        index_episode(conn, id, podcast_title, &episode.title, &episode.description)?;
+        return Ok(id);
     }
 
     /// Inserts a filepath to a downloaded episode.
@@ -258,6 +348,38 @@ impl Database {
         return Ok(());
     }
 
+    /// Generates list of all downloaded files in the database, as pairs
+    /// of episode id and filepath.
+    pub fn get_files(&self) -> Result<Vec<(i64, PathBuf)>> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached("SELECT episode_id, path FROM files;")?;
+        let file_iter = stmt.query_map(params![], |row| {
+            let path: String = row.get("path")?;
+            Ok((row.get("episode_id")?, PathBuf::from(path)))
+        })?;
+        let mut files = Vec::new();
+        for file in file_iter {
+            files.push(file?);
+        }
+        return Ok(files);
+    }
+
+    /// Rewrites the stored filepaths for a set of downloaded episodes,
+    /// given as pairs of episode id and new filepath. All paths are
+    /// updated in a single transaction, so either all of them change
+    /// or none of them do.
+    pub fn update_file_paths(&self, files: &[(i64, PathBuf)]) -> Result<()> {
+        let tx = self.write_transaction()?;
+        {
+            let mut stmt = tx.prepare_cached("UPDATE files SET path = ? WHERE episode_id = ?;")?;
+            for (episode_id, path) in files.iter() {
+                stmt.execute(params![path.to_str(), episode_id])?;
+            }
+        }
+        tx.commit()?;
+        return Ok(());
+    }
+
     /// Removes a file listing for an episode from the database when the
     /// user has chosen to delete the file.
     pub fn remove_file(&self, episode_id: i64) -> Result<()> {
@@ -270,25 +392,153 @@ impl Database {
     /// Removes all file listings for the selected episode ids.
     pub fn remove_files(&self, episode_ids: &[i64]) -> Result<()> {
         let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached("DELETE FROM files WHERE episode_id = ?;")?;
+        for episode_id in episode_ids.iter() {
+            stmt.execute(params![episode_id])?;
+        }
+        return Ok(());
+    }
+
+    /// Removes a podcast, all episodes, and files from the database,
+    /// after saving all of its rows (episodes, files, history, notes,
+    /// etc.) in the trash, so that it can be put back with
+    /// `restore_podcast()`. Returns the id of the trash entry.
+    pub fn trash_podcast(&self, podcast_id: i64) -> Result<i64> {
+        let tx = self.write_transaction()?;
+        let mut data = serde_json::Map::new();
+        for (table, condition) in PODCAST_TABLES.iter() {
+            let rows = dump_rows(&tx, table, condition, podcast_id)?;
+            data.insert(table.to_string(), JsonValue::Array(rows));
+        }
+        tx.execute(
+            "INSERT INTO trash (podcast_id, data, removed) VALUES (?, ?, ?);",
+            params![podcast_id, JsonValue::Object(data).to_string(), Utc::now().timestamp()],
+        )?;
+        let trash_id = tx.last_insert_rowid();
+        delete_podcast(&tx, podcast_id)?;
+        tx.commit()?;
+        return Ok(trash_id);
+    }
+
+    /// Puts a podcast that was removed with `trash_podcast()` back, with
+    /// the same ids as before, and deletes the trash entry. Returns the
+    /// id of the podcast. Fails, changing nothing, if the podcast has
+    /// been added again since it was removed.
+    pub fn restore_podcast(&self, trash_id: i64) -> Result<i64> {
+        let tx = self.write_transaction()?;
+        let (podcast_id, data): (i64, String) = tx
+            .query_row(
+                "SELECT podcast_id, data FROM trash WHERE id = ?;",
+                params![trash_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .with_context(|| "The podcast is no longer in the trash")?;
+        let data: JsonValue = serde_json::from_str(&data)?;
+        for (table, _) in PODCAST_TABLES.iter() {
+            if let Some(rows) = data.get(table).and_then(|rows| rows.as_array()) {
+                for row in rows.iter() {
+                    insert_row(&tx, table, row)?;
+                }
+            }
+        }
+        tx.execute(
+            "INSERT INTO episodes_fts (rowid, podcast_title, title, description)
+                SELECT episodes.id, podcasts.title, episodes.title, episodes.description
+                FROM episodes INNER JOIN podcasts ON podcasts.id = episodes.podcast_id
+                WHERE podcasts.id = ?;",
+            params![podcast_id],
+        )?;
+        tx.execute("DELETE FROM trash WHERE id = ?;", params![trash_id])?;
+        tx.commit()?;
+        return Ok(podcast_id);
+    }
+
+    /// Permanently deletes a podcast that was moved to the trash.
+    pub fn remove_trash(&self, trash_id: i64) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        conn.execute("DELETE FROM trash WHERE id = ?;", params![trash_id])?;
+        return Ok(());
+    }
+
+    /// Permanently deletes all podcasts in the trash.
+    pub fn empty_trash(&self) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        conn.execute("DELETE FROM trash;", params![])?;
+        return Ok(());
+    }
+
+    /// Generates list of all episode files that have been copied to the
+    /// given device directory, as pairs of episode id and filepath
+    /// relative to the device directory.
+    pub fn get_device_files(&self, device: &str) -> Result<Vec<(i64, PathBuf)>> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt =
+            conn.prepare_cached("SELECT episode_id, path FROM device_files WHERE device = ?;")?;
+        let file_iter = stmt.query_map(params![device], |row| {
+            let path: String = row.get("path")?;
+            Ok((row.get("episode_id")?, PathBuf::from(path)))
+        })?;
+        let mut files = Vec::new();
+        for file in file_iter {
+            files.push(file?);
+        }
+        return Ok(files);
+    }
 
-        // convert list of episode ids into a comma-separated String
-        let episode_list: Vec<String> = episode_ids.iter().map(|x| x.to_string()).collect();
-        let episodes = episode_list.join(", ");
+    /// Records that an episode file has been copied to the given device
+    /// directory, at a path relative to that directory.
+    pub fn insert_device_file(&self, device: &str, episode_id: i64, path: &Path) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached(
+            "INSERT OR REPLACE INTO device_files (device, episode_id, path)
+                VALUES (?, ?, ?);",
+        )?;
+        stmt.execute(params![device, episode_id, path.to_str()])?;
+        return Ok(());
+    }
 
-        let mut stmt = conn.prepare_cached("DELETE FROM files WHERE episode_id = (?);")?;
-        stmt.execute(params![episodes])?;
+    /// Removes the record of an episode file on the given device
+    /// directory.
+    pub fn remove_device_file(&self, device: &str, episode_id: i64) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt =
+            conn.prepare_cached("DELETE FROM device_files WHERE device = ? AND episode_id = ?;")?;
+        stmt.execute(params![device, episode_id])?;
         return Ok(());
     }
 
-    /// Removes a podcast, all episodes, and files from the database.
-    pub fn remove_podcast(&self, podcast_id: i64) -> Result<()> {
+    /// Returns the play queue, in order, as pairs of podcast id and
+    /// episode id.
+    pub fn get_queue(&self) -> Result<Vec<(i64, i64)>> {
         let conn = self.conn.as_ref().expect("Error connecting to database.");
-        // Note: Because of the foreign key constraints on `episodes`
-        // and `files` tables, all associated episodes for this podcast
-        // will also be deleted, and all associated file entries for
-        // those episodes as well.
-        let mut stmt = conn.prepare_cached("DELETE FROM podcasts WHERE id = ?;")?;
-        stmt.execute(params![podcast_id])?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT episodes.podcast_id, queue.episode_id FROM queue
+                INNER JOIN episodes ON queue.episode_id = episodes.id
+                ORDER BY queue.sort_order;",
+        )?;
+        let queue_iter = stmt.query_map(params![], |row| {
+            Ok((row.get("podcast_id")?, row.get("episode_id")?))
+        })?;
+        let mut queue = Vec::new();
+        for item in queue_iter {
+            queue.push(item?);
+        }
+        return Ok(queue);
+    }
+
+    /// Replaces the play queue with the given list of episode ids, in
+    /// the order they will be played.
+    pub fn set_queue(&self, episode_ids: &[i64]) -> Result<()> {
+        let tx = self.write_transaction()?;
+        tx.execute("DELETE FROM queue;", params![])?;
+        {
+            let mut stmt =
+                tx.prepare_cached("INSERT INTO queue (episode_id, sort_order) VALUES (?, ?);")?;
+            for (i, ep_id) in episode_ids.iter().enumerate() {
+                stmt.execute(params![ep_id, i as i64])?;
+            }
+        }
+        tx.commit()?;
         return Ok(());
     }
 
@@ -296,9 +546,14 @@ impl Database {
     /// changed if necessary, and episodes are updated (modified episodes
     /// are updated, new episodes are inserted).
     pub fn update_podcast(&self, pod_id: i64, podcast: PodcastNoId) -> Result<SyncResult> {
+        let tx = self.write_transaction()?;
         {
-            let conn = self.conn.as_ref().expect("Error connecting to database.");
-            let mut stmt = conn.prepare_cached(
+            let old_title: String = tx.query_row(
+                "SELECT title FROM podcasts WHERE id = ?;",
+                params![pod_id],
+                |row| row.get(0),
+            )?;
+            let mut stmt = tx.prepare_cached(
                 "UPDATE podcasts SET title = ?, url = ?, description = ?,
             author = ?, explicit = ?, last_checked = ?
             WHERE id = ?;",
@@ -312,9 +567,18 @@ impl Database {
                 podcast.last_checked.timestamp(),
                 pod_id,
             ])?;
+
+            if podcast.title != old_title {
+                let mut stmt = tx.prepare_cached(
+                    "UPDATE episodes_fts SET podcast_title = ? WHERE rowid IN
+                        (SELECT id FROM episodes WHERE podcast_id = ?);",
+                )?;
+                stmt.execute(params![podcast.title, pod_id])?;
+            }
         }
 
-        let result = self.update_episodes(pod_id, podcast.title, podcast.episodes)?;
+        let result = self.update_episodes(&tx, pod_id, podcast.title, podcast.episodes)?;
+        tx.commit()?;
         return Ok(result);
     }
 
@@ -325,9 +589,11 @@ impl Database {
     /// order to determine if they already exist. As such, an existing
     /// episode that has changed either of these fields will show up as
     /// a "new" episode. The old version will still remain in the
-    /// database.
+    /// database. The changes are made as part of the transaction `tx`,
+    /// which must be on the main connection.
     fn update_episodes(
         &self,
+        tx: &Transaction,
         podcast_id: i64,
         podcast_title: String,
         episodes: Vec<EpisodeNoId>,
@@ -340,9 +606,6 @@ impl Database {
             }
         }
 
-        let mut conn = Connection::open(&self.path).expect("Error connecting to database.");
-        let tx = conn.transaction()?;
-
         let mut insert_ep = Vec::new();
         let mut update_ep = Vec::new();
         for new_ep in episodes.iter().rev() {
@@ -390,7 +653,7 @@ impl Database {
                         let mut stmt = tx.prepare_cached(
                             "UPDATE episodes SET title = ?, url = ?,
                                 guid = ?, description = ?, pubdate = ?,
-                                duration = ? WHERE id = ?;",
+                                duration = ?, mime_type = ? WHERE id = ?;",
                         )?;
                         stmt.execute(params![
                             new_ep.title,
@@ -399,13 +662,30 @@ impl Database {
                             new_ep.description,
                             new_pd,
                             new_ep.duration,
+                            new_ep.mime_type,
                             id,
                         ])?;
+                        index_episode(
+                            tx,
+                            id,
+                            &podcast_title,
+                            &new_ep.title,
+                            &new_ep.description,
+                        )?;
                         update_ep.push(id);
+                    } else if new_ep.mime_type.is_some() {
+                        // episodes synced before the type was stored
+                        // just get it filled in, without counting as
+                        // an update
+                        let mut stmt = tx.prepare_cached(
+                            "UPDATE episodes SET mime_type = ?
+                                WHERE id = ? AND mime_type IS NULL;",
+                        )?;
+                        stmt.execute(params![new_ep.mime_type, id])?;
                     }
                 }
                 None => {
-                    let id = self.insert_episode(&tx, podcast_id, new_ep)?;
+                    let id = self.insert_episode(tx, podcast_id, &podcast_title, new_ep)?;
                     let new_ep = NewEpisode {
                         id: id,
                         pod_id: podcast_id,
@@ -417,8 +697,8 @@ impl Database {
                 }
             }
         }
-        tx.commit()?;
         return Ok(SyncResult {
+            pod_id: podcast_id,
             added: insert_ep,
             updated: update_ep,
         });
@@ -447,125 +727,2162 @@ impl Database {
         return false;
     }
 
+    /// Updates an episode to mark it as starred or not.
+    pub fn set_starred(&self, episode_id: i64, starred: bool) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached("UPDATE episodes SET starred = ? WHERE id = ?;")?;
+        stmt.execute(params![starred, episode_id])?;
+        return Ok(());
+    }
+
     /// Updates an episode to mark it as played or unplayed.
     pub fn set_played_status(&self, episode_id: i64, played: bool) -> Result<()> {
         let conn = self.conn.as_ref().expect("Error connecting to database.");
 
-        let mut stmt = conn.prepare_cached("UPDATE episodes SET played = ? WHERE id = ?;")?;
-        stmt.execute(params![played, episode_id])?;
+        // keep the time an episode was first marked as played
+        let mut stmt = conn.prepare_cached(
+            "UPDATE episodes SET played = ?1,
+                played_at = CASE WHEN ?1 THEN COALESCE(played_at, ?2) ELSE NULL END
+            WHERE id = ?3;",
+        )?;
+        stmt.execute(params![played, Utc::now().timestamp(), episode_id])?;
         return Ok(());
     }
 
-    /// Updates an episode to "remove" it by hiding it. "Removed"
-    /// episodes need to stay in the database so that they don't get
-    /// re-added when the podcast is synced again.
-    pub fn hide_episode(&self, episode_id: i64, hide: bool) -> Result<()> {
+    /// Updates the saved playback position (in seconds) of an episode.
+    pub fn set_position(&self, episode_id: i64, position: i64) -> Result<()> {
         let conn = self.conn.as_ref().expect("Error connecting to database.");
 
-        let mut stmt = conn.prepare_cached("UPDATE episodes SET hidden = ? WHERE id = ?;")?;
-        stmt.execute(params![hide, episode_id])?;
+        let mut stmt = conn.prepare_cached("UPDATE episodes SET position = ? WHERE id = ?;")?;
+        stmt.execute(params![position, episode_id])?;
         return Ok(());
     }
 
-    /// Generates list of all podcasts in database.
-    /// TODO: This should probably use a JOIN statement instead.
-    pub fn get_podcasts(&self) -> Result<Vec<Podcast>> {
+    /// Updates the playback settings of a podcast.
+    pub fn set_podcast_settings(&self, podcast_id: i64, settings: &PodcastSettings) -> Result<()> {
         let conn = self.conn.as_ref().expect("Error connecting to database.");
-        let mut stmt = conn.prepare_cached("SELECT * FROM podcasts;")?;
-        let podcast_iter = stmt.query_map(params![], |row| {
-            let pod_id = row.get("id")?;
-            let episodes = match self.get_episodes(pod_id, false) {
-                Ok(ep_list) => Ok(ep_list),
-                Err(_) => Err(rusqlite::Error::QueryReturnedNoRows),
-            }?;
 
-            // create a sort title that is lowercased and removes
-            // articles from the beginning
-            let title: String = row.get("title")?;
-            let title_lower = title.to_lowercase();
-            let sort_title = RE_ARTICLES.replace(&title_lower, "").to_string();
+        let mut stmt = conn.prepare_cached(
+            "UPDATE podcasts SET speed = ?, skip_intro = ?, skip_outro = ? WHERE id = ?;",
+        )?;
+        stmt.execute(params![
+            settings.speed,
+            settings.skip_intro,
+            settings.skip_outro,
+            podcast_id,
+        ])?;
+        return Ok(());
+    }
 
-            Ok(Podcast {
-                id: pod_id,
-                title: title,
-                sort_title: sort_title,
-                url: row.get("url")?,
-                description: row.get("description")?,
-                author: row.get("author")?,
-                explicit: row.get("explicit")?,
-                last_checked: convert_date(row.get("last_checked")).unwrap(),
-                episodes: LockVec::new(episodes),
+    /// Records that an episode was played, from `started_at` to
+    /// `ended_at`, with `listened` seconds of it heard.
+    pub fn add_history(&self, entry: &HistoryEntry) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO history (episode_id, podcast_id, started_at, ended_at, listened, finished)
+                VALUES (?, ?, ?, ?, ?, ?);",
+        )?;
+        stmt.execute(params![
+            entry.ep_id,
+            entry.pod_id,
+            entry.started_at.timestamp(),
+            entry.ended_at.timestamp(),
+            entry.listened,
+            entry.finished,
+        ])?;
+        return Ok(());
+    }
+
+    /// Retrieves the full listening history, oldest first.
+    pub fn get_history(&self) -> Result<Vec<HistoryEntry>> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT episode_id, podcast_id, started_at, ended_at, listened, finished
+                FROM history ORDER BY started_at;",
+        )?;
+        let history_iter = stmt.query_map(params![], |row| {
+            Ok(HistoryEntry {
+                ep_id: row.get("episode_id")?,
+                pod_id: row.get("podcast_id")?,
+                started_at: convert_date(row.get("started_at")).unwrap_or_else(Utc::now),
+                ended_at: convert_date(row.get("ended_at")).unwrap_or_else(Utc::now),
+                listened: row.get("listened")?,
+                finished: row.get("finished")?,
             })
         })?;
-        let mut podcasts = Vec::new();
-        for pc in podcast_iter {
-            podcasts.push(pc?);
+        let mut history = Vec::new();
+        for entry in history_iter {
+            history.push(entry?);
         }
-        podcasts.sort_unstable();
+        return Ok(history);
+    }
 
-        return Ok(podcasts);
+    /// Returns the last time anything was played from each podcast,
+    /// either from the listening history or from when episodes were
+    /// marked as played. Podcasts that were never played are left out.
+    pub fn get_last_played(&self) -> Result<AHashMap<i64, DateTime<Utc>>> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT podcast_id, MAX(time) AS last_played FROM (
+                SELECT podcast_id, ended_at AS time FROM history
+                UNION ALL
+                SELECT podcast_id, played_at AS time FROM episodes WHERE played_at IS NOT NULL
+            ) GROUP BY podcast_id;",
+        )?;
+        let mut rows = stmt.query(params![])?;
+        let mut last_played = AHashMap::new();
+        while let Some(row) = rows.next()? {
+            let pod_id: i64 = row.get("podcast_id")?;
+            if let Some(date) = convert_date(row.get("last_played")) {
+                last_played.insert(pod_id, date);
+            }
+        }
+        return Ok(last_played);
     }
 
-    /// Generates list of episodes for a given podcast.
-    pub fn get_episodes(&self, pod_id: i64, include_hidden: bool) -> Result<Vec<Episode>> {
+    /// Returns the ids of all episodes that have been marked as played,
+    /// whether or not they were played through shellcaster.
+    pub fn get_played_episodes(&self) -> Result<Vec<i64>> {
         let conn = self.conn.as_ref().expect("Error connecting to database.");
-        let mut stmt = if include_hidden {
-            conn.prepare_cached(
-                "SELECT * FROM episodes
-                        LEFT JOIN files ON episodes.id = files.episode_id
-                        WHERE episodes.podcast_id = ?
-                        ORDER BY pubdate DESC;",
-            )?
-        } else {
-            conn.prepare_cached(
-                "SELECT * FROM episodes
-                        LEFT JOIN files ON episodes.id = files.episode_id
-                        WHERE episodes.podcast_id = ?
-                        AND episodes.hidden = 0
-                        ORDER BY pubdate DESC;",
-            )?
-        };
-        let episode_iter = stmt.query_map(params![pod_id], |row| {
-            let path = match row.get::<&str, String>("path") {
-                Ok(val) => Some(PathBuf::from(val)),
-                Err(_) => None,
-            };
-            Ok(Episode {
+        let mut stmt =
+            conn.prepare_cached("SELECT id FROM episodes WHERE played_at IS NOT NULL;")?;
+        let id_iter = stmt.query_map(params![], |row| row.get("id"))?;
+        let mut ids = Vec::new();
+        for id in id_iter {
+            ids.push(id?);
+        }
+        return Ok(ids);
+    }
+
+    /// Returns the tags given to each podcast, keyed by podcast id. Each
+    /// list of tags is in alphabetical order.
+    pub fn get_tags(&self) -> Result<AHashMap<i64, Vec<String>>> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached("SELECT podcast_id, tag FROM tags ORDER BY tag;")?;
+        let tag_iter = stmt.query_map(params![], |row| {
+            Ok((row.get::<&str, i64>("podcast_id")?, row.get::<&str, String>("tag")?))
+        })?;
+        let mut tags: AHashMap<i64, Vec<String>> = AHashMap::new();
+        for tag in tag_iter {
+            let (pod_id, tag) = tag?;
+            tags.entry(pod_id).or_default().push(tag);
+        }
+        return Ok(tags);
+    }
+
+    /// Replaces the tags given to a podcast.
+    pub fn set_tags(&self, podcast_id: i64, tags: &[String]) -> Result<()> {
+        let tx = self.write_transaction()?;
+        tx.execute("DELETE FROM tags WHERE podcast_id = ?;", params![podcast_id])?;
+        insert_tags(&tx, podcast_id, tags)?;
+        tx.commit()?;
+        return Ok(());
+    }
+
+    /// Adds a note to an episode, optionally tied to a position in the
+    /// episode (in seconds). Returns the new note.
+    pub fn add_note(&self, episode_id: i64, position: Option<i64>, text: &str) -> Result<Note> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let created = Utc::now().timestamp();
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO notes (episode_id, position, text, created)
+                VALUES (?, ?, ?, ?);",
+        )?;
+        stmt.execute(params![episode_id, position, text, created])?;
+        return Ok(Note {
+            id: conn.last_insert_rowid(),
+            ep_id: episode_id,
+            position: position,
+            text: text.to_string(),
+            created: Utc.timestamp(created, 0),
+        });
+    }
+
+    /// Deletes a note.
+    pub fn remove_note(&self, note_id: i64) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached("DELETE FROM notes WHERE id = ?;")?;
+        stmt.execute(params![note_id])?;
+        return Ok(());
+    }
+
+    /// Returns the notes on all episodes of a podcast, keyed by episode
+    /// id. Notes with a position come first, in the order they appear
+    /// in the episode, followed by the rest in the order they were
+    /// written.
+    fn get_notes(&self, pod_id: i64) -> Result<AHashMap<i64, Vec<Note>>> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached(
+            "SELECT notes.* FROM notes
+                INNER JOIN episodes ON episodes.id = notes.episode_id
+                WHERE episodes.podcast_id = ?
+                ORDER BY notes.position IS NULL, notes.position, notes.created, notes.id;",
+        )?;
+        let note_iter = stmt.query_map(params![pod_id], |row| {
+            Ok(Note {
                 id: row.get("id")?,
-                pod_id: row.get("podcast_id")?,
-                title: row.get("title")?,
-                url: row.get("url")?,
-                guid: row
-                    .get::<&str, Option<String>>("guid")?
-                    .unwrap_or_else(|| "".to_string()),
-                description: row.get("description")?,
-                pubdate: convert_date(row.get("pubdate")),
-                duration: row.get("duration")?,
-                path: path,
-                played: row.get("played")?,
+                ep_id: row.get("episode_id")?,
+                position: row.get("position")?,
+                text: row.get("text")?,
+                created: convert_date(row.get("created")).unwrap_or_else(Utc::now),
             })
         })?;
-        let episodes = episode_iter.flatten().collect();
-        return Ok(episodes);
+        let mut notes: AHashMap<i64, Vec<Note>> = AHashMap::new();
+        for note in note_iter {
+            let note = note?;
+            notes.entry(note.ep_id).or_default().push(note);
+        }
+        return Ok(notes);
     }
 
-    /// Deletes all rows in all tables
-    pub fn clear_db(&self) -> Result<()> {
+    /// Updates a podcast to mark it as archived or not.
+    pub fn set_archived(&self, podcast_id: i64, archived: bool) -> Result<()> {
         let conn = self.conn.as_ref().expect("Error connecting to database.");
-        conn.execute("DELETE FROM files;", params![])?;
-        conn.execute("DELETE FROM episodes;", params![])?;
-        conn.execute("DELETE FROM podcasts;", params![])?;
+        let mut stmt = conn.prepare_cached("UPDATE podcasts SET archived = ? WHERE id = ?;")?;
+        stmt.execute(params![archived, podcast_id])?;
         return Ok(());
     }
-}
 
-/// Helper function converting an (optional) Unix timestamp to a
-/// DateTime<Utc> object
-fn convert_date(result: Result<i64, rusqlite::Error>) -> Option<DateTime<Utc>> {
-    return match result {
+    /// Updates an episode to "remove" it by hiding it. "Removed"
+    /// episodes need to stay in the database so that they don't get
+    /// re-added when the podcast is synced again.
+    pub fn hide_episode(&self, episode_id: i64, hide: bool) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+
+        let mut stmt = conn.prepare_cached("UPDATE episodes SET hidden = ? WHERE id = ?;")?;
+        stmt.execute(params![hide, episode_id])?;
+        return Ok(());
+    }
+
+    /// Keeps a record of something the user did with an episode, to be
+    /// uploaded to a gpodder.net-compatible server. Returns the id of
+    /// the record.
+    pub fn add_episode_action(&self, episode_id: i64, action: &ActionKind) -> Result<i64> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let (started, position, total) = match action {
+            ActionKind::Play {
+                started,
+                position,
+                total,
+            } => (Some(*started), Some(*position), Some(*total)),
+            _ => (None, None, None),
+        };
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO episode_actions (podcast_url, episode_url, guid, action,
+                started, position, total, timestamp)
+                SELECT podcasts.url, episodes.url, episodes.guid, ?, ?, ?, ?, ?
+                FROM episodes INNER JOIN podcasts ON podcasts.id = episodes.podcast_id
+                WHERE episodes.id = ?;",
+        )?;
+        stmt.execute(params![
+            action.name(),
+            started,
+            position,
+            total,
+            Utc::now().timestamp(),
+            episode_id
+        ])?;
+        return Ok(conn.last_insert_rowid());
+    }
+
+    /// Returns the episode actions waiting to be uploaded, oldest
+    /// first, along with their ids.
+    pub fn get_episode_actions(&self) -> Result<Vec<(i64, EpisodeAction)>> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached("SELECT * FROM episode_actions ORDER BY id;")?;
+        let action_iter = stmt.query_map(params![], |row| {
+            let timestamp = convert_date(row.get("timestamp")).unwrap();
+            Ok((row.get("id")?, EpisodeAction {
+                podcast: row.get("podcast_url")?,
+                episode: row.get("episode_url")?,
+                guid: row
+                    .get::<&str, Option<String>>("guid")?
+                    .filter(|guid| !guid.is_empty()),
+                device: None,
+                action: row.get("action")?,
+                timestamp: gpodder::format_timestamp(timestamp),
+                started: row.get("started")?,
+                position: row.get("position")?,
+                total: row.get("total")?,
+            }))
+        })?;
+        let mut actions = Vec::new();
+        for action in action_iter {
+            actions.push(action?);
+        }
+        return Ok(actions);
+    }
+
+    /// Removes the episode actions with ids up to `last_id`, once they
+    /// have been uploaded.
+    pub fn remove_episode_actions(&self, last_id: i64) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached("DELETE FROM episode_actions WHERE id <= ?;")?;
+        stmt.execute(params![last_id])?;
+        return Ok(());
+    }
+
+    /// Removes a single episode action before it is uploaded. Returns
+    /// false if it was no longer there, i.e., it has been uploaded.
+    pub fn remove_episode_action(&self, id: i64) -> Result<bool> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached("DELETE FROM episode_actions WHERE id = ?;")?;
+        let removed = stmt.execute(params![id])?;
+        return Ok(removed > 0);
+    }
+
+    /// Finds an episode by the URL of its podcast's feed and its own
+    /// URL, or its guid, returning its id and whether it was played.
+    pub fn find_episode(
+        &self,
+        podcast_url: &str,
+        episode_url: &str,
+        guid: Option<&str>,
+    ) -> Result<Option<(i64, bool)>> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached(
+            "SELECT episodes.id, episodes.played FROM episodes
+                INNER JOIN podcasts ON podcasts.id = episodes.podcast_id
+                WHERE podcasts.url = ?1
+                AND (episodes.url = ?2 OR (?3 IS NOT NULL AND episodes.guid = ?3))
+                ORDER BY episodes.url = ?2 DESC
+                LIMIT 1;",
+        )?;
+        let mut rows = stmt.query(params![podcast_url, episode_url, guid])?;
+        return match rows.next()? {
+            Some(row) => Ok(Some((row.get(0)?, row.get(1)?))),
+            None => Ok(None),
+        };
+    }
+
+    /// Returns how far the given account on a gpodder.net-compatible
+    /// server has been synced, or a fresh state if it never has.
+    pub fn get_gpodder_state(&self, account: &str) -> Result<GpodderState> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached("SELECT * FROM gpodder_state WHERE account = ?;")?;
+        let mut rows = stmt.query(params![account])?;
+        return match rows.next()? {
+            Some(row) => {
+                let subscriptions: String = row.get("subscriptions")?;
+                Ok(GpodderState {
+                    subscriptions_since: row.get("subscriptions_since")?,
+                    actions_since: row.get("actions_since")?,
+                    subscriptions: serde_json::from_str(&subscriptions)?,
+                })
+            }
+            None => Ok(GpodderState::default()),
+        };
+    }
+
+    /// Saves how far the given account on a gpodder.net-compatible
+    /// server has been synced.
+    pub fn set_gpodder_state(&self, account: &str, state: &GpodderState) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached(
+            "INSERT OR REPLACE INTO gpodder_state
+                (account, subscriptions_since, actions_since, subscriptions)
+                VALUES (?, ?, ?, ?);",
+        )?;
+        stmt.execute(params![
+            account,
+            state.subscriptions_since,
+            state.actions_since,
+            serde_json::to_string(&state.subscriptions)?
+        ])?;
+        return Ok(());
+    }
+
+    /// Returns the full state of every podcast: its tags and settings,
+    /// and all of its episodes, including hidden ones, with their played
+    /// status, position, files, notes, and listening history. This is
+    /// what `shellcaster export --format json` writes.
+    pub fn export_state(&self) -> Result<Vec<PodcastState>> {
+        let podcasts = self.get_podcasts(true)?;
+        let mut history: AHashMap<i64, Vec<PlayState>> = AHashMap::new();
+        for entry in self.get_history()?.into_iter() {
+            history.entry(entry.ep_id).or_default().push(PlayState {
+                started_at: entry.started_at.timestamp(),
+                ended_at: entry.ended_at.timestamp(),
+                listened: entry.listened,
+                finished: entry.finished,
+            });
+        }
+        let mut played_at: AHashMap<i64, i64> = AHashMap::new();
+        {
+            let conn = self.conn.as_ref().expect("Error connecting to database.");
+            let mut stmt = conn.prepare_cached(
+                "SELECT id, played_at FROM episodes WHERE played_at IS NOT NULL;",
+            )?;
+            let mut rows = stmt.query(params![])?;
+            while let Some(row) = rows.next()? {
+                played_at.insert(row.get("id")?, row.get("played_at")?);
+            }
+        }
+
+        let mut states = Vec::new();
+        for pod in podcasts.into_iter() {
+            let episodes = pod.episodes.map(
+                |ep| EpisodeState {
+                    title: ep.title.clone(),
+                    url: ep.url.clone(),
+                    guid: ep.guid.clone(),
+                    description: ep.description.clone(),
+                    pubdate: ep.pubdate.map(|date| date.timestamp()),
+                    duration: ep.duration,
+                    mime_type: ep.mime_type.clone(),
+                    played: ep.played,
+                    played_at: played_at.get(&ep.id).copied(),
+                    position: ep.position,
+                    hidden: ep.hidden,
+                    starred: ep.starred,
+                    file: ep.path.clone(),
+                    notes: ep
+                        .notes
+                        .iter()
+                        .map(|note| NoteState {
+                            position: note.position,
+                            text: note.text.clone(),
+                            created: note.created.timestamp(),
+                        })
+                        .collect(),
+                    history: history.remove(&ep.id).unwrap_or_default(),
+                },
+                false,
+            );
+            states.push(PodcastState {
+                title: pod.title,
+                url: pod.url,
+                description: pod.description,
+                author: pod.author,
+                explicit: pod.explicit,
+                last_checked: pod.last_checked.timestamp(),
+                archived: pod.archived,
+                tags: pod.tags,
+                settings: pod.settings,
+                episodes: episodes,
+            });
+        }
+        return Ok(states);
+    }
+
+    /// Brings in the state of podcasts written by `export_state()`, in a
+    /// single transaction. Podcasts are matched by feed URL, and
+    /// episodes by guid, or by URL if they have none; anything not
+    /// already in the database is added. For episodes that are, the
+    /// file's played status, position, and hidden and starred flags
+    /// replace the database's, unless `merge` is set: then the played
+    /// status and position are only taken if the file's episode was
+    /// played more recently, and episodes stay hidden or starred if
+    /// either side has them so. Likewise, podcasts keep their tags and
+    /// settings, or the file's replace them, or with `merge`, gain the
+    /// file's tags and keep any settings the user has changed. Notes and
+    /// listening history are added to, and downloaded files are only
+    /// recorded if they exist on this computer. With `replace`, all
+    /// podcasts are deleted first, in the same transaction, so nothing
+    /// is lost if the import fails.
+    pub fn import_state(
+        &self,
+        podcasts: &[PodcastState],
+        merge: bool,
+        replace: bool,
+    ) -> Result<ImportReport> {
+        let tx = self.write_transaction()?;
+        if replace {
+            clear_tables(&tx)?;
+        }
+        let mut report = ImportReport::default();
+        for podcast in podcasts.iter() {
+            let existing = {
+                let mut stmt = tx.prepare_cached("SELECT id FROM podcasts WHERE url = ?;")?;
+                let mut rows = stmt.query(params![podcast.url])?;
+                match rows.next()? {
+                    Some(row) => Some(row.get::<usize, i64>(0)?),
+                    None => None,
+                }
+            };
+            let pod_id = match existing {
+                Some(pod_id) => {
+                    let current = self.get_podcast_summary(pod_id, true)?;
+                    let mut tags = podcast.tags.clone();
+                    let mut settings = podcast.settings;
+                    let mut archived = podcast.archived;
+                    if merge {
+                        tags.extend(current.tags.iter().cloned());
+                        if current.settings != PodcastSettings::default() {
+                            settings = current.settings;
+                        }
+                        archived = current.archived;
+                    }
+                    tx.execute(
+                        "UPDATE podcasts SET speed = ?, skip_intro = ?, skip_outro = ?,
+                            archived = ? WHERE id = ?;",
+                        params![
+                            settings.speed,
+                            settings.skip_intro,
+                            settings.skip_outro,
+                            archived,
+                            pod_id
+                        ],
+                    )?;
+                    tx.execute("DELETE FROM tags WHERE podcast_id = ?;", params![pod_id])?;
+                    insert_tags(&tx, pod_id, &tags)?;
+                    pod_id
+                }
+                None => {
+                    tx.execute(
+                        "INSERT INTO podcasts (title, url, description, author, explicit,
+                            last_checked, speed, skip_intro, skip_outro, archived)
+                            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+                        params![
+                            podcast.title,
+                            podcast.url,
+                            podcast.description,
+                            podcast.author,
+                            podcast.explicit,
+                            podcast.last_checked,
+                            podcast.settings.speed,
+                            podcast.settings.skip_intro,
+                            podcast.settings.skip_outro,
+                            podcast.archived,
+                        ],
+                    )?;
+                    let pod_id = tx.last_insert_rowid();
+                    insert_tags(&tx, pod_id, &podcast.tags)?;
+                    report.podcasts_added += 1;
+                    pod_id
+                }
+            };
+
+            let mut local = episode_states(&tx, pod_id)?;
+            // episodes are added oldest first, as when syncing
+            for episode in podcast.episodes.iter().rev() {
+                let found = local.iter().position(|(_, ep)| {
+                    if !episode.guid.is_empty() && !ep.guid.is_empty() {
+                        return ep.guid == episode.guid;
+                    }
+                    return ep.url == episode.url;
+                });
+                match found {
+                    Some(index) => {
+                        let (ep_id, current) = local.swap_remove(index);
+                        let mut state = episode.clone();
+                        if merge {
+                            // without times to go by, progress made here
+                            // is kept
+                            let local_newer = current.last_activity() > episode.last_activity()
+                                || (current.last_activity() == episode.last_activity()
+                                    && (current.played || current.position > 0));
+                            if local_newer {
+                                state.played = current.played;
+                                state.played_at = current.played_at;
+                                state.position = current.position;
+                            }
+                            state.hidden |= current.hidden;
+                            state.starred |= current.starred;
+                        }
+                        let changed = state.played != current.played
+                            || state.played_at != current.played_at
+                            || state.position != current.position
+                            || state.hidden != current.hidden
+                            || state.starred != current.starred;
+                        if changed {
+                            set_episode_state(&tx, ep_id, &state)?;
+                            report.episodes_updated += 1;
+                        }
+                        if current.file.is_some() {
+                            state.file = None;
+                        }
+                        state.history.retain(|play| {
+                            !current.history.iter().any(|p| p.started_at == play.started_at)
+                        });
+                        state.notes.retain(|note| {
+                            !current
+                                .notes
+                                .iter()
+                                .any(|n| n.created == note.created && n.text == note.text)
+                        });
+                        add_episode_extras(&tx, pod_id, ep_id, &state)?;
+                    }
+                    None => {
+                        let pubdate = match episode.pubdate {
+                            Some(date) => Some(
+                                Utc.timestamp_opt(date, 0)
+                                    .single()
+                                    .ok_or_else(|| anyhow!("Invalid pubdate: {date}"))?,
+                            ),
+                            None => None,
+                        };
+                        let new_episode = EpisodeNoId {
+                            title: episode.title.clone(),
+                            url: episode.url.clone(),
+                            guid: episode.guid.clone(),
+                            description: episode.description.clone(),
+                            pubdate: pubdate,
+                            duration: episode.duration,
+                            mime_type: episode.mime_type.clone(),
+                        };
+                        let ep_id = self.insert_episode(&tx, pod_id, &podcast.title, &new_episode)?;
+                        set_episode_state(&tx, ep_id, episode)?;
+                        add_episode_extras(&tx, pod_id, ep_id, episode)?;
+                        report.episodes_added += 1;
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+        return Ok(report);
+    }
+
+    /// Generates list of all podcasts in database, with their episodes,
+    /// optionally including hidden episodes.
+    pub fn get_podcasts(&self, include_hidden: bool) -> Result<Vec<Podcast>> {
+        let mut podcasts = self.get_podcast_summaries(include_hidden)?;
+        for pod in podcasts.iter_mut() {
+            pod.episodes = LockVec::new(self.get_episodes(pod.id, include_hidden)?);
+            pod.loaded = true;
+        }
+        return Ok(podcasts);
+    }
+
+    /// Generates list of all podcasts in database without their
+    /// episodes, which can be loaded later with `get_episodes()`. The
+    /// episodes of each podcast are counted instead, optionally
+    /// including hidden episodes.
+    pub fn get_podcast_summaries(&self, include_hidden: bool) -> Result<Vec<Podcast>> {
+        return self.query_podcasts(None, include_hidden);
+    }
+
+    /// Gets a single podcast without its episodes, as with
+    /// `get_podcast_summaries()`.
+    pub fn get_podcast_summary(&self, pod_id: i64, include_hidden: bool) -> Result<Podcast> {
+        return self
+            .query_podcasts(Some(pod_id), include_hidden)?
+            .pop()
+            .ok_or_else(|| anyhow!("No podcast with id {pod_id}"));
+    }
+
+    /// Reads either one podcast or all of them, sorted by title, with
+    /// their episode counts but without their episodes.
+    fn query_podcasts(&self, pod_id: Option<i64>, include_hidden: bool) -> Result<Vec<Podcast>> {
+        let mut tags = self.get_tags()?;
+        let mut counts = self.get_episode_counts(pod_id, include_hidden)?;
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached("SELECT * FROM podcasts WHERE ?1 IS NULL OR id = ?1;")?;
+        let podcast_iter = stmt.query_map(params![pod_id], |row| {
+            let pod_id = row.get("id")?;
+
+            // create a sort title that is lowercased and removes
+            // articles from the beginning
+            let title: String = row.get("title")?;
+            let title_lower = title.to_lowercase();
+            let sort_title = RE_ARTICLES.replace(&title_lower, "").to_string();
+
+            Ok(Podcast {
+                id: pod_id,
+                title: title,
+                sort_title: sort_title,
+                url: row.get("url")?,
+                description: row.get("description")?,
+                author: row.get("author")?,
+                explicit: row.get("explicit")?,
+                last_checked: convert_date(row.get("last_checked")).unwrap(),
+                settings: PodcastSettings {
+                    speed: row.get::<&str, Option<f64>>("speed")?.unwrap_or(1.0),
+                    skip_intro: row.get::<&str, Option<i64>>("skip_intro")?.unwrap_or(0),
+                    skip_outro: row.get::<&str, Option<i64>>("skip_outro")?.unwrap_or(0),
+                },
+                tags: tags.remove(&pod_id).unwrap_or_default(),
+                archived: row.get::<&str, Option<bool>>("archived")?.unwrap_or(false),
+                loaded: false,
+                counts: counts.remove(&pod_id).unwrap_or_default(),
+                episodes: LockVec::new(Vec::new()),
+            })
+        })?;
+        let mut podcasts = Vec::new();
+        for pc in podcast_iter {
+            podcasts.push(pc?);
+        }
+        podcasts.sort_unstable();
+
+        return Ok(podcasts);
+    }
+
+    /// Counts the episodes of either one podcast or all of them, keyed
+    /// by podcast id. Podcasts without episodes are left out.
+    fn get_episode_counts(
+        &self,
+        pod_id: Option<i64>,
+        include_hidden: bool,
+    ) -> Result<AHashMap<i64, EpisodeCounts>> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached(
+            "SELECT episodes.podcast_id, COUNT(*), SUM(episodes.played = 0),
+                    COUNT(files.episode_id)
+                FROM episodes
+                LEFT JOIN files ON episodes.id = files.episode_id
+                WHERE (?1 IS NULL OR episodes.podcast_id = ?1)
+                AND (?2 OR episodes.hidden = 0)
+                GROUP BY episodes.podcast_id;",
+        )?;
+        let count_iter = stmt.query_map(params![pod_id, include_hidden], |row| {
+            Ok((
+                row.get::<usize, i64>(0)?,
+                EpisodeCounts {
+                    total: row.get::<usize, i64>(1)? as usize,
+                    unplayed: row.get::<usize, i64>(2)? as usize,
+                    downloaded: row.get::<usize, i64>(3)? as usize,
+                },
+            ))
+        })?;
+        let mut counts = AHashMap::new();
+        for count in count_iter {
+            let (pod_id, count) = count?;
+            counts.insert(pod_id, count);
+        }
+        return Ok(counts);
+    }
+
+    /// Returns the ids of the podcasts that have at least one episode
+    /// that passes the played, downloaded, starred, and hidden
+    /// `filters`. This stands in for filtering the episodes themselves
+    /// when they have not been loaded.
+    pub fn get_filtered_podcast_ids(&self, filters: &Filters) -> Result<AHashSet<i64>> {
+        let mut conditions = Vec::new();
+        match filters.played {
+            FilterStatus::All => (),
+            FilterStatus::PositiveCases => conditions.push("episodes.played = 1"),
+            FilterStatus::NegativeCases => conditions.push("episodes.played = 0"),
+        }
+        match filters.downloaded {
+            FilterStatus::All => (),
+            FilterStatus::PositiveCases => conditions.push("files.path IS NOT NULL"),
+            FilterStatus::NegativeCases => conditions.push("files.path IS NULL"),
+        }
+        match filters.starred {
+            FilterStatus::All => (),
+            FilterStatus::PositiveCases => conditions.push("IFNULL(episodes.starred, 0) = 1"),
+            FilterStatus::NegativeCases => conditions.push("IFNULL(episodes.starred, 0) = 0"),
+        }
+        match filters.hidden {
+            FilterStatus::All => (),
+            FilterStatus::PositiveCases => conditions.push("episodes.hidden = 1"),
+            FilterStatus::NegativeCases => conditions.push("episodes.hidden = 0"),
+        }
+        let mut sql = "SELECT DISTINCT episodes.podcast_id FROM episodes
+            LEFT JOIN files ON episodes.id = files.episode_id"
+            .to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached(&sql)?;
+        let id_iter = stmt.query_map(params![], |row| row.get::<usize, i64>(0))?;
+        let mut ids = AHashSet::new();
+        for id in id_iter {
+            ids.insert(id?);
+        }
+        return Ok(ids);
+    }
+
+    /// Generates list of episodes for a given podcast.
+    pub fn get_episodes(&self, pod_id: i64, include_hidden: bool) -> Result<Vec<Episode>> {
+        let mut notes = self.get_notes(pod_id)?;
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = if include_hidden {
+            conn.prepare_cached(
+                "SELECT * FROM episodes
+                        LEFT JOIN files ON episodes.id = files.episode_id
+                        WHERE episodes.podcast_id = ?
+                        ORDER BY pubdate DESC;",
+            )?
+        } else {
+            conn.prepare_cached(
+                "SELECT * FROM episodes
+                        LEFT JOIN files ON episodes.id = files.episode_id
+                        WHERE episodes.podcast_id = ?
+                        AND episodes.hidden = 0
+                        ORDER BY pubdate DESC;",
+            )?
+        };
+        let episode_iter = stmt.query_map(params![pod_id], |row| episode_from_row(row, &mut notes))?;
+        let episodes = episode_iter.flatten().collect();
+        return Ok(episodes);
+    }
+
+    /// Gets the episodes of a podcast with the given ids, e.g., those
+    /// that were added or updated by syncing, optionally including
+    /// hidden episodes. Ids that are not found are skipped.
+    pub fn get_episodes_by_id(
+        &self,
+        pod_id: i64,
+        ep_ids: &[i64],
+        include_hidden: bool,
+    ) -> Result<Vec<Episode>> {
+        let mut notes = self.get_notes(pod_id)?;
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached(
+            "SELECT * FROM episodes
+                LEFT JOIN files ON episodes.id = files.episode_id
+                WHERE episodes.id = ?1 AND episodes.podcast_id = ?2
+                AND (?3 OR episodes.hidden = 0);",
+        )?;
+        let mut episodes = Vec::new();
+        for ep_id in ep_ids.iter() {
+            let mut rows = stmt.query(params![ep_id, pod_id, include_hidden])?;
+            if let Some(row) = rows.next()? {
+                episodes.push(episode_from_row(row, &mut notes)?);
+            }
+        }
+        return Ok(episodes);
+    }
+
+    /// Searches podcast titles, episode titles, and episode descriptions
+    /// for episodes matching all of the words in `query`. Words match
+    /// the start of words in the text, so "rust async" finds "Rustacean
+    /// asynchronous". Hidden episodes are left out, unless
+    /// `include_hidden` is set. Results are ordered from best to worst
+    /// match, up to `limit` of them.
+    pub fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        include_hidden: bool,
+    ) -> Result<Vec<SearchResult>> {
+        let fts_query = match fts_query(query) {
+            Some(q) => q,
+            None => return Ok(Vec::new()),
+        };
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        let mut stmt = conn.prepare_cached(
+            "SELECT episodes.id, episodes.podcast_id, episodes.title,
+                podcasts.title AS pod_title, episodes.pubdate, episodes.played,
+                episodes.hidden
+                FROM episodes_fts
+                INNER JOIN episodes ON episodes.id = episodes_fts.rowid
+                INNER JOIN podcasts ON podcasts.id = episodes.podcast_id
+                WHERE episodes_fts MATCH ?1 AND (?3 OR episodes.hidden = 0)
+                ORDER BY episodes_fts.rank
+                LIMIT ?2;",
+        )?;
+        let result_iter = stmt.query_map(params![fts_query, limit as i64, include_hidden], |row| {
+            Ok(SearchResult {
+                id: row.get("id")?,
+                pod_id: row.get("podcast_id")?,
+                title: row.get("title")?,
+                pod_title: row.get("pod_title")?,
+                pubdate: convert_date(row.get("pubdate")),
+                played: row.get("played")?,
+                hidden: row.get::<&str, Option<bool>>("hidden")?.unwrap_or(false),
+            })
+        })?;
+        let mut results = Vec::new();
+        for result in result_iter {
+            results.push(result?);
+        }
+        return Ok(results);
+    }
+
+    /// Deletes all rows in all tables
+    pub fn clear_db(&self) -> Result<()> {
+        let conn = self.conn.as_ref().expect("Error connecting to database.");
+        return clear_tables(conn);
+    }
+}
+
+/// Deletes all podcasts and episodes, and everything about them.
+fn clear_tables(conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM trash;", params![])?;
+    conn.execute("DELETE FROM episode_actions;", params![])?;
+    conn.execute("DELETE FROM episodes_fts;", params![])?;
+    conn.execute("DELETE FROM device_files;", params![])?;
+    conn.execute("DELETE FROM notes;", params![])?;
+    conn.execute("DELETE FROM tags;", params![])?;
+    conn.execute("DELETE FROM history;", params![])?;
+    conn.execute("DELETE FROM queue;", params![])?;
+    conn.execute("DELETE FROM files;", params![])?;
+    conn.execute("DELETE FROM episodes;", params![])?;
+    conn.execute("DELETE FROM podcasts;", params![])?;
+    return Ok(());
+}
+
+/// Schema changes, in the order they were introduced. The database's
+/// `user_version` holds how many of these have been applied, so new
+/// changes must only ever be added to the end. Databases from before
+/// this list existed may already have some of the changes, so each
+/// migration needs to be safe to run again: use `CREATE TABLE IF NOT
+/// EXISTS` and `add_column()`.
+const MIGRATIONS: [fn(&Transaction) -> Result<()>; 13] = [
+    migrate_initial,
+    migrate_device_files,
+    migrate_position_and_queue,
+    migrate_mime_type,
+    migrate_podcast_settings,
+    migrate_history,
+    migrate_search,
+    migrate_tags,
+    migrate_starred,
+    migrate_notes,
+    migrate_archived,
+    migrate_trash,
+    migrate_gpodder,
+];
+
+/// Runs all migrations after `from`, and updates the schema version to
+/// match, in a single transaction. If any of them fails, nothing is
+/// changed.
+fn migrate(conn: &mut Connection, from: usize) -> Result<()> {
+    let tx = conn.transaction()?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(from) {
+        migration(&tx).with_context(|| format!("Database migration {} failed", i + 1))?;
+    }
+    tx.pragma_update(None, "user_version", MIGRATIONS.len() as i64)?;
+    tx.commit()?;
+    return Ok(());
+}
+
+/// Copies the database file before it is migrated, so the user can go
+/// back to it if something goes wrong. Returns the path of the copy.
+fn backup_before_migration(db_path: &Path, version: usize) -> Result<PathBuf> {
+    let backup = db_path.with_file_name(format!("data.db.v{version}.bak"));
+    std::fs::copy(db_path, &backup).with_context(|| {
+        format!(
+            "Could not back up the database to {} before updating it",
+            backup.to_string_lossy()
+        )
+    })?;
+    return Ok(backup);
+}
+
+/// Returns the path for a new backup of the database at `db_path`,
+/// in the `backups` directory next to it, named with `prefix` and the
+/// current time so that the names sort from oldest to newest.
+fn timestamped_backup(db_path: &Path, prefix: &str) -> PathBuf {
+    let name = format!("{prefix}-{}.db", Utc::now().format("%Y%m%d-%H%M%S-%6f"));
+    return db_path.with_file_name("backups").join(name);
+}
+
+/// Copies the database open in `conn` to `dest`, creating any parent
+/// directories as needed.
+fn backup_to(conn: &Connection, dest: &Path) -> Result<()> {
+    if dest.exists() {
+        return Err(anyhow!("{} already exists", dest.to_string_lossy()));
+    }
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    conn.backup(DatabaseName::Main, dest, None)
+        .with_context(|| format!("Could not back up the database to {}", dest.to_string_lossy()))?;
+    return Ok(());
+}
+
+/// Takes an automatic backup of the database open in `conn`, then
+/// deletes the oldest automatic backups so that only `keep` are left.
+/// Backups taken by the user are never deleted.
+fn auto_backup(conn: &Connection, db_path: &Path, keep: usize) -> Result<Option<PathBuf>> {
+    if keep == 0 {
+        return Ok(None);
+    }
+    let dest = timestamped_backup(db_path, "auto");
+    backup_to(conn, &dest)?;
+
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(db_path.with_file_name("backups"))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+            name.starts_with("auto-") && name.ends_with(".db")
+        })
+        .collect();
+    backups.sort();
+    let n_old = backups.len().saturating_sub(keep);
+    for old in backups.iter().take(n_old) {
+        let _ = std::fs::remove_file(old);
+    }
+    return Ok(Some(dest));
+}
+
+/// Checks that a file is a shellcaster database that this version can
+/// read, and that it is not damaged, before restoring from it.
+fn check_backup(backup: &Path) -> Result<()> {
+    let name = backup.to_string_lossy();
+    if !backup.is_file() {
+        return Err(anyhow!("No backup found at {name}"));
+    }
+    let conn = Connection::open_with_flags(backup, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Could not open {name}"))?;
+    let check: String = conn
+        .query_row("PRAGMA quick_check;", params![], |row| row.get(0))
+        .with_context(|| format!("{name} is not a shellcaster database"))?;
+    if check != "ok" {
+        return Err(anyhow!("{name} is damaged: {check}"));
+    }
+    let has_podcasts: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'podcasts';",
+        params![],
+        |row| row.get(0),
+    )?;
+    if has_podcasts == 0 {
+        return Err(anyhow!("{name} is not a shellcaster database"));
+    }
+    let version: usize = conn.query_row("PRAGMA user_version;", params![], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "{name} was created by a newer version of shellcaster (schema version {version}, but this version only understands up to {}). Please upgrade shellcaster.",
+            MIGRATIONS.len()
+        ));
+    }
+    return Ok(());
+}
+
+/// Checks whether a column exists in the given database table.
+fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table});"))?;
+    let mut rows = stmt.query(params![])?;
+    while let Some(row) = rows.next()? {
+        let name: String = row.get("name")?;
+        if name == column {
+            return Ok(true);
+        }
+    }
+    return Ok(false);
+}
+
+/// Adds a column to a database table, unless it is already there.
+fn add_column(conn: &Connection, table: &str, column: &str, col_type: &str) -> Result<()> {
+    if !column_exists(conn, table, column)? {
+        conn.execute(
+            &format!("ALTER TABLE {table} ADD COLUMN {column} {col_type};"),
+            params![],
+        )?;
+    }
+    return Ok(());
+}
+
+/// Migration 1: the original tables for podcasts, episodes, and
+/// downloaded files. Episode guids were added in v1.2.1.
+fn migrate_initial(tx: &Transaction) -> Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS podcasts (
+            id INTEGER PRIMARY KEY NOT NULL,
+            title TEXT NOT NULL,
+            url TEXT NOT NULL UNIQUE,
+            description TEXT,
+            author TEXT,
+            explicit INTEGER,
+            last_checked INTEGER
+        );",
+        params![],
+    )
+    .with_context(|| "Could not create podcasts database table")?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS episodes (
+            id INTEGER PRIMARY KEY NOT NULL,
+            podcast_id INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            url TEXT NOT NULL,
+            guid TEXT,
+            description TEXT,
+            pubdate INTEGER,
+            duration INTEGER,
+            played INTEGER,
+            hidden INTEGER,
+            FOREIGN KEY(podcast_id) REFERENCES podcasts(id) ON DELETE CASCADE
+        );",
+        params![],
+    )
+    .with_context(|| "Could not create episodes database table")?;
+    add_column(tx, "episodes", "guid", "TEXT")?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS files (
+            id INTEGER PRIMARY KEY NOT NULL,
+            episode_id INTEGER NOT NULL,
+            path TEXT NOT NULL UNIQUE,
+            FOREIGN KEY (episode_id) REFERENCES episodes(id) ON DELETE CASCADE
+        );",
+        params![],
+    )
+    .with_context(|| "Could not create files database table")?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS version (
+            id INTEGER PRIMARY KEY NOT NULL,
+            version TEXT NOT NULL
+        );",
+        params![],
+    )
+    .with_context(|| "Could not create version database table")?;
+    return Ok(());
+}
+
+/// Migration 2: table tracking episodes copied to external devices.
+fn migrate_device_files(tx: &Transaction) -> Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS device_files (
+            id INTEGER PRIMARY KEY NOT NULL,
+            device TEXT NOT NULL,
+            episode_id INTEGER NOT NULL,
+            path TEXT NOT NULL,
+            UNIQUE (device, episode_id),
+            FOREIGN KEY (episode_id) REFERENCES episodes(id) ON DELETE CASCADE
+        );",
+        params![],
+    )
+    .with_context(|| "Could not create device_files database table")?;
+    return Ok(());
+}
+
+/// Migration 3: playback position of episodes, and the play queue, in
+/// the order episodes will be played.
+fn migrate_position_and_queue(tx: &Transaction) -> Result<()> {
+    add_column(tx, "episodes", "position", "INTEGER")?;
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS queue (
+            id INTEGER PRIMARY KEY NOT NULL,
+            episode_id INTEGER NOT NULL UNIQUE,
+            sort_order INTEGER NOT NULL,
+            FOREIGN KEY (episode_id) REFERENCES episodes(id) ON DELETE CASCADE
+        );",
+        params![],
+    )
+    .with_context(|| "Could not create queue database table")?;
+    return Ok(());
+}
+
+/// Migration 4: the type of episode file.
+fn migrate_mime_type(tx: &Transaction) -> Result<()> {
+    return add_column(tx, "episodes", "mime_type", "TEXT");
+}
+
+/// Migration 5: per-podcast playback settings.
+fn migrate_podcast_settings(tx: &Transaction) -> Result<()> {
+    add_column(tx, "podcasts", "speed", "REAL")?;
+    add_column(tx, "podcasts", "skip_intro", "INTEGER")?;
+    add_column(tx, "podcasts", "skip_outro", "INTEGER")?;
+    return Ok(());
+}
+
+/// Migration 6: when episodes were played, and a record of each time
+/// an episode was played; `listened` is how much of the episode was
+/// heard, in seconds.
+fn migrate_history(tx: &Transaction) -> Result<()> {
+    add_column(tx, "episodes", "played_at", "INTEGER")?;
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS history (
+            id INTEGER PRIMARY KEY NOT NULL,
+            episode_id INTEGER NOT NULL,
+            podcast_id INTEGER NOT NULL,
+            started_at INTEGER NOT NULL,
+            ended_at INTEGER NOT NULL,
+            listened INTEGER NOT NULL,
+            finished INTEGER NOT NULL,
+            FOREIGN KEY (episode_id) REFERENCES episodes(id) ON DELETE CASCADE,
+            FOREIGN KEY (podcast_id) REFERENCES podcasts(id) ON DELETE CASCADE
+        );",
+        params![],
+    )
+    .with_context(|| "Could not create history database table")?;
+    return Ok(());
+}
+
+/// Migration 7: full-text search index over podcast titles, episode
+/// titles, and episode descriptions. The rowid of each entry is the id
+/// of the episode.
+fn migrate_search(tx: &Transaction) -> Result<()> {
+    tx.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS episodes_fts
+            USING fts5(podcast_title, title, description);",
+        params![],
+    )
+    .with_context(|| "Could not create search index (is SQLite built with FTS5?)")?;
+    tx.execute("DELETE FROM episodes_fts;", params![])?;
+    tx.execute(
+        "INSERT INTO episodes_fts (rowid, podcast_title, title, description)
+            SELECT episodes.id, podcasts.title, episodes.title, episodes.description
+            FROM episodes INNER JOIN podcasts ON podcasts.id = episodes.podcast_id;",
+        params![],
+    )
+    .with_context(|| "Could not fill search index")?;
+    return Ok(());
+}
+
+/// Migration 8: tags the user has given to podcasts.
+fn migrate_tags(tx: &Transaction) -> Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY NOT NULL,
+            podcast_id INTEGER NOT NULL,
+            tag TEXT NOT NULL,
+            UNIQUE (podcast_id, tag),
+            FOREIGN KEY (podcast_id) REFERENCES podcasts(id) ON DELETE CASCADE
+        );",
+        params![],
+    )
+    .with_context(|| "Could not create tags database table")?;
+    return Ok(());
+}
+
+/// Migration 9: episodes the user has starred.
+fn migrate_starred(tx: &Transaction) -> Result<()> {
+    return add_column(tx, "episodes", "starred", "INTEGER");
+}
+
+/// Migration 10: notes the user has written about episodes.
+fn migrate_notes(tx: &Transaction) -> Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS notes (
+            id INTEGER PRIMARY KEY NOT NULL,
+            episode_id INTEGER NOT NULL,
+            position INTEGER,
+            text TEXT NOT NULL,
+            created INTEGER NOT NULL,
+            FOREIGN KEY (episode_id) REFERENCES episodes(id) ON DELETE CASCADE
+        );",
+        params![],
+    )
+    .with_context(|| "Could not create notes database table")?;
+    return Ok(());
+}
+
+/// Migration 11: podcasts the user has archived.
+fn migrate_archived(tx: &Transaction) -> Result<()> {
+    return add_column(tx, "podcasts", "archived", "INTEGER");
+}
+
+/// Migration 12: podcasts the user has removed, kept so that removing
+/// them can be undone. `data` holds all of the podcast's rows, as JSON.
+fn migrate_trash(tx: &Transaction) -> Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS trash (
+            id INTEGER PRIMARY KEY NOT NULL,
+            podcast_id INTEGER NOT NULL,
+            data TEXT NOT NULL,
+            removed INTEGER NOT NULL
+        );",
+        params![],
+    )
+    .with_context(|| "Could not create trash database table")?;
+    return Ok(());
+}
+
+/// Migration 13: what the user has done with episodes, waiting to be
+/// uploaded to a gpodder.net-compatible server, and how far each
+/// account on such a server has been synced. Actions keep the URLs
+/// rather than the episode, so that they outlive removed podcasts.
+fn migrate_gpodder(tx: &Transaction) -> Result<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS episode_actions (
+            id INTEGER PRIMARY KEY NOT NULL,
+            podcast_url TEXT NOT NULL,
+            episode_url TEXT NOT NULL,
+            guid TEXT,
+            action TEXT NOT NULL,
+            started INTEGER,
+            position INTEGER,
+            total INTEGER,
+            timestamp INTEGER NOT NULL
+        );",
+        params![],
+    )
+    .with_context(|| "Could not create episode actions database table")?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS gpodder_state (
+            account TEXT PRIMARY KEY NOT NULL,
+            subscriptions_since INTEGER NOT NULL,
+            actions_since INTEGER NOT NULL,
+            subscriptions TEXT NOT NULL
+        );",
+        params![],
+    )
+    .with_context(|| "Could not create gpodder state database table")?;
+    return Ok(());
+}
+
+/// Deletes a podcast from the database.
+fn delete_podcast(conn: &Connection, podcast_id: i64) -> Result<()> {
+    // Note: Because of the foreign key constraints on `episodes`
+    // and `files` tables, all associated episodes for this podcast
+    // will also be deleted, and all associated file entries for
+    // those episodes as well. The search index has no foreign keys,
+    // so its entries are removed by hand.
+    let mut stmt = conn.prepare_cached(
+        "DELETE FROM episodes_fts WHERE rowid IN
+            (SELECT id FROM episodes WHERE podcast_id = ?);",
+    )?;
+    stmt.execute(params![podcast_id])?;
+    let mut stmt = conn.prepare_cached("DELETE FROM podcasts WHERE id = ?;")?;
+    stmt.execute(params![podcast_id])?;
+    return Ok(());
+}
+
+/// Reads the state of a podcast's episodes as `export_state()` would,
+/// paired with their ids, for `import_state()` to compare against.
+fn episode_states(conn: &Connection, pod_id: i64) -> Result<Vec<(i64, EpisodeState)>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT episodes.*, files.path FROM episodes
+            LEFT JOIN files ON episodes.id = files.episode_id
+            WHERE episodes.podcast_id = ?;",
+    )?;
+    let mut rows = stmt.query(params![pod_id])?;
+    let mut episodes = Vec::new();
+    while let Some(row) = rows.next()? {
+        episodes.push((row.get::<&str, i64>("id")?, EpisodeState {
+            title: row.get("title")?,
+            url: row.get("url")?,
+            guid: row.get::<&str, Option<String>>("guid")?.unwrap_or_default(),
+            description: row.get::<&str, Option<String>>("description")?.unwrap_or_default(),
+            pubdate: row.get("pubdate")?,
+            duration: row.get("duration")?,
+            mime_type: row.get("mime_type")?,
+            played: row.get::<&str, Option<bool>>("played")?.unwrap_or(false),
+            played_at: row.get("played_at")?,
+            position: row.get::<&str, Option<i64>>("position")?.unwrap_or(0),
+            hidden: row.get::<&str, Option<bool>>("hidden")?.unwrap_or(false),
+            starred: row.get::<&str, Option<bool>>("starred")?.unwrap_or(false),
+            file: row.get::<&str, Option<String>>("path")?.map(PathBuf::from),
+            notes: Vec::new(),
+            history: Vec::new(),
+        }));
+    }
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT history.* FROM history
+            INNER JOIN episodes ON episodes.id = history.episode_id
+            WHERE episodes.podcast_id = ?;",
+    )?;
+    let mut rows = stmt.query(params![pod_id])?;
+    while let Some(row) = rows.next()? {
+        let ep_id: i64 = row.get("episode_id")?;
+        if let Some((_, ep)) = episodes.iter_mut().find(|(id, _)| *id == ep_id) {
+            ep.history.push(PlayState {
+                started_at: row.get("started_at")?,
+                ended_at: row.get("ended_at")?,
+                listened: row.get("listened")?,
+                finished: row.get("finished")?,
+            });
+        }
+    }
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT notes.* FROM notes
+            INNER JOIN episodes ON episodes.id = notes.episode_id
+            WHERE episodes.podcast_id = ?;",
+    )?;
+    let mut rows = stmt.query(params![pod_id])?;
+    while let Some(row) = rows.next()? {
+        let ep_id: i64 = row.get("episode_id")?;
+        if let Some((_, ep)) = episodes.iter_mut().find(|(id, _)| *id == ep_id) {
+            ep.notes.push(NoteState {
+                position: row.get("position")?,
+                text: row.get("text")?,
+                created: row.get("created")?,
+            });
+        }
+    }
+    return Ok(episodes);
+}
+
+
+/// Gives a podcast tags, on top of any it already has.
+fn insert_tags(conn: &Connection, podcast_id: i64, tags: &[String]) -> Result<()> {
+    let mut stmt =
+        conn.prepare_cached("INSERT OR IGNORE INTO tags (podcast_id, tag) VALUES (?, ?);")?;
+    for tag in tags.iter() {
+        stmt.execute(params![podcast_id, tag])?;
+    }
+    return Ok(());
+}
+
+/// Sets the played status, position, and hidden and starred flags of an
+/// episode to those of an imported episode.
+fn set_episode_state(conn: &Connection, episode_id: i64, state: &EpisodeState) -> Result<()> {
+    let mut stmt = conn.prepare_cached(
+        "UPDATE episodes SET played = ?, played_at = ?, position = ?, hidden = ?, starred = ?
+            WHERE id = ?;",
+    )?;
+    stmt.execute(params![
+        state.played,
+        state.played_at,
+        state.position,
+        state.hidden,
+        state.starred,
+        episode_id
+    ])?;
+    return Ok(());
+}
+
+/// Adds the notes and listening history of an imported episode, and
+/// its downloaded file if that exists on this computer.
+fn add_episode_extras(
+    conn: &Connection,
+    podcast_id: i64,
+    episode_id: i64,
+    state: &EpisodeState,
+) -> Result<()> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO notes (episode_id, position, text, created) VALUES (?, ?, ?, ?);",
+    )?;
+    for note in state.notes.iter() {
+        stmt.execute(params![episode_id, note.position, note.text, note.created])?;
+    }
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO history (episode_id, podcast_id, started_at, ended_at, listened, finished)
+            VALUES (?, ?, ?, ?, ?, ?);",
+    )?;
+    for play in state.history.iter() {
+        stmt.execute(params![
+            episode_id,
+            podcast_id,
+            play.started_at,
+            play.ended_at,
+            play.listened,
+            play.finished
+        ])?;
+    }
+    if let Some(path) = state.file.as_ref().filter(|path| path.exists()) {
+        let mut stmt = conn
+            .prepare_cached("INSERT OR IGNORE INTO files (episode_id, path) VALUES (?, ?);")?;
+        stmt.execute(params![episode_id, path.to_string_lossy()])?;
+    }
+    return Ok(());
+}
+
+/// Reads the rows of `table` that match `condition` (with `id` as its
+/// parameter) as JSON objects, keyed by column name.
+fn dump_rows(conn: &Connection, table: &str, condition: &str, id: i64) -> Result<Vec<JsonValue>> {
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {table} WHERE {condition};"))?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|col| col.to_string()).collect();
+    let mut rows = stmt.query(params![id])?;
+    let mut dumped = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut object = serde_json::Map::new();
+        for (i, col) in columns.iter().enumerate() {
+            let value = match row.get_ref(i)? {
+                ValueRef::Null => JsonValue::Null,
+                ValueRef::Integer(n) => JsonValue::from(n),
+                ValueRef::Real(x) => JsonValue::from(x),
+                ValueRef::Text(text) => JsonValue::from(String::from_utf8_lossy(text)),
+                ValueRef::Blob(_) => return Err(anyhow!("Unexpected binary data in {table}")),
+            };
+            object.insert(col.clone(), value);
+        }
+        dumped.push(JsonValue::Object(object));
+    }
+    return Ok(dumped);
+}
+
+/// Inserts a row read by `dump_rows()` back into `table`.
+fn insert_row(conn: &Connection, table: &str, row: &JsonValue) -> Result<()> {
+    let damaged = || anyhow!("Damaged trash entry for {table}");
+    let row = row.as_object().ok_or_else(damaged)?;
+    let columns: Vec<String> = row.keys().map(|col| format!("\"{col}\"")).collect();
+    let placeholders = vec!["?"; row.len()].join(", ");
+    let mut values = Vec::new();
+    for value in row.values() {
+        values.push(match value {
+            JsonValue::Null => SqlValue::Null,
+            JsonValue::Number(n) => match n.as_i64() {
+                Some(n) => SqlValue::Integer(n),
+                None => SqlValue::Real(n.as_f64().ok_or_else(damaged)?),
+            },
+            JsonValue::String(text) => SqlValue::Text(text.clone()),
+            _ => return Err(damaged()),
+        });
+    }
+    conn.execute(
+        &format!("INSERT INTO {table} ({}) VALUES ({placeholders});", columns.join(", ")),
+        params_from_iter(values),
+    )
+    .with_context(|| format!("Could not put back rows in {table}"))?;
+    return Ok(());
+}
+
+/// Adds an episode to the search index, replacing any entry it already
+/// has.
+fn index_episode(
+    conn: &Connection,
+    episode_id: i64,
+    podcast_title: &str,
+    title: &str,
+    description: &str,
+) -> Result<()> {
+    let mut stmt = conn.prepare_cached("DELETE FROM episodes_fts WHERE rowid = ?;")?;
+    stmt.execute(params![episode_id])?;
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO episodes_fts (rowid, podcast_title, title, description)
+            VALUES (?, ?, ?, ?);",
+    )?;
+    stmt.execute(params![episode_id, podcast_title, title, description])?;
+    return Ok(());
+}
+
+/// Turns the user's search text into an FTS5 query that matches
+/// entries containing every word, each as a prefix. Words are quoted so
+/// that FTS5 operators and punctuation are taken literally. Returns
+/// None if there is nothing to search for.
+fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        return None;
+    }
+    return Some(terms.join(" "));
+}
+
+/// Builds an episode from a row of the episodes table joined with the
+/// files table, taking its notes out of `notes`.
+fn episode_from_row(
+    row: &Row,
+    notes: &mut AHashMap<i64, Vec<Note>>,
+) -> Result<Episode, rusqlite::Error> {
+    let path = match row.get::<&str, String>("path") {
+        Ok(val) => Some(PathBuf::from(val)),
+        Err(_) => None,
+    };
+    let id = row.get("id")?;
+    return Ok(Episode {
+        id: id,
+        pod_id: row.get("podcast_id")?,
+        title: row.get("title")?,
+        url: row.get("url")?,
+        guid: row
+            .get::<&str, Option<String>>("guid")?
+            .unwrap_or_else(|| "".to_string()),
+        description: row.get("description")?,
+        pubdate: convert_date(row.get("pubdate")),
+        duration: row.get("duration")?,
+        path: path,
+        played: row.get("played")?,
+        position: row.get::<&str, Option<i64>>("position")?.unwrap_or(0),
+        mime_type: row.get("mime_type")?,
+        starred: row.get::<&str, Option<bool>>("starred")?.unwrap_or(false),
+        hidden: row.get::<&str, Option<bool>>("hidden")?.unwrap_or(false),
+        notes: notes.remove(&id).unwrap_or_default(),
+    });
+}
+
+/// Helper function converting an (optional) Unix timestamp to a
+/// DateTime<Utc> object
+fn convert_date(result: Result<i64, rusqlite::Error>) -> Option<DateTime<Utc>> {
+    return match result {
         Ok(timestamp) => {
             NaiveDateTime::from_timestamp_opt(timestamp, 0).map(|ndt| DateTime::from_utc(ndt, Utc))
         }
         Err(_) => None,
     };
 }
+
+
+// TESTS -----------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{make_feed, temp_dir};
+
+    fn user_version(dir: &Path) -> i64 {
+        let conn = Connection::open(dir.join("data.db")).unwrap();
+        return conn
+            .query_row("PRAGMA user_version;", params![], |row| row.get(0))
+            .unwrap();
+    }
+
+    #[test]
+    fn new_database() {
+        let dir = temp_dir("db-new");
+        let db = Database::connect(&dir).unwrap();
+        assert!(db.get_podcasts(false).unwrap().is_empty());
+        assert_eq!(user_version(&dir), MIGRATIONS.len() as i64);
+        // nothing to back up
+        assert!(!dir.join("data.db.v0.bak").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn migrate_legacy_database() {
+        let dir = temp_dir("db-legacy");
+        {
+            // schema from before episode guids were added
+            let conn = Connection::open(dir.join("data.db")).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE podcasts (
+                    id INTEGER PRIMARY KEY NOT NULL,
+                    title TEXT NOT NULL,
+                    url TEXT NOT NULL UNIQUE,
+                    description TEXT,
+                    author TEXT,
+                    explicit INTEGER,
+                    last_checked INTEGER
+                );
+                CREATE TABLE episodes (
+                    id INTEGER PRIMARY KEY NOT NULL,
+                    podcast_id INTEGER NOT NULL,
+                    title TEXT NOT NULL,
+                    url TEXT NOT NULL,
+                    description TEXT,
+                    pubdate INTEGER,
+                    duration INTEGER,
+                    played INTEGER,
+                    hidden INTEGER
+                );
+                CREATE TABLE version (id INTEGER PRIMARY KEY NOT NULL, version TEXT NOT NULL);
+                INSERT INTO version VALUES (1, '1.2.0');
+                INSERT INTO podcasts (id, title, url, last_checked)
+                    VALUES (1, 'Old podcast', 'http://example.com/feed', 0);
+                INSERT INTO episodes (id, podcast_id, title, url, description, played, hidden)
+                    VALUES (1, 1, 'Old episode', 'http://example.com/1.mp3', '', 1, 0);",
+            )
+            .unwrap();
+        }
+
+        let db = Database::connect(&dir).unwrap();
+        assert_eq!(user_version(&dir), MIGRATIONS.len() as i64);
+        assert!(dir.join("data.db.v0.bak").exists());
+
+        let podcasts = db.get_podcasts(false).unwrap();
+        assert_eq!(podcasts.len(), 1);
+        assert_eq!(podcasts[0].title, "Old podcast");
+        assert_eq!(podcasts[0].settings, PodcastSettings::default());
+        let episode = podcasts[0].episodes.clone_episode(1).unwrap();
+        assert!(episode.played);
+        assert!(!episode.starred);
+        assert_eq!(episode.position, 0);
+        db.set_starred(1, true).unwrap();
+        let podcasts = db.get_podcasts(false).unwrap();
+        assert!(podcasts[0].episodes.clone_episode(1).unwrap().starred);
+        assert!(db.get_history().unwrap().is_empty());
+        assert!(db.get_queue().unwrap().is_empty());
+
+        // connecting again does not need another migration
+        std::fs::remove_file(dir.join("data.db.v0.bak")).unwrap();
+        drop(db);
+        let _ = Database::connect(&dir).unwrap();
+        assert!(!dir.join("data.db.v0.bak").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn refuse_newer_database() {
+        let dir = temp_dir("db-newer");
+        {
+            let conn = Connection::open(dir.join("data.db")).unwrap();
+            conn.pragma_update(None, "user_version", MIGRATIONS.len() as i64 + 1)
+                .unwrap();
+        }
+        assert!(Database::connect(&dir).is_err());
+        assert_eq!(user_version(&dir), MIGRATIONS.len() as i64 + 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn search_titles(db: &Database, query: &str) -> Vec<String> {
+        let mut titles: Vec<String> = db
+            .search(query, 100, false)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.title)
+            .collect();
+        titles.sort();
+        return titles;
+    }
+
+    #[test]
+    fn search_episodes() {
+        let dir = temp_dir("db-search");
+        let db = Database::connect(&dir).unwrap();
+        db.insert_podcast(make_feed("Systems Talk", &[
+            ("Async", "All about Rust async and futures."),
+            ("Kernels", "Scheduling in the \"Linux\" kernel."),
+        ]))
+        .unwrap();
+        db.insert_podcast(make_feed("Cooking Hour", &[("Bread", "Sourdough basics.")]))
+            .unwrap();
+
+        assert_eq!(search_titles(&db, "rust asy"), vec!["Async"]);
+        assert_eq!(search_titles(&db, "systems"), vec!["Async", "Kernels"]);
+        assert_eq!(search_titles(&db, "\"linux\" OR"), Vec::<String>::new());
+        assert_eq!(search_titles(&db, "linux"), vec!["Kernels"]);
+        assert!(search_titles(&db, "   ").is_empty());
+
+        // syncing keeps the index up to date
+        let pod_id = db.get_podcasts(false).unwrap()[0].id;
+        let mut feed = make_feed("Cooking Hour", &[
+            ("Bread", "Sourdough and rye."),
+            ("Soup", "Stock from scratch."),
+        ]);
+        feed.title = "Kitchen Hour".to_string();
+        let result = db.update_podcast(pod_id, feed).unwrap();
+        assert_eq!(result.added.len(), 1);
+        assert_eq!(result.updated.len(), 1);
+        assert_eq!(search_titles(&db, "rye"), vec!["Bread"]);
+        assert!(search_titles(&db, "cooking").is_empty());
+        assert_eq!(search_titles(&db, "kitchen"), vec!["Bread", "Soup"]);
+
+        // hidden episodes are left out
+        let soup_id = db.search("soup", 100, false).unwrap()[0].id;
+        db.hide_episode(soup_id, true).unwrap();
+        assert!(search_titles(&db, "soup").is_empty());
+        let results = db.search("soup", 100, true).unwrap();
+        assert_eq!(results[0].id, soup_id);
+        assert!(results[0].hidden);
+        assert_eq!(db.get_episodes(pod_id, false).unwrap().len(), 1);
+        assert!(db.get_episodes(pod_id, true).unwrap().iter().any(|ep| ep.hidden));
+
+        db.trash_podcast(pod_id).unwrap();
+        assert!(search_titles(&db, "bread").is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn podcast_tags() {
+        let dir = temp_dir("db-tags");
+        let db = Database::connect(&dir).unwrap();
+        db.insert_podcast(make_feed("Daily News", &[])).unwrap();
+        db.insert_podcast(make_feed("Space Show", &[])).unwrap();
+        let podcasts = db.get_podcasts(false).unwrap();
+        let (news, space) = (podcasts[0].id, podcasts[1].id);
+        assert!(podcasts.iter().all(|pod| pod.tags.is_empty()));
+
+        db.set_tags(news, &["news".to_string(), "daily".to_string()]).unwrap();
+        db.set_tags(space, &["science".to_string()]).unwrap();
+        let podcasts = db.get_podcasts(false).unwrap();
+        assert_eq!(podcasts[0].tags, vec!["daily".to_string(), "news".to_string()]);
+        assert!(podcasts[1].has_tag("science"));
+
+        // tags are replaced, not added to
+        db.set_tags(news, &["news".to_string()]).unwrap();
+        assert_eq!(db.get_tags().unwrap().get(&news), Some(&vec!["news".to_string()]));
+
+        db.trash_podcast(space).unwrap();
+        assert!(!db.get_tags().unwrap().contains_key(&space));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn archived_podcasts() {
+        let dir = temp_dir("db-archived");
+        let db = Database::connect(&dir).unwrap();
+        db.insert_podcast(make_feed("Old Show", &[("Pilot", "")])).unwrap();
+        let pod = db.get_podcasts(false).unwrap().remove(0);
+        assert!(!pod.archived);
+        let ep_id = db.get_episodes(pod.id, false).unwrap()[0].id;
+        db.set_played_status(ep_id, true).unwrap();
+
+        db.set_archived(pod.id, true).unwrap();
+        assert!(db.get_podcasts(false).unwrap()[0].archived);
+
+        // unarchiving keeps the podcast's history
+        db.set_archived(pod.id, false).unwrap();
+        assert!(!db.get_podcasts(false).unwrap()[0].archived);
+        let episodes = db.get_episodes(pod.id, false).unwrap();
+        assert_eq!(episodes.len(), 1);
+        assert!(episodes[0].played);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn podcast_summaries() {
+        let dir = temp_dir("db-summaries");
+        let db = Database::connect(&dir).unwrap();
+        let result = db
+            .insert_podcast(make_feed("Big Show", &[("One", ""), ("Two", ""), ("Three", "")]))
+            .unwrap();
+        let pod_id = result.pod_id;
+        db.insert_podcast(make_feed("Empty Show", &[])).unwrap();
+        // episodes are added oldest first
+        let ep_ids: Vec<i64> = result.added.iter().rev().map(|ep| ep.id).collect();
+        db.set_played_status(ep_ids[0], true).unwrap();
+        db.insert_file(ep_ids[1], Path::new("/tmp/two.mp3")).unwrap();
+        db.hide_episode(ep_ids[2], true).unwrap();
+
+        // episodes are counted rather than loaded
+        let podcasts = db.get_podcast_summaries(false).unwrap();
+        assert_eq!(podcasts.len(), 2);
+        assert_eq!(podcasts[0].id, pod_id);
+        assert!(!podcasts[0].loaded);
+        assert!(podcasts[0].episodes.is_empty());
+        assert_eq!(podcasts[0].counts, EpisodeCounts {
+            total: 2,
+            unplayed: 1,
+            downloaded: 1,
+        });
+        assert_eq!(podcasts[1].counts, EpisodeCounts::default());
+        assert_eq!(db.get_podcast_summary(pod_id, true).unwrap().counts.total, 3);
+        assert!(db.get_podcasts(false).unwrap()[0].loaded);
+
+        // filters are applied to episodes that have not been loaded
+        let mut filters = Filters::default();
+        assert_eq!(db.get_filtered_podcast_ids(&filters).unwrap().len(), 1);
+        filters.played = FilterStatus::PositiveCases;
+        filters.downloaded = FilterStatus::PositiveCases;
+        assert!(db.get_filtered_podcast_ids(&filters).unwrap().is_empty());
+        filters.played = FilterStatus::NegativeCases;
+        assert!(db.get_filtered_podcast_ids(&filters).unwrap().contains(&pod_id));
+        filters = Filters::default();
+        filters.hidden = FilterStatus::PositiveCases;
+        assert!(db.get_filtered_podcast_ids(&filters).unwrap().contains(&pod_id));
+
+        // syncing reports what changed, which can be read on its own
+        let feed = make_feed("Big Show", &[("One", "New notes"), ("Four", "")]);
+        let result = db.update_podcast(pod_id, feed).unwrap();
+        assert_eq!(result.pod_id, pod_id);
+        assert_eq!(result.updated, vec![ep_ids[0]]);
+        let mut changed = result.updated.clone();
+        changed.push(result.added[0].id);
+        changed.push(ep_ids[2]);
+        let episodes = db.get_episodes_by_id(pod_id, &changed, false).unwrap();
+        let titles: Vec<&str> = episodes.iter().map(|ep| ep.title.as_str()).collect();
+        assert_eq!(titles, vec!["One", "Four"]);
+        assert_eq!(episodes[0].description, "New notes");
+        assert!(episodes[0].played);
+        assert_eq!(db.get_episodes_by_id(pod_id, &changed, true).unwrap().len(), 3);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn concurrent_access() {
+        let dir = temp_dir("db-concurrent");
+        let ui_db = Database::connect(&dir).unwrap();
+        let mode: String = Connection::open(dir.join("data.db"))
+            .unwrap()
+            .query_row("PRAGMA journal_mode;", params![], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        // only one process at a time gets the lock
+        let lock = InstanceLock::acquire(&dir).unwrap();
+        let err = InstanceLock::acquire(&dir).unwrap_err();
+        assert!(err.to_string().contains("already running"));
+        drop(lock);
+        let _lock = InstanceLock::acquire(&dir).unwrap();
+
+        // changes made by the UI itself don't count as outside changes
+        let version = ui_db.data_version().unwrap();
+        ui_db.insert_podcast(make_feed("Daily News", &[("Monday", "")])).unwrap();
+        assert_eq!(ui_db.data_version().unwrap(), version);
+
+        // e.g., `shellcaster sync` running at the same time
+        let sync_db = Database::connect(&dir).unwrap();
+        let pod_id = sync_db.get_podcasts(false).unwrap()[0].id;
+        let result = sync_db
+            .update_podcast(pod_id, make_feed("Daily News", &[("Monday", ""), ("Tuesday", "")]))
+            .unwrap();
+        assert_eq!(result.added.len(), 1);
+        assert_ne!(ui_db.data_version().unwrap(), version);
+        assert_eq!(ui_db.get_episodes(pod_id, false).unwrap().len(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn export_and_import_state() {
+        let dir = temp_dir("db-export");
+        let db = Database::connect(&dir.join("old")).unwrap();
+        let result = db
+            .insert_podcast(make_feed("Big Show", &[("One", ""), ("Two", ""), ("Three", "")]))
+            .unwrap();
+        // episodes are added oldest first
+        let ep_ids: Vec<i64> = result.added.iter().rev().map(|ep| ep.id).collect();
+        db.set_played_status(ep_ids[0], true).unwrap();
+        let audio = dir.join("one.mp3");
+        std::fs::write(&audio, b"audio").unwrap();
+        db.insert_file(ep_ids[0], &audio).unwrap();
+        db.insert_file(ep_ids[1], &dir.join("missing.mp3")).unwrap();
+        db.set_position(ep_ids[1], 120).unwrap();
+        db.add_history(&HistoryEntry {
+            ep_id: ep_ids[1],
+            pod_id: result.pod_id,
+            started_at: Utc.timestamp(1000, 0),
+            ended_at: Utc.timestamp(2000, 0),
+            listened: 120,
+            finished: false,
+        })
+        .unwrap();
+        db.add_note(ep_ids[1], Some(60), "Good bit").unwrap();
+        db.set_starred(ep_ids[2], true).unwrap();
+        db.hide_episode(ep_ids[2], true).unwrap();
+        db.set_tags(result.pod_id, &["news".to_string()]).unwrap();
+        db.set_podcast_settings(result.pod_id, &PodcastSettings {
+            speed: 1.5,
+            skip_intro: 0,
+            skip_outro: 0,
+        })
+        .unwrap();
+        let state = db.export_state().unwrap();
+
+        let new_db = Database::connect(&dir.join("new")).unwrap();
+        let report = new_db.import_state(&state, false, false).unwrap();
+        assert_eq!(report, ImportReport {
+            podcasts_added: 1,
+            episodes_added: 3,
+            episodes_updated: 0,
+        });
+        // files are only kept if they are there; the episodes have no
+        // dates to sort them by
+        let mut exported = state.clone();
+        exported[0].episodes.sort_by(|a, b| a.title.cmp(&b.title));
+        exported[0].episodes[2].file = None;
+        let mut imported = new_db.export_state().unwrap();
+        imported[0].episodes.sort_by(|a, b| a.title.cmp(&b.title));
+        assert_eq!(imported, exported);
+
+        // importing again changes nothing, or adds anything twice
+        let report = new_db.import_state(&state, false, false).unwrap();
+        assert_eq!(report, ImportReport::default());
+        assert_eq!(new_db.get_history().unwrap().len(), 1);
+        let pod = &new_db.get_podcasts(true).unwrap()[0];
+        let id_of = |title: &str| {
+            return pod.episodes.filter_map(|ep| (ep.title == title).then_some(ep.id))[0];
+        };
+        let (one, two, three) = (id_of("One"), id_of("Two"), id_of("Three"));
+        assert_eq!(pod.episodes.clone_episode(two).unwrap().notes.len(), 1);
+
+        // merging keeps what was done here more recently
+        new_db.set_played_status(one, false).unwrap();
+        new_db.set_starred(one, true).unwrap();
+        new_db.set_position(two, 500).unwrap();
+        new_db
+            .add_history(&HistoryEntry {
+                ep_id: two,
+                pod_id: pod.id,
+                started_at: Utc.timestamp(3000, 0),
+                ended_at: Utc.timestamp(3500, 0),
+                listened: 380,
+                finished: false,
+            })
+            .unwrap();
+        new_db.hide_episode(three, false).unwrap();
+        new_db.set_tags(pod.id, &["talk".to_string()]).unwrap();
+        let report = new_db.import_state(&state, true, false).unwrap();
+        assert_eq!(report.episodes_updated, 2);
+        let pod = &new_db.get_podcasts(true).unwrap()[0];
+        let episode = pod.episodes.clone_episode(one).unwrap();
+        assert!(episode.played && episode.starred);
+        assert_eq!(pod.episodes.clone_episode(two).unwrap().position, 500);
+        assert!(pod.episodes.clone_episode(three).unwrap().hidden);
+        assert_eq!(pod.tags, vec!["news", "talk"]);
+
+        // otherwise, the file wins
+        new_db.import_state(&state, false, false).unwrap();
+        let pod = &new_db.get_podcasts(true).unwrap()[0];
+        assert!(!pod.episodes.clone_episode(one).unwrap().starred);
+        assert_eq!(pod.episodes.clone_episode(two).unwrap().position, 120);
+        assert_eq!(pod.tags, vec!["news"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn replace_on_import() {
+        let dir = temp_dir("db-replace");
+        let db = Database::connect(&dir.join("old")).unwrap();
+        db.insert_podcast(make_feed("Big Show", &[("One", "")]))
+            .unwrap();
+        let state = db.export_state().unwrap();
+
+        let new_db = Database::connect(&dir.join("new")).unwrap();
+        new_db
+            .insert_podcast(make_feed("Other Show", &[("Two", "")]))
+            .unwrap();
+        // the podcasts are only deleted if the import succeeds
+        let mut invalid = state.clone();
+        invalid[0].episodes[0].pubdate = Some(i64::MAX);
+        assert!(new_db.import_state(&invalid, false, true).is_err());
+        let titles = |db: &Database| {
+            return db
+                .get_podcast_summaries(false)
+                .unwrap()
+                .iter()
+                .map(|pod| pod.title.clone())
+                .collect::<Vec<String>>();
+        };
+        assert_eq!(titles(&new_db), vec!["Other Show"]);
+
+        new_db.import_state(&state, false, true).unwrap();
+        assert_eq!(titles(&new_db), vec!["Big Show"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn podcast_settings_round_trip() {
+        let dir = temp_dir("db-settings");
+        let db = Database::connect(&dir).unwrap();
+        let pod_id = db.insert_podcast(make_feed("Big Show", &[])).unwrap().pod_id;
+        let other_id = db.insert_podcast(make_feed("Other Show", &[])).unwrap().pod_id;
+        assert_eq!(
+            db.get_podcast_summary(pod_id, false).unwrap().settings,
+            PodcastSettings::default()
+        );
+
+        let settings = PodcastSettings {
+            speed: 1.75,
+            skip_intro: 45,
+            skip_outro: 90,
+        };
+        db.set_podcast_settings(pod_id, &settings).unwrap();
+        assert_eq!(db.get_podcast_summary(pod_id, false).unwrap().settings, settings);
+        let pod = db.get_podcasts(false).unwrap().into_iter().find(|pod| pod.id == pod_id);
+        assert_eq!(pod.unwrap().settings, settings);
+        // other podcasts keep their own settings
+        assert_eq!(
+            db.get_podcast_summary(other_id, false).unwrap().settings,
+            PodcastSettings::default()
+        );
+
+        db.set_podcast_settings(pod_id, &PodcastSettings::default()).unwrap();
+        assert_eq!(
+            db.get_podcast_summary(pod_id, false).unwrap().settings,
+            PodcastSettings::default()
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn queue_round_trip() {
+        let dir = temp_dir("db-queue");
+        let db = Database::connect(&dir).unwrap();
+        let result = db
+            .insert_podcast(make_feed("Big Show", &[("One", ""), ("Two", ""), ("Three", "")]))
+            .unwrap();
+        let pod_id = result.pod_id;
+        let ep_ids: Vec<i64> = result.added.iter().rev().map(|ep| ep.id).collect();
+        assert!(db.get_queue().unwrap().is_empty());
+
+        db.set_queue(&[ep_ids[2], ep_ids[0], ep_ids[1]]).unwrap();
+        assert_eq!(db.get_queue().unwrap(), vec![
+            (pod_id, ep_ids[2]),
+            (pod_id, ep_ids[0]),
+            (pod_id, ep_ids[1])
+        ]);
+        // the queue is replaced, not added to
+        db.set_queue(&[ep_ids[1]]).unwrap();
+        assert_eq!(db.get_queue().unwrap(), vec![(pod_id, ep_ids[1])]);
+        db.set_queue(&[]).unwrap();
+        assert!(db.get_queue().unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn trash_and_restore_podcast() {
+        let dir = temp_dir("db-trash");
+        let db = Database::connect(&dir).unwrap();
+        db.insert_podcast(make_feed("Old Show", &[("Pilot", "first"), ("Finale", "")]))
+            .unwrap();
+        db.insert_podcast(make_feed("Other Show", &[])).unwrap();
+        let pod_id = db.get_podcasts(false).unwrap()[0].id;
+        let episodes = db.get_episodes(pod_id, false).unwrap();
+        let ep_id = episodes[0].id;
+        db.set_played_status(ep_id, true).unwrap();
+        db.set_tags(pod_id, &["comedy".to_string()]).unwrap();
+        db.add_note(ep_id, Some(60), "good bit").unwrap();
+        db.insert_file(ep_id, Path::new("/podcasts/pilot.mp3")).unwrap();
+        db.set_queue(&[episodes[1].id]).unwrap();
+
+        let trash_id = db.trash_podcast(pod_id).unwrap();
+        assert_eq!(db.get_podcasts(false).unwrap().len(), 1);
+        assert!(db.get_files().unwrap().is_empty());
+        assert!(db.get_queue().unwrap().is_empty());
+        assert!(search_titles(&db, "pilot").is_empty());
+
+        assert_eq!(db.restore_podcast(trash_id).unwrap(), pod_id);
+        let podcasts = db.get_podcasts(false).unwrap();
+        let pod = podcasts.iter().find(|pod| pod.id == pod_id).unwrap();
+        assert!(pod.has_tag("comedy"));
+        let restored = db.get_episodes(pod_id, false).unwrap();
+        let pilot = restored.iter().find(|ep| ep.id == ep_id).unwrap();
+        assert!(pilot.played);
+        assert_eq!(pilot.notes[0].format(), "[01:00] good bit");
+        assert_eq!(db.get_files().unwrap(), vec![(ep_id, PathBuf::from("/podcasts/pilot.mp3"))]);
+        assert_eq!(db.get_queue().unwrap(), vec![(pod_id, episodes[1].id)]);
+        assert_eq!(search_titles(&db, "first"), vec!["Pilot"]);
+        assert!(db.restore_podcast(trash_id).is_err());
+
+        // a podcast that has been added again can't be restored
+        let trash_id = db.trash_podcast(pod_id).unwrap();
+        db.insert_podcast(make_feed("Old Show", &[])).unwrap();
+        assert!(db.restore_podcast(trash_id).is_err());
+        assert_eq!(db.get_podcasts(false).unwrap().len(), 2);
+        db.empty_trash().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn episode_notes() {
+        let dir = temp_dir("db-notes");
+        let db = Database::connect(&dir).unwrap();
+        db.insert_podcast(make_feed("Research", &[("Claims", ""), ("Other", "")]))
+            .unwrap();
+        let pod_id = db.get_podcasts(false).unwrap()[0].id;
+        let ep_id = db.search("claims", 100, false).unwrap()[0].id;
+
+        let general = db.add_note(ep_id, None, "Worth a second listen").unwrap();
+        db.add_note(ep_id, Some(2530), "interesting claim about X").unwrap();
+        db.add_note(ep_id, Some(95), "intro ends").unwrap();
+        let episodes = db.get_episodes(pod_id, false).unwrap();
+        let claims = episodes.iter().find(|ep| ep.id == ep_id).unwrap();
+        let texts: Vec<String> = claims.notes.iter().map(|note| note.format()).collect();
+        assert_eq!(texts, vec![
+            "[01:35] intro ends",
+            "[42:10] interesting claim about X",
+            "Worth a second listen",
+        ]);
+        assert!(episodes.iter().find(|ep| ep.id != ep_id).unwrap().notes.is_empty());
+
+        db.remove_note(general.id).unwrap();
+        assert_eq!(db.get_notes(pod_id).unwrap()[&ep_id].len(), 2);
+
+        // notes go along with their podcast
+        db.trash_podcast(pod_id).unwrap();
+        let n_notes: i64 = db
+            .conn
+            .as_ref()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM notes;", params![], |row| row.get(0))
+            .unwrap();
+        assert_eq!(n_notes, 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn backup_and_restore() {
+        let dir = temp_dir("db-backup");
+        let db = Database::connect(&dir).unwrap();
+        db.insert_podcast(make_feed("Keep Me", &[("One", "")])).unwrap();
+        let backup = dir.join("manual.db");
+        db.backup(&backup).unwrap();
+        // existing files are not overwritten
+        assert!(db.backup(&backup).is_err());
+
+        db.clear_db().unwrap();
+        drop(db);
+        let saved = Database::restore(&dir, &backup, 5).unwrap().unwrap();
+        assert!(saved.starts_with(dir.join("backups")));
+        let db = Database::connect(&dir).unwrap();
+        let podcasts = db.get_podcasts(false).unwrap();
+        assert_eq!(podcasts.len(), 1);
+        assert_eq!(podcasts[0].title, "Keep Me");
+
+        // the automatic backup holds the database as it was
+        drop(db);
+        Database::restore(&dir, &saved, 0).unwrap();
+        let db = Database::connect(&dir).unwrap();
+        assert!(db.get_podcasts(false).unwrap().is_empty());
+
+        // only the newest automatic backups are kept
+        for _ in 0..3 {
+            db.auto_backup(2).unwrap();
+        }
+        assert_eq!(std::fs::read_dir(dir.join("backups")).unwrap().count(), 2);
+        assert_eq!(db.auto_backup(0).unwrap(), None);
+
+        assert!(Database::restore(&dir, &dir.join("missing.db"), 0).is_err());
+        std::fs::write(dir.join("junk.db"), "not a database").unwrap();
+        assert!(Database::restore(&dir, &dir.join("junk.db"), 0).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use ahash::AHashMap;
+use sanitize_filename::{sanitize_with_options, Options};
+
+use crate::types::*;
+use crate::undo;
+
+/// Name of the playlist file written to the root of the device.
+pub const PLAYLIST_NAME: &str = "shellcaster.m3u";
+
+/// Struct holding data about an episode that is (or will be) stored on
+/// a device. `dest` is the filepath relative to the root of the device.
+#[derive(Debug, Clone)]
+pub struct DeviceEntry {
+    pub ep_id: i64,
+    pub title: String,
+    pub pod_title: String,
+    pub duration: Option<i64>,
+    pub source: Option<PathBuf>,
+    pub dest: PathBuf,
+}
+
+/// Struct describing the changes needed to bring a device up to date.
+/// `playlist` lists every episode that will be on the device once the
+/// copies and removals are done, in the order they should be played.
+#[derive(Debug, Default)]
+pub struct DevicePlan {
+    pub copy: Vec<DeviceEntry>,
+    pub remove: Vec<(i64, PathBuf)>,
+    pub playlist: Vec<DeviceEntry>,
+}
+
+/// Works out which episodes need to be copied to or removed from the
+/// device at `device_dir`, given the full list of podcasts, the ids of
+/// the podcasts selected for syncing, and the episodes already tracked
+/// as being on the device.
+///
+/// Unplayed, downloaded episodes of the selected podcasts are copied if
+/// they are not on the device yet. Episodes on the device are removed
+/// once they have been marked as played, or if they no longer exist in
+/// the database. Episodes of podcasts that were not selected this time
+/// are left alone.
+pub fn plan(
+    podcasts: &[Podcast],
+    selected: &HashSet<i64>,
+    tracked: &[(i64, PathBuf)],
+    device_dir: &Path,
+) -> DevicePlan {
+    let mut plan = DevicePlan::default();
+
+    let mut episodes = AHashMap::new();
+    for pod in podcasts.iter() {
+        for ep in pod.episodes.map(|ep| ep.clone(), false).into_iter() {
+            episodes.insert(ep.id, (pod, ep));
+        }
+    }
+
+    // figure out what should stay and what should go
+    let mut on_device = AHashMap::new();
+    for (ep_id, dest) in tracked.iter() {
+        match episodes.get(ep_id) {
+            Some((_, ep)) if !ep.played => {
+                if device_dir.join(dest).exists() {
+                    on_device.insert(*ep_id, dest.clone());
+                } else {
+                    // the file was deleted from the device by some
+                    // other means, so forget about it; it will be
+                    // copied again below if it is still wanted
+                    plan.remove.push((*ep_id, dest.clone()));
+                }
+            }
+            _ => plan.remove.push((*ep_id, dest.clone())),
+        }
+    }
+
+    for pod in podcasts.iter() {
+        let dir_name = sanitize_with_options(&pod.title, Options {
+            truncate: true,
+            windows: true, // for simplicity, we'll just use Windows-friendly paths for everyone
+            replacement: "",
+        });
+
+        // episodes are stored newest first, but it makes more sense to
+        // listen to them oldest first
+        let pod_episodes = pod.episodes.map(|ep| ep.clone(), false);
+        for ep in pod_episodes.into_iter().rev() {
+            let dest = match on_device.get(&ep.id) {
+                Some(dest) => dest.clone(),
+                None => {
+                    if !selected.contains(&pod.id) || ep.played {
+                        continue;
+                    }
+                    // deleted files waiting in the trash are not copied
+                    let source = ep.path.as_ref().filter(|p| !undo::in_trash(p));
+                    let file_name = match source.and_then(|p| p.file_name()) {
+                        Some(name) => name.to_owned(),
+                        None => continue,
+                    };
+                    let mut dest = PathBuf::from(&dir_name);
+                    dest.push(file_name);
+
+                    let entry = DeviceEntry {
+                        ep_id: ep.id,
+                        title: ep.title.clone(),
+                        pod_title: pod.title.clone(),
+                        duration: ep.duration,
+                        source: ep.path.clone(),
+                        dest: dest.clone(),
+                    };
+                    plan.copy.push(entry);
+                    dest
+                }
+            };
+            plan.playlist.push(DeviceEntry {
+                ep_id: ep.id,
+                title: ep.title,
+                pod_title: pod.title.clone(),
+                duration: ep.duration,
+                source: ep.path,
+                dest: dest,
+            });
+        }
+    }
+    return plan;
+}
+
+/// Creates the contents of an extended M3U playlist for the given
+/// entries. Paths are written relative to the root of the device, with
+/// forward slashes, which most players understand.
+pub fn playlist(entries: &[DeviceEntry]) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    for entry in entries.iter() {
+        let path: Vec<String> = entry
+            .dest
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        out.push_str(&format!(
+            "#EXTINF:{},{} - {}\n{}\n",
+            entry.duration.unwrap_or(-1),
+            entry.pod_title,
+            entry.title,
+            path.join("/")
+        ));
+    }
+    return out;
+}
+
+
+// TESTS -----------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{make_episode, make_podcast, temp_dir};
+    use std::fs;
+
+    fn make_device_episode(id: i64, played: bool, downloaded: bool) -> Episode {
+        return Episode {
+            duration: Some(60 * id),
+            path: if downloaded {
+                Some(PathBuf::from(format!("/downloads/Pod/ep{id}.mp3")))
+            } else {
+                None
+            },
+            played: played,
+            ..make_episode(id, 1, &format!("Episode {id}"))
+        };
+    }
+
+    #[test]
+    fn copies_unplayed_downloaded() {
+        let dir = temp_dir("device-copy");
+        // newest first, as they come from the database
+        let pods = vec![make_podcast(1, "Pod 1", vec![
+            make_device_episode(3, false, true),
+            make_device_episode(2, false, false),
+            make_device_episode(1, true, true),
+        ])];
+        let selected: HashSet<i64> = [1].into_iter().collect();
+        let plan = plan(&pods, &selected, &[], &dir);
+
+        let copied: Vec<i64> = plan.copy.iter().map(|e| e.ep_id).collect();
+        assert_eq!(copied, vec![3]);
+        assert_eq!(plan.copy[0].dest, PathBuf::from("Pod 1/ep3.mp3"));
+        assert!(plan.remove.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn removes_played_and_keeps_unselected() {
+        let dir = temp_dir("device-remove");
+        fs::create_dir_all(dir.join("Pod 1")).unwrap();
+        fs::create_dir_all(dir.join("Pod 2")).unwrap();
+        fs::write(dir.join("Pod 1/ep1.mp3"), "").unwrap();
+        fs::write(dir.join("Pod 2/ep5.mp3"), "").unwrap();
+
+        let pods = vec![
+            make_podcast(1, "Pod 1", vec![make_device_episode(1, true, true)]),
+            make_podcast(2, "Pod 2", vec![make_device_episode(5, false, true)]),
+        ];
+        let tracked = vec![
+            (1, PathBuf::from("Pod 1/ep1.mp3")),
+            (5, PathBuf::from("Pod 2/ep5.mp3")),
+            (9, PathBuf::from("Pod 3/ep9.mp3")),
+        ];
+        let selected: HashSet<i64> = [1].into_iter().collect();
+        let plan = plan(&pods, &selected, &tracked, &dir);
+
+        let removed: Vec<i64> = plan.remove.iter().map(|(id, _)| *id).collect();
+        assert_eq!(removed, vec![1, 9]);
+        assert!(plan.copy.is_empty());
+        let listed: Vec<i64> = plan.playlist.iter().map(|e| e.ep_id).collect();
+        assert_eq!(listed, vec![5]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recopies_missing_files() {
+        let dir = temp_dir("device-recopy");
+        let pods = vec![make_podcast(1, "Pod 1", vec![make_device_episode(1, false, true)])];
+        let tracked = vec![(1, PathBuf::from("Pod 1/ep1.mp3"))];
+        let selected: HashSet<i64> = [1].into_iter().collect();
+        let plan = plan(&pods, &selected, &tracked, &dir);
+
+        assert_eq!(plan.remove.len(), 1);
+        assert_eq!(plan.copy.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn playlist_format() {
+        let entries = vec![DeviceEntry {
+            ep_id: 1,
+            title: "First".to_string(),
+            pod_title: "Pod".to_string(),
+            duration: Some(90),
+            source: None,
+            dest: PathBuf::from("Pod").join("first.mp3"),
+        }];
+        assert_eq!(
+            playlist(&entries),
+            "#EXTM3U\n#EXTINF:90,Pod - First\nPod/first.mp3\n"
+        );
+    }
+}
@@ -12,6 +12,7 @@ use crate::types::Message;
 /// Enum used for communicating back to the main controller upon
 /// successful or unsuccessful downloading of a file. i32 value
 /// represents the episode ID, and PathBuf the location of the new file.
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum DownloadMsg {
     Complete(EpData),
@@ -29,11 +29,14 @@ pub enum FeedMsg {
 
 /// Struct holding data about a podcast feed (subset of info about a
 /// podcast) for the purpose of passing back and forth between threads.
+/// `tags` holds tags to give the podcast once it has been added, e.g.,
+/// when importing from OPML.
 #[derive(Debug, Clone)]
 pub struct PodcastFeed {
     pub id: Option<i64>,
     pub url: String,
     pub title: Option<String>,
+    pub tags: Vec<String>,
 }
 
 impl PodcastFeed {
@@ -42,6 +45,7 @@ impl PodcastFeed {
             id: id,
             url: url,
             title: title,
+            tags: Vec::new(),
         };
     }
 }
@@ -166,9 +170,17 @@ fn parse_episode_data(item: &Item) -> EpisodeNoId {
         Some(s) => s.to_string(),
         None => "".to_string(),
     };
-    let url = match item.enclosure() {
-        Some(enc) => enc.url().to_string(),
-        None => "".to_string(),
+    let (url, mime_type) = match item.enclosure() {
+        Some(enc) => {
+            let mime_type = enc.mime_type().trim();
+            let mime_type = if mime_type.is_empty() {
+                None
+            } else {
+                Some(mime_type.to_lowercase())
+            };
+            (enc.url().to_string(), mime_type)
+        }
+        None => ("".to_string(), None),
     };
     let guid = match item.guid() {
         Some(guid) => guid.value().to_string(),
@@ -205,6 +217,7 @@ fn parse_episode_data(item: &Item) -> EpisodeNoId {
         description: description,
         pubdate: pubdate,
         duration: duration,
+        mime_type: mime_type,
     };
 }
 
@@ -0,0 +1,744 @@
+use anyhow::{anyhow, Context, Result};
+use std::time::Duration;
+
+use ahash::{AHashMap, AHashSet};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::config::{GpodderConfig, SyncApi};
+use crate::db::Database;
+
+/// Format of the timestamps on episode actions, which are in UTC.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Something the user did with an episode that is shared with other
+/// devices. Plays give where playback started and stopped, and the
+/// length of the episode, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActionKind {
+    Play {
+        started: i64,
+        position: i64,
+        total: i64,
+    },
+    Download,
+    Delete,
+}
+
+impl ActionKind {
+    /// Returns the name the gpodder.net API uses for the action.
+    pub fn name(&self) -> &'static str {
+        return match self {
+            ActionKind::Play { .. } => "play",
+            ActionKind::Download => "download",
+            ActionKind::Delete => "delete",
+        };
+    }
+}
+
+/// An episode action as it is sent to and received from the server.
+/// Episodes are identified by the URL of their podcast's feed and
+/// their own URL, and optionally their guid.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EpisodeAction {
+    pub podcast: String,
+    pub episode: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    pub action: String,
+    pub timestamp: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<i64>,
+}
+
+/// How far an account on the server has been synced: the server's
+/// timestamps to ask for changes since, and the subscriptions as they
+/// were after the last sync, to tell what the user has changed since.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GpodderState {
+    pub subscriptions_since: i64,
+    pub actions_since: i64,
+    pub subscriptions: Vec<String>,
+}
+
+/// What syncing subscriptions changed: feeds subscribed to on other
+/// devices, which still need to be added, podcasts archived because
+/// they were unsubscribed from elsewhere, or unarchived because they
+/// were subscribed to again, and how many changes were uploaded.
+#[derive(Debug, Default, PartialEq)]
+pub struct SubscriptionReport {
+    pub new_feeds: Vec<String>,
+    pub archived: Vec<String>,
+    pub unarchived: Vec<String>,
+    pub uploaded: usize,
+}
+
+/// What syncing episode actions changed: how many episodes were
+/// updated from other devices, how many updates were skipped because
+/// the episode has been played here since, and how many actions were
+/// uploaded.
+#[derive(Debug, Default, PartialEq)]
+pub struct ActionReport {
+    pub applied: usize,
+    pub skipped: usize,
+    pub uploaded: usize,
+}
+
+/// Changes to the subscriptions on the server since a given time.
+#[derive(Debug, Deserialize)]
+struct SubscriptionChanges {
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+    timestamp: i64,
+}
+
+/// Episode actions on the server since a given time.
+#[derive(Debug, Deserialize)]
+struct EpisodeActions {
+    #[serde(default)]
+    actions: Vec<EpisodeAction>,
+    timestamp: i64,
+}
+
+/// The server's answer to an upload.
+#[derive(Debug, Deserialize)]
+struct UploadResponse {
+    timestamp: i64,
+}
+
+/// Client for the gpodder.net API v2, or the API of the Nextcloud
+/// gpoddersync app, which works the same way at different addresses.
+pub struct Client {
+    agent: ureq::Agent,
+    config: GpodderConfig,
+}
+
+impl Client {
+    /// Creates a client for the account in the user's configuration.
+    pub fn new(config: &GpodderConfig) -> Client {
+        let agent_builder = ureq::builder()
+            .timeout_connect(Duration::from_secs(5))
+            .timeout_read(Duration::from_secs(20));
+        #[cfg(feature = "native_tls")]
+        let tls_connector = std::sync::Arc::new(native_tls::TlsConnector::new().unwrap());
+        #[cfg(feature = "native_tls")]
+        let agent_builder = agent_builder.tls_connector(tls_connector);
+
+        return Client {
+            agent: agent_builder.build(),
+            config: config.clone(),
+        };
+    }
+
+    /// Identifies the account and device, so that how far each has been
+    /// synced is kept separately.
+    pub fn account(&self) -> String {
+        return format!(
+            "{} {} {}",
+            self.config.username, self.config.server, self.config.device
+        );
+    }
+
+    /// Registers shellcaster as a device on the account. The Nextcloud
+    /// API has no devices, so this does nothing there.
+    pub fn register_device(&self) -> Result<()> {
+        if self.config.api == SyncApi::Nextcloud {
+            return Ok(());
+        }
+        let url = format!(
+            "{}/api/2/devices/{}/{}.json",
+            self.config.server, self.config.username, self.config.device
+        );
+        self.request("POST", &url)
+            .send_json(json!({ "caption": "shellcaster", "type": "desktop" }))
+            .map_err(|err| self.server_error(err))?;
+        return Ok(());
+    }
+
+    /// Gets the changes to the subscriptions since the server's
+    /// timestamp `since`.
+    fn get_subscriptions(&self, since: i64) -> Result<SubscriptionChanges> {
+        let url = match self.config.api {
+            SyncApi::Gpodder => format!(
+                "{}/api/2/subscriptions/{}/{}.json",
+                self.config.server, self.config.username, self.config.device
+            ),
+            SyncApi::Nextcloud => {
+                format!("{}/index.php/apps/gpoddersync/subscriptions", self.config.server)
+            }
+        };
+        return self.get(&url, since);
+    }
+
+    /// Uploads feeds that were subscribed to and unsubscribed from,
+    /// returning the server's timestamp for the change.
+    fn upload_subscriptions(&self, add: &[String], remove: &[String]) -> Result<i64> {
+        let url = match self.config.api {
+            SyncApi::Gpodder => format!(
+                "{}/api/2/subscriptions/{}/{}.json",
+                self.config.server, self.config.username, self.config.device
+            ),
+            SyncApi::Nextcloud => format!(
+                "{}/index.php/apps/gpoddersync/subscription_change/create",
+                self.config.server
+            ),
+        };
+        let response: UploadResponse = self.post(&url, json!({ "add": add, "remove": remove }))?;
+        return Ok(response.timestamp);
+    }
+
+    /// Gets the episode actions of all devices since the server's
+    /// timestamp `since`.
+    fn get_episode_actions(&self, since: i64) -> Result<EpisodeActions> {
+        let url = match self.config.api {
+            SyncApi::Gpodder => {
+                format!("{}/api/2/episodes/{}.json", self.config.server, self.config.username)
+            }
+            SyncApi::Nextcloud => {
+                format!("{}/index.php/apps/gpoddersync/episode_action", self.config.server)
+            }
+        };
+        return self.get(&url, since);
+    }
+
+    /// Uploads episode actions, returning the server's timestamp for
+    /// them.
+    fn upload_episode_actions(&self, actions: &[EpisodeAction]) -> Result<i64> {
+        let url = match self.config.api {
+            SyncApi::Gpodder => {
+                format!("{}/api/2/episodes/{}.json", self.config.server, self.config.username)
+            }
+            SyncApi::Nextcloud => format!(
+                "{}/index.php/apps/gpoddersync/episode_action/create",
+                self.config.server
+            ),
+        };
+        let response: UploadResponse = self.post(&url, json!(actions))?;
+        return Ok(response.timestamp);
+    }
+
+    /// Starts a request to the server, logged in with HTTP basic
+    /// authentication.
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let credentials = base64::encode(format!(
+            "{}:{}",
+            self.config.username, self.config.password
+        ));
+        return self
+            .agent
+            .request(method, url)
+            .set("Authorization", &format!("Basic {credentials}"));
+    }
+
+    /// Sends a GET request for changes since the server's timestamp
+    /// `since`, and reads the JSON response.
+    fn get<T: DeserializeOwned>(&self, url: &str, since: i64) -> Result<T> {
+        let response = self
+            .request("GET", url)
+            .query("since", &since.to_string())
+            .call()
+            .map_err(|err| self.server_error(err))?;
+        return response
+            .into_json()
+            .with_context(|| format!("Unexpected response from {}", self.config.server));
+    }
+
+    /// Sends a POST request with a JSON body, and reads the JSON
+    /// response.
+    fn post<T: DeserializeOwned>(&self, url: &str, body: serde_json::Value) -> Result<T> {
+        let response = self
+            .request("POST", url)
+            .send_json(body)
+            .map_err(|err| self.server_error(err))?;
+        return response
+            .into_json()
+            .with_context(|| format!("Unexpected response from {}", self.config.server));
+    }
+
+    /// Turns a failed request into an error the user can act on.
+    fn server_error(&self, err: ureq::Error) -> anyhow::Error {
+        let server = &self.config.server;
+        return match err {
+            ureq::Error::Status(401, _) | ureq::Error::Status(403, _) => {
+                anyhow!("{server} did not accept the username and password")
+            }
+            ureq::Error::Status(code, _) => anyhow!("{server} answered with error {code}"),
+            ureq::Error::Transport(_) => anyhow!("Could not connect to {server}"),
+        };
+    }
+}
+
+/// Formats the time of an episode action the way the server expects.
+pub fn format_timestamp(time: DateTime<Utc>) -> String {
+    return time.format(TIMESTAMP_FORMAT).to_string();
+}
+
+/// Parses the time of an episode action from the server, which may
+/// also have fractions of a second or end in "Z" for UTC.
+fn parse_timestamp(timestamp: &str) -> Option<NaiveDateTime> {
+    let timestamp = timestamp.trim_end_matches('Z');
+    return NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%.f").ok();
+}
+
+/// Syncs the subscriptions with the server. Podcasts count as
+/// subscribed to unless they are archived, so podcasts unsubscribed
+/// from on other devices are archived, and subscribing to them again
+/// unarchives them. Feeds that are new to shellcaster are returned to
+/// be added. If a podcast was changed both here and elsewhere since the
+/// last sync, the change made here wins.
+pub fn sync_subscriptions(client: &Client, db: &Database) -> Result<SubscriptionReport> {
+    let account = client.account();
+    let mut state = db.get_gpodder_state(&account)?;
+    if state == GpodderState::default() {
+        client.register_device()?;
+    }
+
+    // work out what the user changed here before anything else does
+    let podcasts = db.get_podcast_summaries(false)?;
+    let known: AHashSet<&String> = state.subscriptions.iter().collect();
+    let current: AHashSet<&String> = podcasts
+        .iter()
+        .filter(|pod| !pod.archived)
+        .map(|pod| &pod.url)
+        .collect();
+    let mut added: Vec<String> = current.difference(&known).map(|url| url.to_string()).collect();
+    let removed: Vec<String> = known.difference(&current).map(|url| url.to_string()).collect();
+
+    let mut report = SubscriptionReport::default();
+    let changes = client.get_subscriptions(state.subscriptions_since)?;
+    for url in changes.add.iter().filter(|url| !removed.contains(url)) {
+        match podcasts.iter().find(|pod| pod.url == *url) {
+            Some(pod) if pod.archived => {
+                db.set_archived(pod.id, false)?;
+                report.unarchived.push(pod.title.clone());
+            }
+            Some(_) => (),
+            None => report.new_feeds.push(url.clone()),
+        }
+    }
+    for url in changes.remove.iter().filter(|url| !added.contains(url)) {
+        if let Some(pod) = podcasts.iter().find(|pod| pod.url == *url && !pod.archived) {
+            db.set_archived(pod.id, true)?;
+            report.archived.push(pod.title.clone());
+        }
+    }
+
+    // the server already has what was subscribed to on both sides
+    added.retain(|url| !changes.add.contains(url));
+    state.subscriptions_since = changes.timestamp;
+    if !added.is_empty() || !removed.is_empty() {
+        state.subscriptions_since = client.upload_subscriptions(&added, &removed)?;
+        report.uploaded = added.len() + removed.len();
+    }
+
+    // feeds that have yet to be added count as new on the next sync
+    // once they are, which the server will take in its stride
+    state.subscriptions = db
+        .get_podcast_summaries(false)?
+        .into_iter()
+        .filter(|pod| !pod.archived)
+        .map(|pod| pod.url)
+        .collect();
+    db.set_gpodder_state(&account, &state)?;
+    return Ok(report);
+}
+
+/// Syncs episode actions with the server. Plays from other devices
+/// update where to resume each episode, and mark episodes as played
+/// once they got past `played_threshold` percent of the episode.
+/// Downloads and deletions only concern the device they happened on,
+/// so they are only uploaded.
+pub fn sync_episode_actions(
+    client: &Client,
+    db: &Database,
+    played_threshold: i64,
+) -> Result<ActionReport> {
+    let account = client.account();
+    let mut state = db.get_gpodder_state(&account)?;
+    let mut report = ActionReport::default();
+
+    // episodes played here since the last sync, with the last time each
+    // one was played; these have not been uploaded yet, so anything the
+    // server has from before then is out of date
+    let pending = db.get_episode_actions()?;
+    let mut played_here: AHashMap<i64, NaiveDateTime> = AHashMap::new();
+    for (_, action) in pending.iter().filter(|(_, action)| action.action == "play") {
+        let found = db.find_episode(&action.podcast, &action.episode, action.guid.as_deref())?;
+        if let (Some((ep_id, _)), Some(time)) = (found, parse_timestamp(&action.timestamp)) {
+            let latest = played_here.entry(ep_id).or_insert(time);
+            *latest = time.max(*latest);
+        }
+    }
+
+    let mut remote = client.get_episode_actions(state.actions_since)?;
+    // the timestamps sort in the order the actions happened
+    remote
+        .actions
+        .sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    for action in remote.actions.iter() {
+        if action.device.as_deref() == Some(client.config.device.as_str())
+            || !action.action.eq_ignore_ascii_case("play")
+        {
+            continue;
+        }
+        let position = match action.position {
+            Some(position) => position,
+            None => continue,
+        };
+        let (ep_id, played) =
+            match db.find_episode(&action.podcast, &action.episode, action.guid.as_deref())? {
+                Some(episode) => episode,
+                None => continue,
+            };
+        if let Some(latest) = played_here.get(&ep_id) {
+            // without a time to go by, the action from here wins
+            if parse_timestamp(&action.timestamp).is_none_or(|time| time <= *latest) {
+                report.skipped += 1;
+                continue;
+            }
+        }
+        let finished = action
+            .total
+            .is_some_and(|total| total > 0 && position * 100 >= total * played_threshold);
+        if finished {
+            if !played {
+                db.set_played_status(ep_id, true)?;
+            }
+            db.set_position(ep_id, 0)?;
+        } else {
+            db.set_position(ep_id, position)?;
+        }
+        report.applied += 1;
+    }
+
+    if let Some((last_id, _)) = pending.last() {
+        let last_id = *last_id;
+        let actions: Vec<EpisodeAction> = pending
+            .into_iter()
+            .map(|(_, mut action)| {
+                action.device = Some(client.config.device.clone());
+                action
+            })
+            .collect();
+        client.upload_episode_actions(&actions)?;
+        db.remove_episode_actions(last_id)?;
+        report.uploaded = actions.len();
+    }
+
+    // actions uploaded here come back next time, but are skipped as
+    // this device's own
+    state.actions_since = remote.timestamp;
+    db.set_gpodder_state(&account, &state)?;
+    return Ok(report);
+}
+
+
+// TESTS -----------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use crate::test_helpers::{make_feed, temp_dir};
+
+    /// A request received by the stub server.
+    #[derive(Debug, Clone)]
+    struct Request {
+        method: String,
+        path: String,
+        auth: String,
+        body: String,
+    }
+
+    /// Starts a server on localhost that records the requests it gets
+    /// and answers each with the JSON `respond` gives for its method and
+    /// path. Returns the server's address and the recorded requests.
+    fn stub_server(
+        respond: fn(&str, &str) -> &'static str,
+    ) -> (String, Arc<Mutex<Vec<Request>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_string();
+                let path = parts.next().unwrap_or_default().to_string();
+
+                let mut auth = String::new();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let header = header.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    let (name, value) = header.split_once(':').unwrap();
+                    match name.to_lowercase().as_str() {
+                        "authorization" => auth = value.trim().to_string(),
+                        "content-length" => length = value.trim().parse().unwrap(),
+                        _ => (),
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let response = respond(&method, &path);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+                recorded.lock().unwrap().push(Request {
+                    method: method,
+                    path: path,
+                    auth: auth,
+                    body: String::from_utf8(body).unwrap(),
+                });
+            }
+        });
+        return (address, requests);
+    }
+
+    fn make_config(server: &str, api: SyncApi) -> GpodderConfig {
+        return GpodderConfig {
+            server: server.to_string(),
+            username: "alice".to_string(),
+            password: "secret".to_string(),
+            device: "shellcaster".to_string(),
+            api: api,
+        };
+    }
+
+    fn respond_gpodder(method: &str, path: &str) -> &'static str {
+        return match (method, path) {
+            ("GET", "/api/2/subscriptions/alice/shellcaster.json?since=50") => {
+                r#"{"add": ["http://example.com/show-a", "http://example.com/show-c"],
+                    "remove": ["http://example.com/show-b"], "timestamp": 100}"#
+            }
+            ("GET", "/api/2/episodes/alice.json?since=60") => {
+                r#"{"actions": [
+                    {"podcast": "http://example.com/show-a", "episode": "http://example.com/show-a/One.mp3",
+                     "device": "phone", "action": "play", "timestamp": "2026-01-02T10:00:00",
+                     "started": 0, "position": 980, "total": 1000},
+                    {"podcast": "http://example.com/show-a", "episode": "http://cdn.example/two.mp3",
+                     "guid": "http://example.com/show-a/Two", "device": "phone", "action": "PLAY",
+                     "timestamp": "2026-01-02T11:00:00", "started": 0, "position": 300, "total": 1000},
+                    {"podcast": "http://example.com/show-a", "episode": "http://example.com/show-a/Two.mp3",
+                     "device": "shellcaster", "action": "play", "timestamp": "2026-01-01T09:00:00",
+                     "started": 0, "position": 700, "total": 1000},
+                    {"podcast": "http://example.com/show-a", "episode": "http://example.com/show-a/Two.mp3",
+                     "device": "phone", "action": "delete", "timestamp": "2026-01-02T12:00:00"}
+                ], "timestamp": 200}"#
+            }
+            ("POST", _) => r#"{"timestamp": 201, "update_urls": []}"#,
+            _ => "{}",
+        };
+    }
+
+    #[test]
+    fn sync_with_server() {
+        let dir = temp_dir("gpodder-sync");
+        let db = Database::connect(&dir).unwrap();
+        let result = db
+            .insert_podcast(make_feed("Show A", &[("One", ""), ("Two", "")]))
+            .unwrap();
+        let pod_a = result.pod_id;
+        // episodes are added oldest first
+        let ep_ids: Vec<i64> = result.added.iter().rev().map(|ep| ep.id).collect();
+        let pod_b = db
+            .insert_podcast(make_feed("Show B", &[]))
+            .unwrap()
+            .pod_id;
+        db.insert_podcast(make_feed("Show D", &[]))
+            .unwrap();
+        db.add_episode_action(ep_ids[1], &ActionKind::Download).unwrap();
+
+        let (server, requests) = stub_server(respond_gpodder);
+        let client = Client::new(&make_config(&server, SyncApi::Gpodder));
+        // A and B were there at the last sync, D was added since
+        db.set_gpodder_state(&client.account(), &GpodderState {
+            subscriptions_since: 50,
+            actions_since: 60,
+            subscriptions: vec![
+                "http://example.com/show-a".to_string(),
+                "http://example.com/show-b".to_string(),
+            ],
+        })
+        .unwrap();
+
+        let report = sync_subscriptions(&client, &db).unwrap();
+        assert_eq!(report, SubscriptionReport {
+            new_feeds: vec!["http://example.com/show-c".to_string()],
+            archived: vec!["Show B".to_string()],
+            unarchived: Vec::new(),
+            uploaded: 1,
+        });
+        assert!(db.get_podcast_summary(pod_b, true).unwrap().archived);
+        let state = db.get_gpodder_state(&client.account()).unwrap();
+        assert_eq!(state.subscriptions_since, 201);
+        let mut subscriptions = state.subscriptions.clone();
+        subscriptions.sort();
+        assert_eq!(subscriptions, vec!["http://example.com/show-a", "http://example.com/show-d"]);
+
+        let report = sync_episode_actions(&client, &db, 95).unwrap();
+        assert_eq!(report, ActionReport {
+            applied: 2,
+            skipped: 0,
+            uploaded: 1,
+        });
+        let episodes = db.get_episodes(pod_a, false).unwrap();
+        let one = episodes.iter().find(|ep| ep.id == ep_ids[0]).unwrap();
+        assert!(one.played);
+        assert_eq!(one.position, 0);
+        // found by its guid, and this device's own action is skipped
+        let two = episodes.iter().find(|ep| ep.id == ep_ids[1]).unwrap();
+        assert!(!two.played);
+        assert_eq!(two.position, 300);
+        assert!(db.get_episode_actions().unwrap().is_empty());
+        assert_eq!(db.get_gpodder_state(&client.account()).unwrap().actions_since, 200);
+
+        let requests = requests.lock().unwrap().clone();
+        let credentials = format!("Basic {}", base64::encode("alice:secret"));
+        assert!(requests.iter().all(|req| req.auth == credentials));
+        let upload = requests
+            .iter()
+            .find(|req| req.method == "POST" && req.path.starts_with("/api/2/subscriptions/"))
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&upload.body).unwrap();
+        assert_eq!(body, json!({ "add": ["http://example.com/show-d"], "remove": [] }));
+        let upload = requests
+            .iter()
+            .find(|req| req.method == "POST" && req.path == "/api/2/episodes/alice.json")
+            .unwrap();
+        let actions: Vec<EpisodeAction> = serde_json::from_str(&upload.body).unwrap();
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].action, "download");
+        assert_eq!(actions[0].episode, "http://example.com/show-a/Two.mp3");
+        assert_eq!(actions[0].device.as_deref(), Some("shellcaster"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn respond_conflict(method: &str, path: &str) -> &'static str {
+        return match (method, path) {
+            ("GET", "/api/2/episodes/alice.json?since=0") => {
+                r#"{"actions": [
+                    {"podcast": "http://example.com/show-a", "episode": "http://example.com/show-a/One.mp3",
+                     "device": "phone", "action": "play", "timestamp": "2026-01-02T10:00:00",
+                     "started": 0, "position": 980, "total": 1000},
+                    {"podcast": "http://example.com/show-a", "episode": "http://example.com/show-a/Two.mp3",
+                     "device": "phone", "action": "play", "timestamp": "2026-01-02T10:00:00.5Z",
+                     "started": 0, "position": 980, "total": 1000},
+                    {"podcast": "http://example.com/show-a", "episode": "http://example.com/show-a/Two.mp3",
+                     "device": "phone", "action": "play", "timestamp": "9999-01-01T00:00:00",
+                     "started": 0, "position": 400, "total": 1000}
+                ], "timestamp": 300}"#
+            }
+            ("POST", _) => r#"{"timestamp": 301, "update_urls": []}"#,
+            _ => "{}",
+        };
+    }
+
+    #[test]
+    fn local_plays_win_over_older_remote_plays() {
+        let dir = temp_dir("gpodder-conflict");
+        let db = Database::connect(&dir).unwrap();
+        let result = db
+            .insert_podcast(make_feed("Show A", &[("One", ""), ("Two", "")]))
+            .unwrap();
+        let pod_id = result.pod_id;
+        let ep_ids: Vec<i64> = result.added.iter().rev().map(|ep| ep.id).collect();
+        // both episodes were played here after the server's first plays
+        let stopped = ActionKind::Play {
+            started: 0,
+            position: 120,
+            total: 1000,
+        };
+        for &ep_id in ep_ids.iter() {
+            db.set_position(ep_id, 120).unwrap();
+            db.add_episode_action(ep_id, &stopped).unwrap();
+        }
+
+        let (server, requests) = stub_server(respond_conflict);
+        let client = Client::new(&make_config(&server, SyncApi::Gpodder));
+        let report = sync_episode_actions(&client, &db, 95).unwrap();
+        assert_eq!(report, ActionReport {
+            applied: 1,
+            skipped: 2,
+            uploaded: 2,
+        });
+        let episodes = db.get_episodes(pod_id, false).unwrap();
+        let one = episodes.iter().find(|ep| ep.id == ep_ids[0]).unwrap();
+        assert!(!one.played);
+        assert_eq!(one.position, 120);
+        // only the play from after this one is applied
+        let two = episodes.iter().find(|ep| ep.id == ep_ids[1]).unwrap();
+        assert!(!two.played);
+        assert_eq!(two.position, 400);
+
+        let requests = requests.lock().unwrap().clone();
+        let upload = requests
+            .iter()
+            .find(|req| req.method == "POST" && req.path == "/api/2/episodes/alice.json")
+            .unwrap();
+        let actions: Vec<EpisodeAction> = serde_json::from_str(&upload.body).unwrap();
+        assert!(actions.iter().all(|action| action.position == Some(120)));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn respond_nextcloud(method: &str, path: &str) -> &'static str {
+        return match (method, path) {
+            ("GET", "/index.php/apps/gpoddersync/subscriptions?since=0") => {
+                r#"{"add": [], "remove": [], "timestamp": 10}"#
+            }
+            ("POST", "/index.php/apps/gpoddersync/subscription_change/create") => {
+                r#"{"timestamp": 11}"#
+            }
+            _ => "not found",
+        };
+    }
+
+    #[test]
+    fn sync_with_nextcloud() {
+        let dir = temp_dir("gpodder-nextcloud");
+        let db = Database::connect(&dir).unwrap();
+        db.insert_podcast(make_feed("Show A", &[]))
+            .unwrap();
+
+        let (server, requests) = stub_server(respond_nextcloud);
+        let client = Client::new(&make_config(&server, SyncApi::Nextcloud));
+        // on the first sync, everything here is new to the server
+        let report = sync_subscriptions(&client, &db).unwrap();
+        assert_eq!(report.uploaded, 1);
+        assert_eq!(db.get_gpodder_state(&client.account()).unwrap().subscriptions_since, 11);
+
+        // there are no devices to register with Nextcloud
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|req| req.path.starts_with("/index.php/apps/gpoddersync/")));
+
+        // anything other than JSON is an error
+        assert!(sync_episode_actions(&client, &db, 95).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
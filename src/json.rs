@@ -0,0 +1,235 @@
+use anyhow::{anyhow, Context, Result};
+use std::path::PathBuf;
+
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::types::PodcastSettings;
+
+/// Version of the JSON export format. Files from a newer version of
+/// shellcaster may hold data this version would silently drop, so they
+/// are refused.
+pub const FORMAT_VERSION: i64 = 1;
+
+/// Everything shellcaster knows about the user's podcasts, as written
+/// by `shellcaster export --format json`. Times are Unix timestamps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Export {
+    pub version: i64,
+    pub exported: i64,
+    pub podcasts: Vec<PodcastState>,
+}
+
+/// A podcast, along with the user's tags and settings for it, and all
+/// of its episodes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodcastState {
+    pub title: String,
+    pub url: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub explicit: Option<bool>,
+    pub last_checked: i64,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub settings: PodcastSettings,
+    #[serde(default)]
+    pub episodes: Vec<EpisodeState>,
+}
+
+/// An episode, along with whether and how far it was played, whether it
+/// was hidden or starred, where it was downloaded to, and the user's
+/// notes and listening history for it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EpisodeState {
+    pub title: String,
+    pub url: String,
+    #[serde(default)]
+    pub guid: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub pubdate: Option<i64>,
+    #[serde(default)]
+    pub duration: Option<i64>,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    #[serde(default)]
+    pub played: bool,
+    #[serde(default)]
+    pub played_at: Option<i64>,
+    #[serde(default)]
+    pub position: i64,
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub starred: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<NoteState>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<PlayState>,
+}
+
+impl EpisodeState {
+    /// Returns the last time the episode was played or marked as
+    /// played, if ever. Merging imports keeps whichever side of an
+    /// episode's played status and position is more recent by this.
+    pub fn last_activity(&self) -> Option<i64> {
+        return self
+            .history
+            .iter()
+            .map(|play| play.ended_at)
+            .chain(self.played_at)
+            .max();
+    }
+}
+
+/// A note on an episode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoteState {
+    #[serde(default)]
+    pub position: Option<i64>,
+    pub text: String,
+    pub created: i64,
+}
+
+/// An entry in the listening history of an episode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayState {
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub listened: i64,
+    pub finished: bool,
+}
+
+/// What importing a JSON file changed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub podcasts_added: usize,
+    pub episodes_added: usize,
+    pub episodes_updated: usize,
+}
+
+/// Writes the state of all podcasts as (pretty-printed) JSON.
+pub fn export(podcasts: Vec<PodcastState>) -> Result<String> {
+    let export = Export {
+        version: FORMAT_VERSION,
+        exported: Utc::now().timestamp(),
+        podcasts: podcasts,
+    };
+    return serde_json::to_string_pretty(&export).with_context(|| "Could not create JSON format");
+}
+
+/// Reads the state of podcasts from a file written by `export()`.
+pub fn import(contents: &str) -> Result<Vec<PodcastState>> {
+    let export: Export = serde_json::from_str(contents)?;
+    if export.version > FORMAT_VERSION {
+        return Err(anyhow!(
+            "The file was exported by a newer version of shellcaster (format version {}), which this version cannot read",
+            export.version
+        ));
+    }
+    for podcast in export.podcasts.iter() {
+        check_times(podcast)
+            .with_context(|| format!("Podcast \"{}\" has an invalid time", podcast.title))?;
+    }
+    return Ok(export.podcasts);
+}
+
+/// Checks that all times in a podcast's state can be read back as
+/// dates, so that a damaged file is refused before anything from it is
+/// saved.
+fn check_times(podcast: &PodcastState) -> Result<()> {
+    check_time(podcast.last_checked, "last_checked")?;
+    for episode in podcast.episodes.iter() {
+        if let Some(pubdate) = episode.pubdate {
+            check_time(pubdate, "pubdate")?;
+        }
+        if let Some(played_at) = episode.played_at {
+            check_time(played_at, "played_at")?;
+        }
+        for note in episode.notes.iter() {
+            check_time(note.created, "created")?;
+        }
+        for play in episode.history.iter() {
+            check_time(play.started_at, "started_at")?;
+            check_time(play.ended_at, "ended_at")?;
+        }
+    }
+    return Ok(());
+}
+
+/// Checks that a Unix timestamp is within the range of dates chrono
+/// can represent.
+fn check_time(time: i64, field: &str) -> Result<()> {
+    return match Utc.timestamp_opt(time, 0).single() {
+        Some(_) => Ok(()),
+        None => Err(anyhow!("{field} is out of range: {time}")),
+    };
+}
+
+
+// TESTS -----------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_minimal_file() {
+        // everything but what identifies podcasts and episodes can be
+        // left out
+        let contents = r#"{"version": 1, "exported": 0, "podcasts": [
+            {"title": "Show", "url": "http://example.com/feed", "last_checked": 0,
+             "episodes": [{"title": "One", "url": "http://example.com/one.mp3",
+                           "played_at": 100, "history": [
+                               {"started_at": 50, "ended_at": 200, "listened": 150, "finished": true}
+                           ]}]}
+        ]}"#;
+        let podcasts = import(contents).unwrap();
+        assert_eq!(podcasts[0].settings, PodcastSettings::default());
+        let episode = &podcasts[0].episodes[0];
+        assert!(!episode.played);
+        assert_eq!(episode.last_activity(), Some(200));
+
+        let round_trip = import(&export(podcasts.clone()).unwrap()).unwrap();
+        assert_eq!(round_trip, podcasts);
+
+        let newer = r#"{"version": 2, "exported": 0, "podcasts": []}"#;
+        assert!(import(newer).is_err());
+    }
+
+    #[test]
+    fn refuse_invalid_times() {
+        let file = |episode: &str| {
+            return format!(
+                r#"{{"version": 1, "exported": 0, "podcasts": [
+                    {{"title": "Show", "url": "http://example.com/feed", "last_checked": 0,
+                     "episodes": [{episode}]}}
+                ]}}"#
+            );
+        };
+        let valid = r#"{"title": "One", "url": "http://example.com/one.mp3", "pubdate": 100}"#;
+        assert!(import(&file(valid)).is_ok());
+        let invalid = [
+            r#"{"title": "One", "url": "http://example.com/one.mp3",
+                "pubdate": 9223372036854775807}"#,
+            r#"{"title": "One", "url": "http://example.com/one.mp3",
+                "notes": [{"text": "Good bit", "created": -9223372036854775807}]}"#,
+            r#"{"title": "One", "url": "http://example.com/one.mp3", "history": [
+                {"started_at": 50, "ended_at": 9223372036854775807,
+                 "listened": 150, "finished": true}
+            ]}"#,
+        ];
+        for episode in invalid.iter() {
+            assert!(import(&file(episode)).is_err());
+        }
+    }
+}
@@ -26,6 +26,7 @@ pub enum UserAction {
     Play,
     MarkPlayed,
     MarkAllPlayed,
+    Star,
 
     Download,
     DownloadAll,
@@ -33,9 +34,38 @@ pub enum UserAction {
     DeleteAll,
     Remove,
     RemoveAll,
+    Archive,
+    Undo,
+    Unhide,
 
     FilterPlayed,
     FilterDownloaded,
+    FilterStarred,
+    FilterTag,
+    FilterArchived,
+    FilterHidden,
+
+    PlayPause,
+    SeekForward,
+    SeekBackward,
+    SpeedUp,
+    SpeedDown,
+    StopPlayback,
+    SleepTimer,
+    SleepTimerCustom,
+
+    Enqueue,
+    EnqueueNext,
+    ShowQueue,
+    MoveUp,
+    MoveDown,
+
+    PodcastSettings,
+    EditTags,
+    ShowStats,
+    Search,
+    AddNote,
+    ShowNotes,
 
     Help,
     Quit,
@@ -84,14 +114,41 @@ impl Keybindings {
             (config.play, UserAction::Play),
             (config.mark_played, UserAction::MarkPlayed),
             (config.mark_all_played, UserAction::MarkAllPlayed),
+            (config.star, UserAction::Star),
             (config.download, UserAction::Download),
             (config.download_all, UserAction::DownloadAll),
             (config.delete, UserAction::Delete),
             (config.delete_all, UserAction::DeleteAll),
             (config.remove, UserAction::Remove),
             (config.remove_all, UserAction::RemoveAll),
+            (config.archive, UserAction::Archive),
+            (config.undo, UserAction::Undo),
+            (config.unhide, UserAction::Unhide),
             (config.filter_played, UserAction::FilterPlayed),
             (config.filter_downloaded, UserAction::FilterDownloaded),
+            (config.filter_starred, UserAction::FilterStarred),
+            (config.filter_tag, UserAction::FilterTag),
+            (config.filter_archived, UserAction::FilterArchived),
+            (config.filter_hidden, UserAction::FilterHidden),
+            (config.play_pause, UserAction::PlayPause),
+            (config.seek_forward, UserAction::SeekForward),
+            (config.seek_backward, UserAction::SeekBackward),
+            (config.speed_up, UserAction::SpeedUp),
+            (config.speed_down, UserAction::SpeedDown),
+            (config.stop_playback, UserAction::StopPlayback),
+            (config.sleep_timer, UserAction::SleepTimer),
+            (config.sleep_timer_custom, UserAction::SleepTimerCustom),
+            (config.enqueue, UserAction::Enqueue),
+            (config.enqueue_next, UserAction::EnqueueNext),
+            (config.show_queue, UserAction::ShowQueue),
+            (config.move_up, UserAction::MoveUp),
+            (config.move_down, UserAction::MoveDown),
+            (config.podcast_settings, UserAction::PodcastSettings),
+            (config.edit_tags, UserAction::EditTags),
+            (config.show_stats, UserAction::ShowStats),
+            (config.search, UserAction::Search),
+            (config.add_note, UserAction::AddNote),
+            (config.show_notes, UserAction::ShowNotes),
             (config.help, UserAction::Help),
             (config.quit, UserAction::Quit),
         ];
@@ -166,14 +223,41 @@ impl Keybindings {
             (UserAction::Play, vec!["Enter".to_string(), "p".to_string()]),
             (UserAction::MarkPlayed, vec!["m".to_string()]),
             (UserAction::MarkAllPlayed, vec!["M".to_string()]),
+            (UserAction::Star, vec!["*".to_string()]),
             (UserAction::Download, vec!["d".to_string()]),
             (UserAction::DownloadAll, vec!["D".to_string()]),
             (UserAction::Delete, vec!["x".to_string()]),
             (UserAction::DeleteAll, vec!["X".to_string()]),
             (UserAction::Remove, vec!["r".to_string()]),
             (UserAction::RemoveAll, vec!["R".to_string()]),
+            (UserAction::Archive, vec!["A".to_string()]),
+            (UserAction::Undo, vec!["U".to_string()]),
+            (UserAction::Unhide, vec!["H".to_string()]),
             (UserAction::FilterPlayed, vec!["1".to_string()]),
             (UserAction::FilterDownloaded, vec!["2".to_string()]),
+            (UserAction::FilterStarred, vec!["4".to_string()]),
+            (UserAction::FilterTag, vec!["3".to_string()]),
+            (UserAction::FilterArchived, vec!["5".to_string()]),
+            (UserAction::FilterHidden, vec!["6".to_string()]),
+            (UserAction::PlayPause, vec!["Space".to_string()]),
+            (UserAction::SeekForward, vec![".".to_string()]),
+            (UserAction::SeekBackward, vec![",".to_string()]),
+            (UserAction::SpeedUp, vec!["]".to_string()]),
+            (UserAction::SpeedDown, vec!["[".to_string()]),
+            (UserAction::StopPlayback, vec!["Q".to_string()]),
+            (UserAction::SleepTimer, vec!["z".to_string()]),
+            (UserAction::SleepTimerCustom, vec!["Z".to_string()]),
+            (UserAction::Enqueue, vec!["e".to_string()]),
+            (UserAction::EnqueueNext, vec!["E".to_string()]),
+            (UserAction::ShowQueue, vec!["u".to_string()]),
+            (UserAction::MoveUp, vec!["<".to_string()]),
+            (UserAction::MoveDown, vec![">".to_string()]),
+            (UserAction::PodcastSettings, vec!["o".to_string()]),
+            (UserAction::EditTags, vec!["#".to_string()]),
+            (UserAction::ShowStats, vec!["t".to_string()]),
+            (UserAction::Search, vec!["/".to_string()]),
+            (UserAction::AddNote, vec!["n".to_string()]),
+            (UserAction::ShowNotes, vec!["N".to_string()]),
             (UserAction::Help, vec!["?".to_string()]),
             (UserAction::Quit, vec!["q".to_string()]),
         ];
@@ -225,6 +309,8 @@ pub fn input_to_str(input: KeyEvent) -> Option<String> {
                 Some(format!("{ctrl}{alt}{shift}Enter"))
             } else if c == '\t' {
                 Some(format!("{ctrl}{alt}{shift}Tab"))
+            } else if c == ' ' {
+                Some(format!("{ctrl}{alt}{shift}Space"))
             } else {
                 // here we don't include "shift" because that will
                 // already be encoded in the character itself
@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
@@ -5,24 +6,35 @@ use std::process;
 use std::sync::mpsc;
 
 use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
 use clap::{Arg, Command};
 
 mod config;
 mod db;
+mod device;
 mod downloads;
 mod feeds;
+mod gpodder;
+mod json;
 mod keymap;
 mod main_controller;
+mod notes;
 mod opml;
 mod play_file;
+mod player;
+mod stats;
+#[cfg(test)]
+mod test_helpers;
 mod threadpool;
 mod types;
+mod undo;
 mod ui;
 
 use crate::config::Config;
-use crate::db::Database;
+use crate::db::{Database, InstanceLock};
 use crate::feeds::{FeedMsg, PodcastFeed};
 use crate::main_controller::{MainController, MainMessage};
+use crate::stats::Stats;
 use crate::threadpool::Threadpool;
 use crate::types::*;
 
@@ -45,16 +57,52 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 /// Connects to the sqlite database, then initiates a full sync of all
 /// podcasts. No UI is created for this, as the intention is to be used
 /// in a programmatic way (e.g., setting up a cron job to sync
-/// regularly.)
+/// regularly.) The `-t` option limits this to podcasts with a tag.
 ///
 /// *Import subcommand:*
 /// Reads in an OPML file and adds feeds to the database that do not
-/// already exist. If the `-r` option is used, the database is wiped
-/// first.
+/// already exist. With `--format json`, reads in a file from the export
+/// subcommand instead, and brings in the state of its podcasts and
+/// episodes; `-m` merges it with the database's. If the `-r` option is
+/// used, the database is backed up and wiped first.
+///
+/// *Backup subcommand:*
+/// Copies the database to a timestamped file in the `backups`
+/// directory next to it, or to a file given on the command line.
+///
+/// *Restore subcommand:*
+/// Replaces the database with a backup, after backing up the current
+/// one.
 ///
 /// *Export subcommand:*
 /// Connects to the sqlite database, and reads all podcasts into an OPML
 /// file, with the location specified from the command line arguments.
+/// With `--format json`, writes all episodes and what the user has done
+/// with them as well.
+///
+/// *Relocate subcommand:*
+/// Moves all downloaded episode files from the current download
+/// directory to a new one, and updates the filepaths stored in the
+/// database to match.
+///
+/// *Device-sync subcommand:*
+/// Copies unplayed, downloaded episodes to a directory (e.g., a mounted
+/// portable player), removes episodes from it that have since been
+/// played, and writes an M3U playlist of its contents.
+///
+/// *Stats subcommand:*
+/// Prints statistics about the user's listening history.
+///
+/// *Search subcommand:*
+/// Prints the episodes whose title, description, or podcast title
+/// match the search words.
+///
+/// *Starred subcommand:*
+/// Prints the episodes the user has starred, grouped by podcast.
+///
+/// *Notes subcommand:*
+/// Exports the user's notes and bookmarks on episodes as Markdown,
+/// either to a file or to stdout.
 fn main() -> Result<()> {
     // SETUP -----------------------------------------------------------
 
@@ -73,36 +121,147 @@ fn main() -> Result<()> {
             .value_name("FILE")
             .help("Sets a custom config file location. Can also be set with environment variable."))
         .subcommand(Command::new("sync")
-            .about("Syncs all podcasts in database")
+            .about("Syncs all podcasts in database, and the gpodder account if one is set up")
+            .arg(Arg::new("tag")
+                .short('t')
+                .long("tag")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("TAG")
+                .help("Only syncs podcasts with this tag. Can be used more than once. \
+                    Subscriptions and played episodes are still synced with the \
+                    gpodder server in full."))
             .arg(Arg::new("quiet")
                 .short('q')
                 .long("quiet")
                 .help("Suppresses output messages to stdout.")))
         .subcommand(Command::new("import")
-            .about("Imports podcasts from an OPML file")
+            .about("Imports podcasts from an OPML or JSON file")
             .arg(Arg::new("file")
                 .short('f')
                 .long("file")
                 .takes_value(true)
                 .value_name("FILE")
-                .help("Specifies the filepath to the OPML file to be imported. If this flag is not set, the command will read from stdin."))
+                .help("Specifies the filepath to the file to be imported. If this flag is not set, the command will read from stdin."))
+            .arg(Arg::new("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(["opml", "json"])
+                .default_value("opml")
+                .value_name("FORMAT")
+                .help("Format of the file: \"opml\" for a list of feeds, or \"json\" for a file from `shellcaster export --format json`, with played episodes, positions, and the like."))
             .arg(Arg::new("replace")
                 .short('r')
                 .long("replace")
                 .takes_value(false)
-                .help("If set, the contents of the OPML file will replace all existing data in the shellcaster database."))
+                .help("If set, the contents of the file will replace all existing data in the shellcaster database."))
+            .arg(Arg::new("merge")
+                .short('m')
+                .long("merge")
+                .takes_value(false)
+                .conflicts_with("replace")
+                .help("With --format json, keeps the played status and position of episodes played more recently here than in the file, and anything hidden, starred, or tagged here."))
             .arg(Arg::new("quiet")
                 .short('q')
                 .long("quiet")
                 .help("Suppresses output messages to stdout.")))
         .subcommand(Command::new("export")
-            .about("Exports podcasts to an OPML file")
+            .about("Exports podcasts to an OPML or JSON file")
+            .arg(Arg::new("file")
+                .short('f')
+                .long("file")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Specifies the filepath for where the file will be exported. If this flag is not set, the command will print to stdout."))
+            .arg(Arg::new("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(["opml", "json"])
+                .default_value("opml")
+                .value_name("FORMAT")
+                .help("Format of the file: \"opml\" for a list of feeds, or \"json\" for everything needed to move to another computer, including played episodes, positions, notes, and listening history.")))
+        .subcommand(Command::new("backup")
+            .about("Backs up the database")
+            .arg(Arg::new("file")
+                .short('f')
+                .long("file")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Specifies the filepath for the backup. If this flag is not set, the backup is saved in the `backups` directory next to the database, named with the current time.")))
+        .subcommand(Command::new("restore")
+            .about("Replaces the database with a backup")
+            .arg(Arg::new("file")
+                .required(true)
+                .value_name("FILE")
+                .help("The backup to restore. The current database is backed up first.")))
+        .subcommand(Command::new("relocate")
+            .about("Moves downloaded episodes to a new download directory")
+            .arg(Arg::new("to")
+                .short('t')
+                .long("to")
+                .takes_value(true)
+                .required(true)
+                .value_name("DIR")
+                .help("Specifies the new directory for downloaded episodes. Remember to also update `download_path` in config.toml afterwards."))
+            .arg(Arg::new("dry-run")
+                .short('n')
+                .long("dry-run")
+                .takes_value(false)
+                .help("Lists the files that would be moved, without moving them or changing the database."))
+            .arg(Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .help("Suppresses output messages to stdout.")))
+        .subcommand(Command::new("device-sync")
+            .about("Copies unplayed downloaded episodes to a portable player or mounted device")
+            .arg(Arg::new("to")
+                .short('t')
+                .long("to")
+                .takes_value(true)
+                .required(true)
+                .value_name("DIR")
+                .help("Specifies the directory where the device is mounted."))
+            .arg(Arg::new("podcast")
+                .short('p')
+                .long("podcast")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("TITLE")
+                .help("Only copies new episodes from the podcast with this title. Can be used more than once. If this flag is not set, all podcasts are synced."))
+            .arg(Arg::new("dry-run")
+                .short('n')
+                .long("dry-run")
+                .takes_value(false)
+                .help("Lists the files that would be copied and removed, without changing anything."))
+            .arg(Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .help("Suppresses output messages to stdout.")))
+        .subcommand(Command::new("stats")
+            .about("Shows statistics about your listening history"))
+        .subcommand(Command::new("search")
+            .about("Searches episodes of all podcasts by title or description")
+            .arg(Arg::new("query")
+                .required(true)
+                .multiple_values(true)
+                .value_name("QUERY")
+                .help("Words to search for. Episodes must match all of them; each word also matches longer words starting with it."))
+            .arg(Arg::new("hidden")
+                .long("hidden")
+                .help("Includes episodes that have been removed from the list, marked with [H].")))
+        .subcommand(Command::new("starred")
+            .about("Lists starred episodes")
+            .arg(Arg::new("hidden")
+                .long("hidden")
+                .help("Includes episodes that have been removed from the list, marked with [H].")))
+        .subcommand(Command::new("notes")
+            .about("Exports notes and bookmarks on episodes to a Markdown file")
             .arg(Arg::new("file")
                 .short('f')
                 .long("file")
                 .takes_value(true)
                 .value_name("FILE")
-                .help("Specifies the filepath for where the OPML file will be exported. If this flag is not set, the command will print to stdout.")))
+                .help("Specifies the filepath for where the Markdown file will be exported. If this flag is not set, the command will print to stdout.")))
         .get_matches();
 
     // figure out where config file is located -- either specified from
@@ -131,8 +290,40 @@ fn main() -> Result<()> {
         // EXPORT SUBCOMMAND --------------------------------------------
         Some(("export", sub_args)) => export(&db_path, sub_args),
 
+        // BACKUP SUBCOMMAND --------------------------------------------
+        Some(("backup", sub_args)) => backup(&db_path, sub_args),
+
+        // RESTORE SUBCOMMAND -------------------------------------------
+        Some(("restore", sub_args)) => {
+            restore(&db_path, config, Path::new(sub_args.value_of("file").unwrap()))
+        }
+
+        // RELOCATE SUBCOMMAND ------------------------------------------
+        Some(("relocate", sub_args)) => relocate(&db_path, config, sub_args),
+
+        // DEVICE-SYNC SUBCOMMAND ---------------------------------------
+        Some(("device-sync", sub_args)) => device_sync(&db_path, sub_args),
+
+        // STATS SUBCOMMAND ---------------------------------------------
+        Some(("stats", _)) => stats(&db_path),
+
+        // SEARCH SUBCOMMAND --------------------------------------------
+        Some(("search", sub_args)) => {
+            let query: Vec<&str> = sub_args.values_of("query").unwrap().collect();
+            search(&db_path, &query.join(" "), sub_args.is_present("hidden"))
+        }
+
+        // STARRED SUBCOMMAND -------------------------------------------
+        Some(("starred", sub_args)) => starred(&db_path, sub_args.is_present("hidden")),
+
+        // NOTES SUBCOMMAND ---------------------------------------------
+        Some(("notes", sub_args)) => export_notes(&db_path, sub_args),
+
         // MAIN COMMAND -------------------------------------------------
         _ => {
+            // only one copy of the UI at a time; other subcommands can
+            // still run alongside it
+            let _lock = InstanceLock::acquire(&db_path)?;
             let mut main_ctrl = MainController::new(config, &db_path)?;
 
             main_ctrl.loop_msgs(); // main loop
@@ -172,12 +363,49 @@ fn get_config_path(config: Option<&str>) -> Option<PathBuf> {
 
 
 /// Synchronizes RSS feed data for all podcasts, without setting up a UI.
+/// If any tags are given, only podcasts with one of those tags are
+/// synced.
 fn sync_podcasts(db_path: &Path, config: Config, args: &clap::ArgMatches) -> Result<()> {
     let db_inst = Database::connect(db_path)?;
-    let podcast_list = db_inst.get_podcasts()?;
+    let quiet = args.is_present("quiet");
+    let mut failure = false;
 
-    if podcast_list.is_empty() {
-        if !args.is_present("quiet") {
+    // subscriptions go first, so podcasts archived on other devices are
+    // not synced, and new ones are added along with the rest
+    let gpodder_client = config.gpodder.as_ref().map(gpodder::Client::new);
+    let mut new_feeds = Vec::new();
+    if let Some(client) = &gpodder_client {
+        match gpodder::sync_subscriptions(client, &db_inst) {
+            Ok(report) => {
+                if !quiet {
+                    for title in report.archived.iter() {
+                        println!("Archived {title}");
+                    }
+                    for title in report.unarchived.iter() {
+                        println!("Unarchived {title}");
+                    }
+                    if report.uploaded > 0 {
+                        println!("Uploaded {} subscription changes", report.uploaded);
+                    }
+                }
+                new_feeds = report.new_feeds;
+            }
+            Err(err) => {
+                failure = true;
+                eprintln!("Error syncing subscriptions: {err}");
+            }
+        }
+    }
+
+    let mut podcast_list = db_inst.get_podcast_summaries(false)?;
+    if let Some(tags) = args.values_of("tag") {
+        let tags: Vec<&str> = tags.collect();
+        podcast_list.retain(|pod| tags.iter().any(|tag| pod.has_tag(tag)));
+    }
+    podcast_list.retain(|pod| !pod.archived);
+
+    if podcast_list.is_empty() && new_feeds.is_empty() && gpodder_client.is_none() {
+        if !quiet {
             println!("No podcasts to sync.");
         }
         return Ok(());
@@ -190,17 +418,40 @@ fn sync_podcasts(db_path: &Path, config: Config, args: &clap::ArgMatches) -> Res
         let feed = PodcastFeed::new(Some(pod.id), pod.url.clone(), Some(pod.title.clone()));
         feeds::check_feed(feed, config.max_retries, &threadpool, tx_to_main.clone());
     }
+    for url in new_feeds.iter() {
+        let feed = PodcastFeed::new(None, url.clone(), None);
+        feeds::check_feed(feed, config.max_retries, &threadpool, tx_to_main.clone());
+    }
 
+    let num_feeds = podcast_list.len() + new_feeds.len();
     let mut msg_counter: usize = 0;
-    let mut failure = false;
-    while let Some(message) = rx_to_main.iter().next() {
+    while msg_counter < num_feeds {
+        let message = match rx_to_main.recv() {
+            Ok(message) => message,
+            Err(_) => break,
+        };
         match message {
+            Message::Feed(FeedMsg::NewData(pod)) => {
+                let title = pod.title.clone();
+                match db_inst.insert_podcast(pod) {
+                    Ok(_) => {
+                        if !quiet {
+                            println!("Added {title}");
+                        }
+                    }
+                    Err(_err) => {
+                        failure = true;
+                        eprintln!("Error adding {title}");
+                    }
+                }
+            }
+
             Message::Feed(FeedMsg::SyncData((pod_id, pod))) => {
                 let title = pod.title.clone();
                 let db_result = db_inst.update_podcast(pod_id, pod);
                 match db_result {
                     Ok(_) => {
-                        if !args.is_present("quiet") {
+                        if !quiet {
                             println!("Synced {title}");
                         }
                     }
@@ -222,14 +473,30 @@ fn sync_podcasts(db_path: &Path, config: Config, args: &clap::ArgMatches) -> Res
         }
 
         msg_counter += 1;
-        if msg_counter >= podcast_list.len() {
-            break;
+    }
+
+    // episode actions go last, so they can apply to new episodes
+    if let Some(client) = &gpodder_client {
+        match gpodder::sync_episode_actions(client, &db_inst, config.played_threshold) {
+            Ok(report) => {
+                if !quiet && (report.applied > 0 || report.skipped > 0 || report.uploaded > 0)
+                {
+                    println!(
+                        "Synced episode actions: {} applied, {} skipped, {} uploaded",
+                        report.applied, report.skipped, report.uploaded
+                    );
+                }
+            }
+            Err(err) => {
+                failure = true;
+                eprintln!("Error syncing episode actions: {err}");
+            }
         }
     }
 
     if failure {
         return Err(anyhow!("Process finished with errors."));
-    } else if !args.is_present("quiet") {
+    } else if !quiet {
         println!("Sync successful.");
     }
     return Ok(());
@@ -241,25 +508,31 @@ fn sync_podcasts(db_path: &Path, config: Config, args: &clap::ArgMatches) -> Res
 /// existing data in the database.
 fn import(db_path: &Path, config: Config, args: &clap::ArgMatches) -> Result<()> {
     // read from file or from stdin
-    let xml = match args.value_of("file") {
+    let file_contents = match args.value_of("file") {
         Some(filepath) => {
             let mut f = File::open(filepath)
-                .with_context(|| format!("Could not open OPML file: {filepath}"))?;
+                .with_context(|| format!("Could not open import file: {filepath}"))?;
             let mut contents = String::new();
             f.read_to_string(&mut contents)
-                .with_context(|| format!("Failed to read from OPML file: {filepath}"))?;
+                .with_context(|| format!("Failed to read from import file: {filepath}"))?;
             contents
         }
         None => {
             let mut contents = String::new();
             std::io::stdin()
                 .read_to_string(&mut contents)
-                .with_context(|| "Failed to read OPML file from stdin")?;
+                .with_context(|| "Failed to read import file from stdin")?;
             contents
         }
     };
 
-    let mut podcast_list = opml::import(xml).with_context(|| {
+    if args.value_of("format") == Some("json") {
+        return import_json(db_path, config, args, &file_contents);
+    } else if args.is_present("merge") {
+        return Err(anyhow!("--merge only works with --format json"));
+    }
+
+    let mut podcast_list = opml::import(file_contents).with_context(|| {
         "Could not properly parse OPML file -- file may be formatted improperly or corrupted."
     })?;
 
@@ -271,27 +544,33 @@ fn import(db_path: &Path, config: Config, args: &clap::ArgMatches) -> Result<()>
     }
 
     let db_inst = Database::connect(db_path)?;
-
-    // delete database if we are replacing the data
-    if args.is_present("replace") {
-        db_inst
-            .clear_db()
-            .with_context(|| "Error clearing database")?;
+    let _lock = if args.is_present("replace") {
+        Some(replace_database(&db_inst, db_path, &config, args)?)
     } else {
-        let old_podcasts = db_inst.get_podcasts()?;
+        None
+    };
+    if !args.is_present("replace") {
+        let old_podcasts = db_inst.get_podcast_summaries(false)?;
+
+        // importing an archived podcast brings it back
+        for op in old_podcasts.iter().filter(|op| op.archived) {
+            if podcast_list.iter().any(|pod| pod.url == op.url) {
+                db_inst.set_archived(op.id, false)?;
+                if !args.is_present("quiet") {
+                    println!("Unarchived {}", op.title);
+                }
+            }
+        }
 
         // if URL is already in database, remove it from import
-        podcast_list = podcast_list
-            .into_iter()
-            .filter(|pod| {
-                for op in &old_podcasts {
-                    if pod.url == op.url {
-                        return false;
-                    }
+        podcast_list.retain(|pod| {
+            for op in &old_podcasts {
+                if pod.url == op.url {
+                    return false;
                 }
-                return true;
-            })
-            .collect();
+            }
+            return true;
+        });
     }
 
     // check again, now that we may have removed feeds after looking at
@@ -354,6 +633,17 @@ fn import(db_path: &Path, config: Config, args: &clap::ArgMatches) -> Result<()>
         }
     }
 
+    // tags from the OPML file are given to the podcasts once they have
+    // been added
+    for pod in db_inst.get_podcast_summaries(false)?.iter() {
+        if let Some(feed) = podcast_list.iter().find(|feed| feed.url == pod.url) {
+            if !feed.tags.is_empty() && db_inst.set_tags(pod.id, &feed.tags).is_err() {
+                failure = true;
+                eprintln!("Error adding tags to {}", pod.title);
+            }
+        }
+    }
+
     if failure {
         return Err(anyhow!("Process finished with errors."));
     } else if !args.is_present("quiet") {
@@ -363,28 +653,639 @@ fn import(db_path: &Path, config: Config, args: &clap::ArgMatches) -> Result<()>
 }
 
 
-/// Exports all podcasts to OPML format, either printing to stdout or
-/// exporting to a file.
+/// Imports the state of podcasts from a file written by `shellcaster
+/// export --format json`. Unlike OPML files, these already hold the
+/// episodes, so no feeds are fetched.
+fn import_json(
+    db_path: &Path,
+    config: Config,
+    args: &clap::ArgMatches,
+    contents: &str,
+) -> Result<()> {
+    let podcasts = json::import(contents).with_context(|| {
+        "Could not properly parse JSON file -- file may be formatted improperly or corrupted."
+    })?;
+    if podcasts.is_empty() {
+        if !args.is_present("quiet") {
+            println!("No podcasts to import.");
+        }
+        return Ok(());
+    }
+
+    let db_inst = Database::connect(db_path)?;
+    let replace = args.is_present("replace");
+    let _lock = if replace {
+        Some(back_up_database(&db_inst, db_path, &config, args)?)
+    } else {
+        None
+    };
+    let report = db_inst
+        .import_state(&podcasts, args.is_present("merge"), replace)
+        .with_context(|| "Could not import the file; nothing has been changed")?;
+    if !args.is_present("quiet") {
+        println!(
+            "Import successful: added {} podcasts and {} episodes, and updated {} episodes.",
+            report.podcasts_added, report.episodes_added, report.episodes_updated
+        );
+    }
+    return Ok(());
+}
+
+/// Wipes the database before importing with `--replace`, keeping a
+/// backup in case this was a mistake. Returns the lock that keeps the
+/// UI from opening until the import is done.
+fn replace_database(
+    db_inst: &Database,
+    db_path: &Path,
+    config: &Config,
+    args: &clap::ArgMatches,
+) -> Result<InstanceLock> {
+    let lock = back_up_database(db_inst, db_path, config, args)?;
+    db_inst
+        .clear_db()
+        .with_context(|| "Error clearing database")?;
+    return Ok(lock);
+}
+
+/// Backs up the database before its podcasts are replaced, making sure
+/// shellcaster is not running. The lock that is returned keeps it from
+/// being started until the podcasts have been replaced.
+fn back_up_database(
+    db_inst: &Database,
+    db_path: &Path,
+    config: &Config,
+    args: &clap::ArgMatches,
+) -> Result<InstanceLock> {
+    let lock = InstanceLock::acquire(db_path)
+        .with_context(|| "Please quit shellcaster before replacing its podcasts")?;
+    let backup = db_inst
+        .auto_backup(config.auto_backups)
+        .with_context(|| "Could not back up the database, so it has not been replaced")?;
+    if let Some(backup) = backup {
+        if !args.is_present("quiet") {
+            println!("Backed up the database to {}", backup.to_string_lossy());
+        }
+    }
+    return Ok(lock);
+}
+
+/// Exports all podcasts to OPML format, or with everything about them to
+/// JSON format, either printing to stdout or exporting to a file.
 fn export(db_path: &Path, args: &clap::ArgMatches) -> Result<()> {
     let db_inst = Database::connect(db_path)?;
-    let podcast_list = db_inst.get_podcasts()?;
-    let opml = opml::export(podcast_list);
+    let contents = if args.value_of("format") == Some("json") {
+        json::export(db_inst.export_state()?)?
+    } else {
+        let podcast_list = db_inst.get_podcast_summaries(false)?;
+        let opml = opml::export(podcast_list);
+        opml.to_string()
+            .map_err(|err| anyhow!(err))
+            .with_context(|| "Could not create OPML format")?
+    };
+
+    match args.value_of("file") {
+        // export to file
+        Some(file) => {
+            let mut dst = File::create(file)
+                .with_context(|| format!("Could not create output file: {file}"))?;
+            dst.write_all(contents.as_bytes())
+                .with_context(|| format!("Could not copy data to output file: {file}"))?;
+        }
+        // print to stdout
+        None => println!("{contents}"),
+    }
+    return Ok(());
+}
 
-    let xml = opml
-        .to_string()
-        .map_err(|err| anyhow!(err))
-        .with_context(|| "Could not create OPML format")?;
+
+/// Backs up the database, to the given file or to a timestamped file
+/// in the `backups` directory.
+fn backup(db_path: &Path, args: &clap::ArgMatches) -> Result<()> {
+    let db_inst = Database::connect(db_path)?;
+    let dest = match args.value_of("file") {
+        Some(file) => PathBuf::from(file),
+        None => db_inst.backup_path(),
+    };
+    db_inst.backup(&dest)?;
+    println!("Backed up the database to {}", dest.to_string_lossy());
+    return Ok(());
+}
+
+/// Replaces the database with a backup. Shellcaster should not be
+/// running while this happens.
+fn restore(db_path: &Path, config: Config, backup: &Path) -> Result<()> {
+    let _lock = InstanceLock::acquire(db_path)
+        .with_context(|| "Please quit shellcaster before restoring a backup")?;
+    let saved = Database::restore(db_path, backup, config.auto_backups)?;
+    println!("Restored the database from {}", backup.to_string_lossy());
+    if let Some(saved) = saved {
+        println!("The previous database was backed up to {}", saved.to_string_lossy());
+    }
+    return Ok(());
+}
+
+
+/// Moves all downloaded episode files that live inside the configured
+/// download directory to a new directory, keeping the same
+/// subdirectory structure, and then rewrites the filepaths stored in
+/// the database. Files are moved first; if any file cannot be moved,
+/// the files that were already moved are put back and the database is
+/// left untouched. If a file is missing from the old location but
+/// already exists at the new one (e.g., the user moved the directory
+/// by hand), only the database entry is updated. Shellcaster should
+/// not be running while this happens, unless it is a dry run.
+fn relocate(db_path: &Path, config: Config, args: &clap::ArgMatches) -> Result<()> {
+    let quiet = args.is_present("quiet");
+    let dry_run = args.is_present("dry-run");
+
+    let to = args.value_of("to").expect("Missing required argument");
+    let new_dir = match shellexpand::full(to) {
+        Ok(realpath) => PathBuf::from(realpath.as_ref()),
+        Err(err) => {
+            return Err(anyhow!(
+                "Could not parse environment variable {} in {to}. Reason: {}",
+                err.var_name,
+                err.cause
+            ))
+        }
+    };
+    let old_dir = config.download_path;
+    if new_dir == old_dir {
+        return Err(anyhow!(
+            "New directory is the same as the current download directory."
+        ));
+    }
+
+    // the UI could download, delete or trash files while they are moved;
+    // a dry run changes nothing, so it can run alongside it
+    let _lock = if dry_run {
+        None
+    } else {
+        Some(
+            InstanceLock::acquire(db_path)
+                .with_context(|| "Please quit shellcaster before relocating downloads")?,
+        )
+    };
+    let db_inst = Database::connect(db_path)?;
+    let relocated = relocate_files(&db_inst, &old_dir, &new_dir, dry_run, quiet)?;
+    if relocated > 0 && !dry_run && !quiet {
+        println!(
+            "Remember to set `download_path` in config.toml to {}",
+            new_dir.to_string_lossy()
+        );
+    }
+    return Ok(());
+}
+
+/// Moves the downloaded files from `old_dir` to `new_dir` and updates
+/// their paths in the database, as described for `relocate()`. With
+/// `dry_run`, nothing is changed. Returns the number of files that were
+/// (or would be) relocated.
+fn relocate_files(
+    db_inst: &Database,
+    old_dir: &Path,
+    new_dir: &Path,
+    dry_run: bool,
+    quiet: bool,
+) -> Result<usize> {
+    let files = db_inst.get_files()?;
+
+    // figure out where each file needs to go
+    let mut to_move = Vec::new();
+    let mut to_update = Vec::new();
+    for (ep_id, old_path) in files.into_iter() {
+        // deleted files waiting in the trash are not moved along
+        if undo::in_trash(&old_path) {
+            continue;
+        }
+        let rel_path = match old_path.strip_prefix(old_dir) {
+            Ok(rel) => rel.to_path_buf(),
+            Err(_) => {
+                if !quiet {
+                    println!(
+                        "Skipping file outside of download directory: {}",
+                        old_path.to_string_lossy()
+                    );
+                }
+                continue;
+            }
+        };
+        let new_path = new_dir.join(rel_path);
+
+        if old_path.exists() {
+            if new_path.exists() {
+                return Err(anyhow!(
+                    "File already exists at destination: {}",
+                    new_path.to_string_lossy()
+                ));
+            }
+            to_move.push((old_path.clone(), new_path.clone()));
+        } else if !new_path.exists() {
+            if !quiet {
+                println!("Skipping missing file: {}", old_path.to_string_lossy());
+            }
+            continue;
+        }
+        if !quiet {
+            println!(
+                "{} -> {}",
+                old_path.to_string_lossy(),
+                new_path.to_string_lossy()
+            );
+        }
+        to_update.push((ep_id, new_path));
+    }
+
+    if to_update.is_empty() {
+        if !quiet {
+            println!("No files to relocate.");
+        }
+        return Ok(0);
+    }
+    if dry_run {
+        if !quiet {
+            println!("Dry run: {} files would be relocated.", to_update.len());
+        }
+        return Ok(to_update.len());
+    }
+
+    let mut moved = Vec::new();
+    for (old_path, new_path) in to_move.iter() {
+        if let Err(err) = undo::move_file(old_path, new_path) {
+            restore_files(&moved);
+            return Err(err).with_context(|| {
+                format!(
+                    "Could not move {}. No files were relocated.",
+                    old_path.to_string_lossy()
+                )
+            });
+        }
+        moved.push((old_path.clone(), new_path.clone()));
+    }
+
+    if let Err(err) = db_inst.update_file_paths(&to_update) {
+        restore_files(&moved);
+        return Err(err).with_context(|| "Could not update database. No files were relocated.");
+    }
+
+    if !quiet {
+        println!("Relocated {} files.", to_update.len());
+    }
+    return Ok(to_update.len());
+}
+
+/// Synchronizes episodes with a portable player or other mounted
+/// device. Unplayed, downloaded episodes from the selected podcasts
+/// are copied over, episodes that have been played since the last run
+/// are deleted from the device, and a playlist of everything on the
+/// device is written to its root directory. The database keeps track
+/// of which episodes are on which device, so repeat runs only copy
+/// what is new.
+fn device_sync(db_path: &Path, args: &clap::ArgMatches) -> Result<()> {
+    let quiet = args.is_present("quiet");
+    let dry_run = args.is_present("dry-run");
+
+    let to = args.value_of("to").expect("Missing required argument");
+    let device_dir = match shellexpand::full(to) {
+        Ok(realpath) => PathBuf::from(realpath.as_ref()),
+        Err(err) => {
+            return Err(anyhow!(
+                "Could not parse environment variable {} in {to}. Reason: {}",
+                err.var_name,
+                err.cause
+            ))
+        }
+    };
+    // we don't create this directory, since it not existing probably
+    // means the device is not mounted
+    let device_dir = device_dir
+        .canonicalize()
+        .with_context(|| format!("Could not find device directory: {to}"))?;
+    let device_key = device_dir.to_string_lossy().to_string();
+
+    let db_inst = Database::connect(db_path)?;
+    let podcast_list = db_inst.get_podcasts(false)?;
+
+    let selected: HashSet<i64> = match args.values_of("podcast") {
+        Some(titles) => {
+            let mut selected = HashSet::new();
+            for title in titles {
+                let title_lower = title.to_lowercase();
+                match podcast_list
+                    .iter()
+                    .find(|pod| pod.title.to_lowercase() == title_lower)
+                {
+                    Some(pod) => selected.insert(pod.id),
+                    None => return Err(anyhow!("No podcast found with title: {title}")),
+                };
+            }
+            selected
+        }
+        None => podcast_list.iter().map(|pod| pod.id).collect(),
+    };
+
+    let tracked = db_inst.get_device_files(&device_key)?;
+    let plan = device::plan(&podcast_list, &selected, &tracked, &device_dir);
+
+    if dry_run {
+        if !quiet {
+            for (_, dest) in plan.remove.iter() {
+                println!("Remove {}", dest.to_string_lossy());
+            }
+            for entry in plan.copy.iter() {
+                println!("Copy {}", entry.dest.to_string_lossy());
+            }
+            println!(
+                "Dry run: {} episodes would be copied, {} removed.",
+                plan.copy.len(),
+                plan.remove.len()
+            );
+        }
+        return Ok(());
+    }
+
+    let mut failure = false;
+    for (ep_id, dest) in plan.remove.iter() {
+        let path = device_dir.join(dest);
+        match std::fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                failure = true;
+                eprintln!("Error removing {}", dest.to_string_lossy());
+                continue;
+            }
+            _ => (),
+        }
+        // clean up the podcast directory if it is now empty; this
+        // fails harmlessly if it is not
+        if let Some(parent) = path.parent() {
+            if parent != device_dir {
+                let _ = std::fs::remove_dir(parent);
+            }
+        }
+        db_inst.remove_device_file(&device_key, *ep_id)?;
+        if !quiet {
+            println!("Removed {}", dest.to_string_lossy());
+        }
+    }
+
+    let mut failed_copies = HashSet::new();
+    for entry in plan.copy.iter() {
+        let source = entry.source.as_ref().expect("Episode is not downloaded");
+        let path = device_dir.join(&entry.dest);
+        let copied = match path.parent() {
+            Some(parent) => std::fs::create_dir_all(parent),
+            None => Ok(()),
+        }
+        .and_then(|_| std::fs::copy(source, &path));
+
+        match copied {
+            Ok(_) => {
+                db_inst.insert_device_file(&device_key, entry.ep_id, &entry.dest)?;
+                if !quiet {
+                    println!("Copied {}", entry.dest.to_string_lossy());
+                }
+            }
+            Err(_) => {
+                failure = true;
+                failed_copies.insert(entry.ep_id);
+                eprintln!("Error copying {}", source.to_string_lossy());
+            }
+        }
+    }
+
+    let entries: Vec<device::DeviceEntry> = plan
+        .playlist
+        .into_iter()
+        .filter(|entry| !failed_copies.contains(&entry.ep_id))
+        .collect();
+    let mut playlist_path = device_dir.clone();
+    playlist_path.push(device::PLAYLIST_NAME);
+    let mut dst = File::create(&playlist_path).with_context(|| {
+        format!(
+            "Could not create playlist file: {}",
+            playlist_path.to_string_lossy()
+        )
+    })?;
+    dst.write_all(device::playlist(&entries).as_bytes())
+        .with_context(|| "Could not write playlist file")?;
+
+    if failure {
+        return Err(anyhow!("Process finished with errors."));
+    } else if !quiet {
+        println!("Device sync successful: {} episodes on device.", entries.len());
+    }
+    return Ok(());
+}
+
+/// Prints statistics about the user's listening history: time spent
+/// listening per week and per podcast, episodes finished, the size of
+/// the backlog, and the podcasts that haven't been played in longest.
+fn stats(db_path: &Path) -> Result<()> {
+    let db_inst = Database::connect(db_path)?;
+    let podcast_list = db_inst.get_podcasts(false)?;
+    let history = db_inst.get_history()?;
+    let played = db_inst.get_played_episodes()?;
+    let last_played = db_inst.get_last_played()?;
+
+    let now = Utc::now();
+    let stats = Stats::new(&podcast_list, &history, &played, &last_played, now);
+    for line in stats.format(now) {
+        println!("{line}");
+    }
+    return Ok(());
+}
+
+/// Moves already-relocated files back to where they came from, after
+/// a relocation has failed partway through.
+fn restore_files(moved: &[(PathBuf, PathBuf)]) {
+    for (old_path, new_path) in moved.iter().rev() {
+        if undo::move_file(new_path, old_path).is_err() {
+            eprintln!(
+                "Could not move {} back to {}",
+                new_path.to_string_lossy(),
+                old_path.to_string_lossy()
+            );
+        }
+    }
+}
+
+/// Prints the episodes matching the search query, best matches first.
+/// Hidden episodes are only included if `include_hidden` is set.
+fn search(db_path: &Path, query: &str, include_hidden: bool) -> Result<()> {
+    let db_inst = Database::connect(db_path)?;
+    let results = db_inst.search(query, crate::config::SEARCH_LIMIT, include_hidden)?;
+    if results.is_empty() {
+        println!("No episodes found.");
+    }
+    for result in results.iter() {
+        println!("{}", result.format());
+    }
+    return Ok(());
+}
+
+/// Prints the starred episodes of each podcast, newest first, with
+/// their publication dates and the paths of any downloaded files.
+/// Hidden episodes are only listed if `include_hidden` is set.
+fn starred(db_path: &Path, include_hidden: bool) -> Result<()> {
+    let db_inst = Database::connect(db_path)?;
+    let podcast_list = db_inst.get_podcasts(include_hidden)?;
+
+    let mut any_starred = false;
+    for pod in podcast_list.iter() {
+        let episodes = pod.episodes.map(
+            |ep| if ep.starred { Some(ep.clone()) } else { None },
+            false,
+        );
+        let episodes: Vec<Episode> = episodes.into_iter().flatten().collect();
+        if episodes.is_empty() {
+            continue;
+        }
+        any_starred = true;
+        println!("{}", pod.title);
+        for ep in episodes.iter() {
+            let date = match ep.pubdate {
+                Some(pd) => pd.format("%F").to_string(),
+                None => "----------".to_string(),
+            };
+            let hidden = if ep.hidden { "[H] " } else { "" };
+            match &ep.path {
+                Some(path) => {
+                    println!("  {date}  {hidden}{} [{}]", ep.title, path.to_string_lossy())
+                }
+                None => println!("  {date}  {hidden}{}", ep.title),
+            }
+        }
+    }
+    if !any_starred {
+        println!("No starred episodes.");
+    }
+    return Ok(());
+}
+
+/// Exports the notes on all episodes, including removed ones, as
+/// Markdown, either printing to stdout or writing to a file.
+fn export_notes(db_path: &Path, args: &clap::ArgMatches) -> Result<()> {
+    let db_inst = Database::connect(db_path)?;
+    let mut podcasts = Vec::new();
+    for pod in db_inst.get_podcast_summaries(false)? {
+        let episodes = db_inst.get_episodes(pod.id, true)?;
+        podcasts.push((pod.title, episodes));
+    }
+    let markdown = notes::to_markdown(&podcasts);
 
     match args.value_of("file") {
         // export to file
         Some(file) => {
             let mut dst = File::create(file)
                 .with_context(|| format!("Could not create output file: {file}"))?;
-            dst.write_all(xml.as_bytes())
-                .with_context(|| format!("Could not copy OPML data to output file: {file}"))?;
+            dst.write_all(markdown.as_bytes())
+                .with_context(|| format!("Could not copy notes to output file: {file}"))?;
         }
         // print to stdout
-        None => println!("{xml}"),
+        None => print!("{markdown}"),
     }
     return Ok(());
 }
+
+
+// TESTS -----------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{make_feed, temp_dir};
+
+    /// Sets up a database with one downloaded file per episode, named
+    /// after the episode, under `old_dir`. Only the files in `present`
+    /// are actually written to disk. Returns the episode ids by title.
+    fn setup_files(db: &Database, old_dir: &Path, present: &[&str]) -> Vec<(String, i64)> {
+        let result = db
+            .insert_podcast(make_feed("Show", &[("One", ""), ("Two", ""), ("Three", "")]))
+            .unwrap();
+        let mut ids = Vec::new();
+        for ep in result.added.iter() {
+            let path = old_dir.join("Show").join(format!("{}.mp3", ep.title));
+            if present.contains(&ep.title.as_str()) {
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(&path, ep.title.as_bytes()).unwrap();
+            }
+            db.insert_file(ep.id, &path).unwrap();
+            ids.push((ep.title.clone(), ep.id));
+        }
+        return ids;
+    }
+
+    fn file_path(db: &Database, ep_id: i64) -> PathBuf {
+        return db
+            .get_files()
+            .unwrap()
+            .into_iter()
+            .find(|(id, _)| *id == ep_id)
+            .unwrap()
+            .1;
+    }
+
+    #[test]
+    fn relocate_moves_files() {
+        let dir = temp_dir("relocate-move");
+        let old_dir = dir.join("old");
+        let new_dir = dir.join("new");
+        let db = Database::connect(&dir).unwrap();
+        let ids = setup_files(&db, &old_dir, &["One", "Two", "Three"]);
+
+        let relocated = relocate_files(&db, &old_dir, &new_dir, false, true).unwrap();
+        assert_eq!(relocated, 3);
+        for (title, ep_id) in ids.iter() {
+            let new_path = new_dir.join("Show").join(format!("{title}.mp3"));
+            assert_eq!(file_path(&db, *ep_id), new_path);
+            assert_eq!(std::fs::read(&new_path).unwrap(), title.as_bytes());
+            assert!(!old_dir.join("Show").join(format!("{title}.mp3")).exists());
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn relocate_skips_missing_files() {
+        let dir = temp_dir("relocate-missing");
+        let old_dir = dir.join("old");
+        let new_dir = dir.join("new");
+        let db = Database::connect(&dir).unwrap();
+        let ids = setup_files(&db, &old_dir, &["One"]);
+        // moved by hand already, so only the database is updated
+        let moved = new_dir.join("Show").join("Two.mp3");
+        std::fs::create_dir_all(moved.parent().unwrap()).unwrap();
+        std::fs::write(&moved, b"Two").unwrap();
+
+        let relocated = relocate_files(&db, &old_dir, &new_dir, false, true).unwrap();
+        assert_eq!(relocated, 2);
+        for (title, ep_id) in ids.iter() {
+            let path = file_path(&db, *ep_id);
+            if title == "Three" {
+                assert_eq!(path, old_dir.join("Show").join("Three.mp3"));
+                assert!(!new_dir.join("Show").join("Three.mp3").exists());
+            } else {
+                assert_eq!(path, new_dir.join("Show").join(format!("{title}.mp3")));
+                assert!(path.exists());
+            }
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn relocate_dry_run() {
+        let dir = temp_dir("relocate-dry-run");
+        let old_dir = dir.join("old");
+        let new_dir = dir.join("new");
+        let db = Database::connect(&dir).unwrap();
+        let ids = setup_files(&db, &old_dir, &["One", "Two"]);
+
+        let relocated = relocate_files(&db, &old_dir, &new_dir, true, true).unwrap();
+        assert_eq!(relocated, 2);
+        assert!(!new_dir.exists());
+        for (title, ep_id) in ids.iter() {
+            let old_path = old_dir.join("Show").join(format!("{title}.mp3"));
+            assert_eq!(file_path(&db, *ep_id), old_path);
+            assert_eq!(old_path.exists(), title != "Three");
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
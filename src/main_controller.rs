@@ -1,19 +1,31 @@
 use anyhow::Result;
 use std::collections::HashSet;
-use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use ahash::AHashMap;
+use chrono::{DateTime, Utc};
 use sanitize_filename::{sanitize_with_options, Options};
 
-use crate::config::{Config, DownloadNewEpisodes};
+use crate::config::{
+    Config, DownloadNewEpisodes, MarkPlayed, PlayerIntegration, SleepAction,
+    POSITION_SAVE_INTERVAL,
+};
 use crate::db::{Database, SyncResult};
 use crate::downloads::{self, DownloadMsg, EpData};
 use crate::feeds::{self, FeedMsg, PodcastFeed};
-use crate::play_file;
+use crate::gpodder::ActionKind;
+use crate::play_file::{self, PlayData};
+use crate::player::{
+    MpvPlayer, NowPlaying, PlaybackEnd, PlayerCommand, PlayerMsg, SleepTimer,
+};
+use crate::stats::{self, Stats};
 use crate::threadpool::Threadpool;
 use crate::types::*;
 use crate::ui::{Ui, UiMsg};
+use crate::undo::{self, TrashedFile, UndoAction, UNDO_LIMIT};
 
 /// Enum used for communicating with other threads.
 #[allow(clippy::enum_variant_names)]
@@ -24,6 +36,11 @@ pub enum MainMessage {
     UiSpawnPersistentNotif(String, bool),
     UiClearPersistentNotif,
     UiSpawnDownloadPopup(Vec<NewEpisode>, bool),
+    UiUpdateNowPlaying(Option<NowPlaying>),
+    UiUpdateQueue(Vec<QueueItem>),
+    UiUpdateSleepTimer(Option<SleepTimer>),
+    UiShowStats(Stats),
+    UiSearchResults(String, Vec<SearchResult>),
     UiTearDown,
 }
 
@@ -38,6 +55,16 @@ pub struct MainController {
     sync_counter: usize,
     sync_tracker: Vec<SyncResult>,
     download_tracker: HashSet<i64>,
+    player: Option<MpvPlayer>,
+    now_playing: Option<NowPlaying>,
+    player_counter: i64,
+    queue: Vec<(i64, i64)>,
+    play_sessions: AHashMap<i64, (DateTime<Utc>, i64)>,
+    sleep_timer: Option<SleepTimer>,
+    sleep_timer_counter: i64,
+    stopped_by_timer: Option<i64>,
+    undo_stack: Vec<UndoAction>,
+    db_version: i64,
     pub ui_thread: std::thread::JoinHandle<()>,
     pub tx_to_ui: mpsc::Sender<MainMessage>,
     pub tx_to_main: mpsc::Sender<Message>,
@@ -63,8 +90,10 @@ impl MainController {
         // runtime; this is necessary because we want main.rs to hold the
         // "ground truth" list of podcasts, and it must be mutable, but
         // UI needs to check this list and update the screen when
-        // necessary
-        let podcast_list = LockVec::new(db_inst.get_podcasts()?);
+        // necessary; episodes are only loaded once they are needed
+        let podcast_list = LockVec::new(db_inst.get_podcast_summaries(false)?);
+        let queue = db_inst.get_queue()?;
+        let db_version = db_inst.data_version()?;
 
         // set up UI in new thread
         let tx_ui_to_main = mpsc::Sender::clone(&tx_to_main);
@@ -76,7 +105,7 @@ impl MainController {
         );
         // TODO: Can we do this without cloning the config?
 
-        return Ok(MainController {
+        let mut controller = MainController {
             config: config,
             db: db_inst,
             threadpool: threadpool,
@@ -86,17 +115,59 @@ impl MainController {
             sync_counter: 0,
             sync_tracker: Vec::new(),
             download_tracker: HashSet::new(),
+            player: None,
+            now_playing: None,
+            player_counter: 0,
+            queue: queue,
+            play_sessions: AHashMap::new(),
+            sleep_timer: None,
+            sleep_timer_counter: 0,
+            stopped_by_timer: None,
+            undo_stack: Vec::new(),
+            db_version: db_version,
             tx_to_ui: tx_to_ui,
             tx_to_main: tx_to_main,
             rx_to_main: rx_to_main,
-        });
+        };
+        // anything left in the trash from last time can't be undone
+        controller.empty_trash();
+        // archived podcasts are hidden from the start
+        controller.update_filters(&controller.filters, true);
+        controller.send_queue();
+        return Ok(controller);
     }
 
     /// Initiates the main loop where the controller waits for messages coming in from the UI and other threads, and processes them.
     pub fn loop_msgs(&mut self) {
-        while let Some(message) = self.rx_to_main.iter().next() {
+        let check_interval = Duration::from_millis(crate::config::DB_CHECK_INTERVAL);
+        let mut last_check = Instant::now();
+        loop {
+            // check now and then whether another process has changed
+            // the database, whether or not there are messages coming in
+            if last_check.elapsed() >= check_interval {
+                self.check_db_changes();
+                last_check = Instant::now();
+            }
+            let message = match self.rx_to_main.recv_timeout(check_interval) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
             match message {
-                Message::Ui(UiMsg::Quit) => break,
+                Message::Ui(UiMsg::Quit) => {
+                    self.empty_trash();
+                    break;
+                }
+
+                Message::Ui(UiMsg::Undo) => self.undo(),
+
+                Message::Ui(UiMsg::LoadEpisodes(pod_id)) => {
+                    if self.load_episodes(pod_id) {
+                        self.tx_to_ui
+                            .send(MainMessage::UiUpdateMenus)
+                            .expect("Thread messaging error");
+                    }
+                }
 
                 Message::Ui(UiMsg::AddFeed(url)) => self.add_podcast(url),
 
@@ -115,12 +186,102 @@ impl MainController {
 
                 Message::Ui(UiMsg::SyncAll) => self.sync(None),
 
-                Message::Ui(UiMsg::Play(pod_id, ep_id)) => self.play_file(pod_id, ep_id),
+                Message::Ui(UiMsg::Play(pod_id, ep_id)) => self.play_file(pod_id, ep_id, None),
+
+                Message::Ui(UiMsg::Player(command)) => self.control_player(command),
+
+                Message::Player(PlayerMsg::Status(id, now_playing)) => {
+                    if self.player.as_ref().map(|p| p.id) == Some(id) {
+                        self.update_position(&now_playing);
+                        self.now_playing = Some(now_playing.clone());
+                        self.tx_to_ui
+                            .send(MainMessage::UiUpdateNowPlaying(Some(now_playing)))
+                            .expect("Thread messaging error");
+                    }
+                }
+
+                Message::Player(PlayerMsg::Exited(id, end)) => {
+                    if !end.success {
+                        let message = match end.exit_code {
+                            Some(code) => format!("Error: Player exited with code {code}."),
+                            None => "Error: Player was stopped unexpectedly.".to_string(),
+                        };
+                        self.notif_to_ui(message, true);
+                    }
+                    // the episode may have been replaced by another one
+                    // in the meantime, but its position still counts
+                    let finished = self.playback_stopped(&end);
+                    self.record_history(id, &end, finished);
+                    if self.player.as_ref().map(|p| p.id) == Some(id) {
+                        self.player = None;
+                        self.now_playing = None;
+                        self.tx_to_ui
+                            .send(MainMessage::UiUpdateNowPlaying(None))
+                            .expect("Thread messaging error");
+                    }
+                    if finished && id == self.player_counter {
+                        if self.sleep_timer == Some(SleepTimer::EndOfEpisode) {
+                            self.set_sleep_timer(None);
+                            self.notif_to_ui(
+                                "Sleep timer: stopped at the end of the episode.".to_string(),
+                                false,
+                            );
+                        } else if self.stopped_by_timer != Some(id) {
+                            self.play_next();
+                        }
+                    }
+                }
+
+                Message::Ui(UiMsg::SleepTimer(timer)) => self.start_sleep_timer(timer),
+
+                Message::Ui(UiMsg::ShowStats) => self.show_stats(),
+
+                Message::Ui(UiMsg::Search(query)) => self.search(query),
+
+                Message::Ui(UiMsg::AddNote(pod_id, ep_id, position, text)) => {
+                    self.add_note(pod_id, ep_id, position, text)
+                }
+
+                Message::Ui(UiMsg::RemoveNote(pod_id, ep_id, note_id)) => {
+                    self.remove_note(pod_id, ep_id, note_id)
+                }
+
+                Message::Ui(UiMsg::JumpToNote(pod_id, ep_id, position)) => {
+                    self.jump_to_note(pod_id, ep_id, position)
+                }
+
+                Message::Player(PlayerMsg::SleepTimer(id)) => {
+                    if id == self.sleep_timer_counter {
+                        self.sleep_timer_done();
+                    }
+                }
+
+                Message::Ui(UiMsg::QueueAdd(pod_id, ep_id, next)) => {
+                    self.enqueue(pod_id, ep_id, next)
+                }
+
+                Message::Ui(UiMsg::QueueRemove(ep_id)) => self.dequeue(ep_id),
+
+                Message::Ui(UiMsg::QueueMove(ep_id, offset)) => self.move_in_queue(ep_id, offset),
+
+                Message::Ui(UiMsg::PodcastSettings(pod_id, settings)) => {
+                    self.set_podcast_settings(pod_id, settings)
+                }
+
+                Message::Ui(UiMsg::SetTags(pod_id, tags)) => self.set_tags(pod_id, tags),
 
                 Message::Ui(UiMsg::MarkPlayed(pod_id, ep_id, played)) => {
                     self.mark_played(pod_id, ep_id, played)
                 }
 
+                Message::Ui(UiMsg::Archive(pod_id, archived)) => self.archive(pod_id, archived),
+
+                Message::Ui(UiMsg::Unhide(pod_id, ep_id)) => self.unhide(pod_id, ep_id),
+
+                Message::Ui(UiMsg::Star(pod_id, ep_id, starred)) => {
+                    self.star(pod_id, ep_id, starred)
+                }
+
                 Message::Ui(UiMsg::MarkAllPlayed(pod_id, played)) => {
                     self.mark_all_played(pod_id, played)
                 }
@@ -149,7 +310,7 @@ impl MainController {
 
                 Message::Ui(UiMsg::Delete(pod_id, ep_id)) => self.delete_file(pod_id, ep_id),
 
-                Message::Ui(UiMsg::DeleteAll(pod_id)) => self.delete_files(pod_id),
+                Message::Ui(UiMsg::DeleteAll(pod_id)) => self.delete_files(pod_id, true),
 
                 Message::Ui(UiMsg::RemovePodcast(pod_id, delete_files)) => {
                     self.remove_podcast(pod_id, delete_files)
@@ -164,59 +325,144 @@ impl MainController {
                 }
 
                 Message::Ui(UiMsg::FilterChange(filter_type)) => {
-                    let new_filter;
-                    let message;
-                    match filter_type {
-                        // we need to handle these separately because the
-                        // order that makes the most sense to me is
-                        // different:
-                        // played goes from all -> neg -> pos;
-                        // downloaded goes from all -> pos -> neg;
-                        // this is purely based on the idea that people
-                        // are most likely to want to specifically find
-                        // unplayed episodes, or downloaded episodes
-                        FilterType::Played => {
-                            match self.filters.played {
-                                FilterStatus::All => {
-                                    new_filter = FilterStatus::NegativeCases;
-                                    message = "Unplayed only";
-                                }
-                                FilterStatus::NegativeCases => {
-                                    new_filter = FilterStatus::PositiveCases;
-                                    message = "Played only";
-                                }
-                                FilterStatus::PositiveCases => {
-                                    new_filter = FilterStatus::All;
-                                    message = "Played and unplayed";
-                                }
-                            }
-                            self.filters.played = new_filter;
-                        }
-                        FilterType::Downloaded => {
-                            match self.filters.downloaded {
-                                FilterStatus::All => {
-                                    new_filter = FilterStatus::PositiveCases;
-                                    message = "Downloaded only";
-                                }
-                                FilterStatus::PositiveCases => {
-                                    new_filter = FilterStatus::NegativeCases;
-                                    message = "Undownloaded only";
-                                }
-                                FilterStatus::NegativeCases => {
-                                    new_filter = FilterStatus::All;
-                                    message = "Downloaded and undownloaded";
-                                }
-                            }
-                            self.filters.downloaded = new_filter;
-                        }
-                    }
-                    self.notif_to_ui(format!("Filter: {message}"), false);
-                    self.update_filters(self.filters, true);
+                    self.change_filter(filter_type)
                 }
 
-                Message::Ui(UiMsg::Noop) => (),
+                Message::Ui(UiMsg::SearchPrompt)
+                | Message::Ui(UiMsg::GoToEpisode(_, _))
+                | Message::Ui(UiMsg::NotePrompt(_, _))
+                | Message::Ui(UiMsg::Noop) => (),
+            }
+        }
+    }
+
+    /// Moves the given filter on to its next setting, and updates the
+    /// menus to match.
+    pub fn change_filter(&mut self, filter_type: FilterType) {
+        let new_filter;
+        let message;
+        let tag_message;
+        match filter_type {
+            // we need to handle these separately because the
+            // order that makes the most sense to me is
+            // different:
+            // played goes from all -> neg -> pos;
+            // downloaded goes from all -> pos -> neg;
+            // this is purely based on the idea that people
+            // are most likely to want to specifically find
+            // unplayed episodes, or downloaded episodes
+            FilterType::Played => {
+                match self.filters.played {
+                    FilterStatus::All => {
+                        new_filter = FilterStatus::NegativeCases;
+                        message = "Unplayed only";
+                    }
+                    FilterStatus::NegativeCases => {
+                        new_filter = FilterStatus::PositiveCases;
+                        message = "Played only";
+                    }
+                    FilterStatus::PositiveCases => {
+                        new_filter = FilterStatus::All;
+                        message = "Played and unplayed";
+                    }
+                }
+                self.filters.played = new_filter;
+            }
+            FilterType::Downloaded => {
+                match self.filters.downloaded {
+                    FilterStatus::All => {
+                        new_filter = FilterStatus::PositiveCases;
+                        message = "Downloaded only";
+                    }
+                    FilterStatus::PositiveCases => {
+                        new_filter = FilterStatus::NegativeCases;
+                        message = "Undownloaded only";
+                    }
+                    FilterStatus::NegativeCases => {
+                        new_filter = FilterStatus::All;
+                        message = "Downloaded and undownloaded";
+                    }
+                }
+                self.filters.downloaded = new_filter;
+            }
+            FilterType::Starred => {
+                match self.filters.starred {
+                    FilterStatus::All => {
+                        new_filter = FilterStatus::PositiveCases;
+                        message = "Starred only";
+                    }
+                    _ => {
+                        new_filter = FilterStatus::All;
+                        message = "Starred and unstarred";
+                    }
+                }
+                self.filters.starred = new_filter;
+            }
+            FilterType::Tag => {
+                tag_message = self.next_tag_filter();
+                message = &tag_message;
+            }
+            // archived podcasts are hidden unless asked for
+            FilterType::Archived => {
+                match self.filters.archived {
+                    FilterStatus::NegativeCases => {
+                        new_filter = FilterStatus::All;
+                        message = "Archived and current podcasts";
+                    }
+                    FilterStatus::All => {
+                        new_filter = FilterStatus::PositiveCases;
+                        message = "Archived podcasts only";
+                    }
+                    FilterStatus::PositiveCases => {
+                        new_filter = FilterStatus::NegativeCases;
+                        message = "Current podcasts only";
+                    }
+                }
+                self.filters.archived = new_filter;
+            }
+            // hidden (i.e., removed) episodes are only loaded
+            // when they are shown
+            FilterType::Hidden => {
+                match self.filters.hidden {
+                    FilterStatus::NegativeCases => {
+                        new_filter = FilterStatus::All;
+                        message = "Hidden and visible episodes";
+                    }
+                    FilterStatus::All => {
+                        new_filter = FilterStatus::PositiveCases;
+                        message = "Hidden episodes only";
+                    }
+                    FilterStatus::PositiveCases => {
+                        new_filter = FilterStatus::NegativeCases;
+                        message = "Visible episodes only";
+                    }
+                }
+                self.filters.hidden = new_filter;
+                self.reload_podcasts();
             }
         }
+        self.notif_to_ui(format!("Filter: {message}"), false);
+        self.update_filters(&self.filters, true);
+    }
+
+    /// Reloads the podcasts and the play queue if another process (e.g.,
+    /// `shellcaster sync` run from cron) has changed the database since
+    /// they were last read.
+    pub fn check_db_changes(&mut self) {
+        let version = match self.db.data_version() {
+            Ok(version) => version,
+            Err(_) => return,
+        };
+        if version == self.db_version {
+            return;
+        }
+        self.db_version = version;
+        self.reload_podcasts();
+        if let Ok(queue) = self.db.get_queue() {
+            self.queue = queue;
+            self.send_queue();
+        }
+        self.update_filters(&self.filters, true);
     }
 
     /// Sends the specified notification to the UI, which will display at
@@ -269,8 +515,19 @@ impl MainController {
         }
     }
 
-    /// Add a new podcast by fetching the RSS feed data.
-    pub fn add_podcast(&self, url: String) {
+    /// Add a new podcast by fetching the RSS feed data. Subscribing again
+    /// to an archived podcast brings it back, with its history intact.
+    pub fn add_podcast(&mut self, url: String) {
+        let archived_id = self
+            .podcasts
+            .filter_map(|pod| (pod.archived && pod.url == url).then_some(pod.id))
+            .into_iter()
+            .next();
+        if let Some(pod_id) = archived_id {
+            self.archive(pod_id, false);
+            self.sync(Some(pod_id));
+            return;
+        }
         let feed = PodcastFeed::new(None, url, None);
         feeds::check_feed(
             feed,
@@ -297,12 +554,27 @@ impl MainController {
                     })
                     .unwrap(),
             ),
-            // get all of 'em!
+            // get all of 'em! (or all of the ones with the tag that the
+            // podcasts are filtered by); archived podcasts are left alone
             None => {
-                pod_data = self.podcasts.map(
-                    |pod| PodcastFeed::new(Some(pod.id), pod.url.clone(), Some(pod.title.clone())),
-                    false,
-                )
+                let tag = self.filters.tag.clone();
+                pod_data = self
+                    .podcasts
+                    .map(
+                        |pod| match &tag {
+                            _ if pod.archived => None,
+                            Some(tag) if !pod.has_tag(tag) => None,
+                            _ => Some(PodcastFeed::new(
+                                Some(pod.id),
+                                pod.url.clone(),
+                                Some(pod.title.clone()),
+                            )),
+                        },
+                        false,
+                    )
+                    .into_iter()
+                    .flatten()
+                    .collect();
             }
         }
         for feed in pod_data.into_iter() {
@@ -335,14 +607,8 @@ impl MainController {
         }
         match db_result {
             Ok(result) => {
-                {
-                    self.podcasts.replace_all(
-                        self.db
-                            .get_podcasts()
-                            .expect("Error retrieving info from database."),
-                    );
-                }
-                self.update_filters(self.filters, true);
+                self.apply_sync_result(&result);
+                self.update_filters(&self.filters, true);
 
                 if pod_id.is_some() {
                     self.sync_tracker.push(result);
@@ -400,34 +666,657 @@ impl MainController {
         }
     }
 
+    /// Brings a podcast in the list up to date after it has been added
+    /// or synced, without reloading the rest of the library. If the
+    /// podcast's episodes have been loaded, only the new and updated
+    /// episodes are read from the database.
+    fn apply_sync_result(&self, result: &SyncResult) {
+        let mut podcast = match self.db.get_podcast_summary(result.pod_id, self.show_hidden()) {
+            Ok(pod) => pod,
+            Err(_) => {
+                self.notif_to_ui("Error retrieving info from database.".to_string(), true);
+                return;
+            }
+        };
+        if let Some(old_podcast) = self.podcasts.clone_podcast(result.pod_id) {
+            podcast.loaded = old_podcast.loaded;
+            podcast.episodes = old_podcast.episodes;
+        }
+
+        if podcast.loaded && !(result.added.is_empty() && result.updated.is_empty()) {
+            let mut ep_ids: Vec<i64> = result.added.iter().map(|ep| ep.id).collect();
+            ep_ids.extend(result.updated.iter());
+            match self
+                .db
+                .get_episodes_by_id(podcast.id, &ep_ids, self.show_hidden())
+            {
+                Ok(changed) => {
+                    let mut episodes = podcast.episodes.map(|ep| ep.clone(), false);
+                    episodes.retain(|ep| !result.updated.contains(&ep.id));
+                    episodes.extend(changed);
+                    // newest first, as they come from the database
+                    episodes.sort_by_key(|ep| std::cmp::Reverse(ep.pubdate));
+                    podcast.episodes.replace_all(episodes);
+                }
+                Err(_) => {
+                    self.notif_to_ui("Error retrieving info from database.".to_string(), true)
+                }
+            }
+        }
+        self.podcasts.add_podcast(podcast);
+    }
+
     /// Attempts to execute the play command on the given podcast
-    /// episode.
-    pub fn play_file(&self, pod_id: i64, ep_id: i64) {
-        self.mark_played(pod_id, ep_id, true);
+    /// episode, starting from `start` (in seconds) if given, or else
+    /// resuming from where the user last stopped listening.
+    pub fn play_file(&mut self, pod_id: i64, ep_id: i64, start: Option<i64>) {
+        self.load_episodes(pod_id);
         let episode = self.podcasts.clone_episode(pod_id, ep_id).unwrap();
+        if self.queue.iter().any(|(_, id)| *id == ep_id) {
+            self.dequeue(ep_id);
+        }
 
-        match episode.path {
-            // if there is a local file, try to play that
+        // if there is a local file, try to play that; otherwise, try to
+        // stream the URL
+        let path = match &episode.path {
             Some(path) => match path.to_str() {
-                Some(p) => {
-                    if play_file::execute(&self.config.play_command, p).is_err() {
-                        self.notif_to_ui(
-                            "Error: Could not play file. Check configuration.".to_string(),
-                            true,
-                        );
-                    }
+                Some(p) => Some(p.to_string()),
+                None => {
+                    self.notif_to_ui("Error: Filepath is not valid Unicode.".to_string(), true);
+                    return;
                 }
-                None => self.notif_to_ui("Error: Filepath is not valid Unicode.".to_string(), true),
             },
-            // otherwise, try to stream the URL
-            None => {
-                if play_file::execute(&self.config.play_command, &episode.url).is_err() {
+            None => None,
+        };
+        let (pod_title, pod_url, settings) = self
+            .podcasts
+            .map_single(pod_id, |pod| (pod.title.clone(), pod.url.clone(), pod.settings))
+            .unwrap_or_default();
+
+        // resume where the user left off, but never before the end of
+        // the podcast's intro
+        let start = match start {
+            Some(start) => start,
+            None if episode.in_progress() => episode.position.max(settings.skip_intro),
+            None => settings.skip_intro,
+        };
+        let data = PlayData {
+            target: path.clone().unwrap_or_else(|| episode.url.clone()),
+            title: episode.title.clone(),
+            pod_title: pod_title.clone(),
+            url: episode.url.clone(),
+            path: path,
+            duration: episode.duration,
+            start: start,
+            speed: settings.speed,
+            skip_outro: settings.skip_outro,
+        };
+
+        // a command set for the podcast (by feed URL or title) takes
+        // precedence over one set for the type of file
+        let command = self
+            .config
+            .podcast_play_commands
+            .get(&pod_url)
+            .or_else(|| self.config.podcast_play_commands.get(&pod_title))
+            .map(|cmd| cmd.as_str())
+            .or_else(|| {
+                play_file::command_for_type(
+                    &self.config.play_commands,
+                    episode.mime_type.as_deref(),
+                    &data.target,
+                )
+            })
+            .unwrap_or(&self.config.play_command)
+            .to_string();
+
+        if self.config.player_integration == PlayerIntegration::Mpv {
+            if self.play_with_mpv(&episode, &command, &data) {
+                self.play_sessions
+                    .insert(self.player_counter, (Utc::now(), data.start));
+                if self.config.mark_as_played == MarkPlayed::Launch {
+                    self.mark_played(pod_id, ep_id, true);
+                }
+            }
+            return;
+        }
+
+        match play_file::execute(&command, &data, self.config.play_command_shell) {
+            Ok(child) => {
+                if self.config.mark_as_played == MarkPlayed::Launch {
+                    self.mark_played(pod_id, ep_id, true);
+                }
+                self.player_counter += 1;
+                let id = self.player_counter;
+                self.play_sessions.insert(id, (Utc::now(), data.start));
+                let tx_to_main = self.tx_to_main.clone();
+                thread::spawn(move || {
+                    let (status, position) = play_file::wait(child);
+                    let end = PlaybackEnd {
+                        pod_id: pod_id,
+                        ep_id: ep_id,
+                        success: status.map(|s| s.success()).unwrap_or(false),
+                        exit_code: status.and_then(|s| s.code()),
+                        position: position,
+                    };
+                    let _ = tx_to_main.send(Message::Player(PlayerMsg::Exited(id, end)));
+                });
+            }
+            Err(_) => {
+                if episode.path.is_some() {
+                    self.notif_to_ui(
+                        "Error: Could not play file. Check configuration.".to_string(),
+                        true,
+                    );
+                } else {
                     self.notif_to_ui("Error: Could not stream URL.".to_string(), true);
                 }
             }
         }
     }
 
+    /// Starts playing an episode file or URL in mpv, controlled over
+    /// its IPC interface. The play command is never run through the
+    /// shell here, as shellcaster needs to add its own arguments. Any
+    /// episode that is already playing is stopped first. Returns true if
+    /// the player was started.
+    pub fn play_with_mpv(&mut self, episode: &Episode, command: &str, data: &PlayData) -> bool {
+        if let Some(mut player) = self.player.take() {
+            let _ = player.send(PlayerCommand::Stop);
+        }
+
+        self.player_counter += 1;
+        let now_playing = NowPlaying {
+            pod_id: episode.pod_id,
+            ep_id: episode.id,
+            title: episode.title.clone(),
+            position: data.start as f64,
+            duration: episode.duration.map(|dur| dur as f64),
+            paused: false,
+            speed: data.speed,
+        };
+        match MpvPlayer::spawn(
+            self.player_counter,
+            command,
+            data,
+            now_playing.clone(),
+            self.tx_to_main.clone(),
+        ) {
+            Ok(player) => {
+                self.player = Some(player);
+                self.now_playing = Some(now_playing.clone());
+                self.tx_to_ui
+                    .send(MainMessage::UiUpdateNowPlaying(Some(now_playing)))
+                    .expect("Thread messaging error");
+                return true;
+            }
+            Err(_) => {
+                self.notif_to_ui(
+                    "Error: Could not start mpv. Check configuration.".to_string(),
+                    true,
+                );
+                return false;
+            }
+        }
+    }
+
+    /// Sends a command (e.g., pause, seek) to the player, if there is
+    /// an episode currently playing.
+    pub fn control_player(&mut self, command: PlayerCommand) {
+        match self.player.as_mut() {
+            Some(player) => {
+                if player.send(command).is_err() {
+                    self.notif_to_ui("Error: Could not control player.".to_string(), true);
+                }
+            }
+            None => {
+                if self.config.player_integration == PlayerIntegration::None {
+                    self.notif_to_ui(
+                        "Player controls require the mpv integration to be turned on.".to_string(),
+                        true,
+                    );
+                } else {
+                    self.notif_to_ui("Nothing is playing.".to_string(), false);
+                }
+            }
+        }
+    }
+
+    /// Sets the sleep timer, or turns it off if `timer` is None. A
+    /// thread is started to wait for the timer to run out; if the timer
+    /// is changed in the meantime, the message it sends is ignored.
+    /// Stopping at a set time needs the player integration, as
+    /// shellcaster has no way to control other players.
+    pub fn start_sleep_timer(&mut self, timer: Option<SleepTimer>) {
+        if let Some(SleepTimer::At(time)) = timer {
+            if self.config.player_integration == PlayerIntegration::None {
+                self.notif_to_ui(
+                    "The sleep timer requires the mpv integration to be turned on.".to_string(),
+                    true,
+                );
+                self.set_sleep_timer(None);
+                return;
+            }
+            let id = self.sleep_timer_counter + 1;
+            let tx_to_main = self.tx_to_main.clone();
+            thread::spawn(move || {
+                thread::sleep(time.saturating_duration_since(Instant::now()));
+                let _ = tx_to_main.send(Message::Player(PlayerMsg::SleepTimer(id)));
+            });
+        }
+        self.set_sleep_timer(timer);
+
+        let message = match timer {
+            Some(timer) => format!("{}.", timer.format()),
+            None => "Sleep timer turned off.".to_string(),
+        };
+        self.notif_to_ui(message, false);
+    }
+
+    /// Stores the current sleep timer and passes it on to the UI.
+    /// Any timer that was running before is cancelled.
+    fn set_sleep_timer(&mut self, timer: Option<SleepTimer>) {
+        self.sleep_timer_counter += 1;
+        self.sleep_timer = timer;
+        self.tx_to_ui
+            .send(MainMessage::UiUpdateSleepTimer(timer))
+            .expect("Thread messaging error");
+    }
+
+    /// Called when the sleep timer runs out: pauses or stops the player,
+    /// depending on the user's settings.
+    pub fn sleep_timer_done(&mut self) {
+        self.set_sleep_timer(None);
+        let player = match self.player.as_mut() {
+            Some(player) => player,
+            None => return,
+        };
+        let (command, message) = match self.config.sleep_timer_action {
+            SleepAction::Pause => (PlayerCommand::Pause, "Sleep timer: paused playback."),
+            SleepAction::Stop => {
+                // don't move on to the next episode in the queue
+                self.stopped_by_timer = Some(player.id);
+                (PlayerCommand::Stop, "Sleep timer: stopped playback.")
+            }
+        };
+        if player.send(command).is_err() {
+            self.notif_to_ui("Error: Could not control player.".to_string(), true);
+        } else {
+            self.notif_to_ui(message.to_string(), false);
+        }
+    }
+
+    /// Saves the playback position reported by the player, if it has
+    /// moved far enough since it was last saved. Saving on every status
+    /// update would mean hitting the database twice a second.
+    pub fn update_position(&self, now_playing: &NowPlaying) {
+        let saved = self
+            .podcasts
+            .clone_episode(now_playing.pod_id, now_playing.ep_id)
+            .map(|ep| ep.position);
+        if let Some(saved) = saved {
+            let position = now_playing.position as i64;
+            if (position - saved).abs() >= POSITION_SAVE_INTERVAL {
+                self.set_position(now_playing.pod_id, now_playing.ep_id, position);
+            }
+        }
+    }
+
+    /// Called when a player exits. If the user got far enough into the
+    /// episode, the saved position is reset; otherwise, it is saved so
+    /// playback can be resumed. The episode is then marked as played
+    /// according to the user's settings. Returns true if the episode
+    /// counts as finished, i.e., the user got far enough into it, or,
+    /// if we don't know how far they got, the player exited cleanly.
+    pub fn playback_stopped(&self, end: &PlaybackEnd) -> bool {
+        let episode = match self.podcasts.clone_episode(end.pod_id, end.ep_id) {
+            Some(ep) => ep,
+            None => return false,
+        };
+
+        // if the podcast's outro is skipped, the player stops before the
+        // end, which should still count as finishing the episode
+        let skip_outro = self
+            .podcasts
+            .map_single(end.pod_id, |pod| pod.settings.skip_outro)
+            .unwrap_or(0);
+        let finished = match end.position {
+            Some((position, duration)) => {
+                let duration = duration.or(episode.duration).map(|dur| dur - skip_outro);
+                match duration.filter(|dur| *dur > 0) {
+                    Some(dur) => {
+                        let finished = position * 100 >= dur * self.config.played_threshold;
+                        let saved = if finished { 0 } else { position };
+                        self.set_position(end.pod_id, end.ep_id, saved);
+                        finished
+                    }
+                    None => {
+                        self.set_position(end.pod_id, end.ep_id, position);
+                        end.success
+                    }
+                }
+            }
+            None => end.success,
+        };
+
+        let mark = match self.config.mark_as_played {
+            MarkPlayed::Launch => false, // already done
+            MarkPlayed::Exit => end.success,
+            MarkPlayed::Position => finished,
+        };
+        if mark && !episode.played {
+            self.mark_played(end.pod_id, end.ep_id, true);
+        }
+        return finished;
+    }
+
+    /// Adds an entry to the listening history for a player that has
+    /// exited, based on where it started and stopped, or the episode's
+    /// duration if the player didn't tell us where it stopped.
+    pub fn record_history(&mut self, id: i64, end: &PlaybackEnd, finished: bool) {
+        let (started_at, start) = match self.play_sessions.remove(&id) {
+            Some(session) => session,
+            None => return,
+        };
+        let duration = self
+            .podcasts
+            .clone_episode(end.pod_id, end.ep_id)
+            .and_then(|ep| ep.duration);
+        let ended_at = Utc::now();
+        let listened = stats::listened_time(
+            start,
+            end.position.map(|(pos, _)| pos),
+            end.position.and_then(|(_, dur)| dur).or(duration),
+            (ended_at - started_at).num_seconds(),
+            finished,
+        );
+        let entry = HistoryEntry {
+            ep_id: end.ep_id,
+            pod_id: end.pod_id,
+            started_at: started_at,
+            ended_at: ended_at,
+            listened: listened,
+            finished: finished,
+        };
+        if self.db.add_history(&entry).is_err() {
+            self.notif_to_ui("Error: Could not save listening history.".to_string(), true);
+        }
+
+        let total = end.position.and_then(|(_, dur)| dur).or(duration);
+        let position = match end.position {
+            Some((pos, _)) => Some(pos),
+            None if finished => total,
+            None => None,
+        };
+        if let (Some(position), Some(total)) = (position, total) {
+            self.record_action(end.ep_id, ActionKind::Play {
+                started: start,
+                position: position,
+                total: total,
+            });
+        }
+    }
+
+    /// Saves an episode action to upload to the gpodder server at the
+    /// next sync, if syncing is set up. Returns the id of the saved
+    /// action.
+    fn record_action(&self, ep_id: i64, action: ActionKind) -> Option<i64> {
+        // nothing to do unless syncing is set up
+        self.config.gpodder.as_ref()?;
+        return match self.db.add_episode_action(ep_id, &action) {
+            Ok(id) => Some(id),
+            Err(_) => {
+                self.notif_to_ui(
+                    "Error: Could not save episode action for syncing.".to_string(),
+                    true,
+                );
+                None
+            }
+        };
+    }
+
+    /// Records marking an episode as played or unplayed as a play
+    /// action, as played up to the end or from the start. Nothing is
+    /// recorded if the episode's duration is unknown. Returns the id of
+    /// the saved action.
+    fn record_played(&self, ep_id: i64, duration: Option<i64>, played: bool) -> Option<i64> {
+        let total = duration?;
+        return self.record_action(ep_id, ActionKind::Play {
+            started: 0,
+            position: if played { total } else { 0 },
+            total: total,
+        });
+    }
+
+    /// Works out listening statistics and sends them to the UI to show.
+    pub fn show_stats(&self) {
+        let history = self.db.get_history();
+        let played = self.db.get_played_episodes();
+        let last_played = self.db.get_last_played();
+        match (history, played, last_played) {
+            (Ok(history), Ok(played), Ok(last_played)) => {
+                // the backlog needs every episode, not just the loaded ones
+                let podcasts = match self.db.get_podcasts(false) {
+                    Ok(podcasts) => podcasts,
+                    Err(_) => {
+                        self.notif_to_ui("Error retrieving info from database.".to_string(), true);
+                        return;
+                    }
+                };
+                let stats = Stats::new(&podcasts, &history, &played, &last_played, Utc::now());
+                self.tx_to_ui
+                    .send(MainMessage::UiShowStats(stats))
+                    .expect("Thread messaging error");
+            }
+            _ => self.notif_to_ui("Error: Could not read listening history.".to_string(), true),
+        }
+    }
+
+    /// Searches all episodes for the given text, and sends the results
+    /// to the UI.
+    pub fn search(&self, query: String) {
+        match self.db.search(&query, crate::config::SEARCH_LIMIT, self.show_hidden()) {
+            Ok(results) => {
+                // the results can be played, queued, or gone to
+                for res in results.iter() {
+                    self.load_episodes(res.pod_id);
+                }
+                self.tx_to_ui
+                    .send(MainMessage::UiSearchResults(query, results))
+                    .expect("Thread messaging error");
+            }
+            Err(_) => self.notif_to_ui("Error: Could not search episodes.".to_string(), true),
+        }
+    }
+
+    /// Adds a note to an episode. A note without a time that is added
+    /// while the episode is playing in mpv is bookmarked at the current
+    /// position.
+    pub fn add_note(&self, pod_id: i64, ep_id: i64, position: Option<i64>, text: String) {
+        let podcast = match self.podcasts.clone_podcast(pod_id) {
+            Some(pod) => pod,
+            None => return,
+        };
+        let mut episode = match podcast.episodes.clone_episode(ep_id) {
+            Some(ep) => ep,
+            None => return,
+        };
+        let position = position.or_else(|| {
+            self.now_playing
+                .as_ref()
+                .filter(|np| np.ep_id == ep_id)
+                .map(|np| np.position as i64)
+        });
+        let note = match self.db.add_note(ep_id, position, &text) {
+            Ok(note) => note,
+            Err(_) => {
+                self.notif_to_ui("Error: Could not save note.".to_string(), true);
+                return;
+            }
+        };
+        let message = match note.format_position() {
+            Some(pos) => format!("Bookmarked {pos}."),
+            None => "Note added.".to_string(),
+        };
+        episode.notes.push(note);
+        episode
+            .notes
+            .sort_by_key(|note| (note.position.is_none(), note.position, note.created, note.id));
+        podcast.episodes.replace(ep_id, episode);
+
+        self.podcasts.replace(pod_id, podcast);
+        self.update_filters(&self.filters, true);
+        self.notif_to_ui(message, false);
+    }
+
+    /// Deletes a note from an episode.
+    pub fn remove_note(&self, pod_id: i64, ep_id: i64, note_id: i64) {
+        let podcast = match self.podcasts.clone_podcast(pod_id) {
+            Some(pod) => pod,
+            None => return,
+        };
+        let mut episode = match podcast.episodes.clone_episode(ep_id) {
+            Some(ep) => ep,
+            None => return,
+        };
+        if self.db.remove_note(note_id).is_err() {
+            self.notif_to_ui("Error: Could not remove note.".to_string(), true);
+            return;
+        }
+        episode.notes.retain(|note| note.id != note_id);
+        podcast.episodes.replace(ep_id, episode);
+
+        self.podcasts.replace(pod_id, podcast);
+        self.update_filters(&self.filters, true);
+    }
+
+    /// Jumps to a bookmarked position in an episode. If the episode is
+    /// already playing in mpv, it seeks there; otherwise, the episode
+    /// is played from that point.
+    pub fn jump_to_note(&mut self, pod_id: i64, ep_id: i64, position: i64) {
+        let playing = self.now_playing.as_ref().map(|np| np.ep_id) == Some(ep_id);
+        if playing && self.player.is_some() {
+            self.control_player(PlayerCommand::SeekTo(position));
+        } else {
+            self.play_file(pod_id, ep_id, Some(position));
+        }
+    }
+
+    /// Sets the playback position of an episode, sending this info to
+    /// the database and updating in self.podcasts
+    pub fn set_position(&self, pod_id: i64, ep_id: i64, position: i64) {
+        let podcast = match self.podcasts.clone_podcast(pod_id) {
+            Some(pod) => pod,
+            None => return,
+        };
+        let mut episode = match podcast.episodes.clone_episode(ep_id) {
+            Some(ep) => ep,
+            None => return,
+        };
+        episode.position = position.max(0);
+
+        let _ = self.db.set_position(episode.id, episode.position);
+        podcast.episodes.replace(ep_id, episode);
+
+        self.podcasts.replace(pod_id, podcast);
+        self.update_filters(&self.filters, true);
+    }
+
+    /// Adds an episode to the play queue, either at the end, or at the
+    /// front if `next` is true. If the episode is already in the queue,
+    /// it is moved.
+    pub fn enqueue(&mut self, pod_id: i64, ep_id: i64, next: bool) {
+        self.load_episodes(pod_id);
+        let episode = match self.podcasts.clone_episode(pod_id, ep_id) {
+            Some(ep) => ep,
+            None => return,
+        };
+        self.queue.retain(|(_, id)| *id != ep_id);
+        if next {
+            self.queue.insert(0, (pod_id, ep_id));
+        } else {
+            self.queue.push((pod_id, ep_id));
+        }
+        self.save_queue();
+
+        let position = if next { "front" } else { "end" };
+        self.notif_to_ui(format!("Added to {position} of queue: {}", episode.title), false);
+
+        if self.config.prioritize_queued_downloads && episode.path.is_none() {
+            self.download(pod_id, Some(ep_id));
+        }
+    }
+
+    /// Removes an episode from the play queue.
+    pub fn dequeue(&mut self, ep_id: i64) {
+        self.queue.retain(|(_, id)| *id != ep_id);
+        self.save_queue();
+    }
+
+    /// Moves an episode up (negative `offset`) or down (positive
+    /// `offset`) in the play queue.
+    pub fn move_in_queue(&mut self, ep_id: i64, offset: i64) {
+        if let Some(index) = self.queue.iter().position(|(_, id)| *id == ep_id) {
+            let new_index = (index as i64 + offset).clamp(0, self.queue.len() as i64 - 1) as usize;
+            if new_index != index {
+                let item = self.queue.remove(index);
+                self.queue.insert(new_index, item);
+                self.save_queue();
+            }
+        }
+    }
+
+    /// Starts playing the first episode in the play queue, if there is
+    /// one.
+    pub fn play_next(&mut self) {
+        if let Some(&(pod_id, ep_id)) = self.queue.first() {
+            self.play_file(pod_id, ep_id, None);
+        }
+    }
+
+    /// Saves the play queue to the database and sends it to the UI,
+    /// dropping any episodes that have since been removed.
+    pub fn save_queue(&mut self) {
+        let podcasts = &self.podcasts;
+        self.queue
+            .retain(|(pod_id, ep_id)| {
+                podcasts
+                    .clone_episode(*pod_id, *ep_id)
+                    .is_some_and(|ep| !ep.hidden)
+            });
+        let ep_ids: Vec<i64> = self.queue.iter().map(|(_, ep_id)| *ep_id).collect();
+        if self.db.set_queue(&ep_ids).is_err() {
+            self.notif_to_ui("Error: Could not save play queue.".to_string(), true);
+        }
+        self.send_queue();
+    }
+
+    /// Sends the current contents of the play queue to the UI.
+    pub fn send_queue(&self) {
+        let mut items = Vec::new();
+        for (pod_id, ep_id) in self.queue.iter() {
+            // queued episodes can be played from the queue, so their
+            // podcasts' episodes are needed
+            self.load_episodes(*pod_id);
+            let pod_title = match self.podcasts.map_single(*pod_id, |pod| pod.title.clone()) {
+                Some(title) => title,
+                None => continue,
+            };
+            if let Some(ep) = self.podcasts.clone_episode(*pod_id, *ep_id) {
+                items.push(QueueItem {
+                    id: ep.id,
+                    pod_id: ep.pod_id,
+                    title: ep.title,
+                    pod_title: pod_title,
+                    downloaded: ep.path.is_some(),
+                });
+            }
+        }
+        self.tx_to_ui
+            .send(MainMessage::UiUpdateQueue(items))
+            .expect("Thread messaging error");
+    }
+
     /// Given a podcast and episode, it marks the given episode as
     /// played/unplayed, sending this info to the database and updating
     /// in self.podcasts
@@ -440,31 +1329,153 @@ impl MainController {
         episode.played = played;
 
         let _ = self.db.set_played_status(episode.id, played);
+        self.record_played(ep_id, episode.duration, played);
         podcast.episodes.replace(ep_id, episode);
 
         self.podcasts.replace(pod_id, podcast);
-        self.update_filters(self.filters, true);
+        self.update_filters(&self.filters, true);
+    }
+
+    /// Stars or unstars the given episode, sending this info to the
+    /// database and updating in self.podcasts
+    pub fn star(&self, pod_id: i64, ep_id: i64, starred: bool) {
+        let podcast = match self.podcasts.clone_podcast(pod_id) {
+            Some(pod) => pod,
+            None => return,
+        };
+        let mut episode = match podcast.episodes.clone_episode(ep_id) {
+            Some(ep) => ep,
+            None => return,
+        };
+        if self.db.set_starred(ep_id, starred).is_err() {
+            self.notif_to_ui("Error: Could not star episode.".to_string(), true);
+            return;
+        }
+        episode.starred = starred;
+        podcast.episodes.replace(ep_id, episode);
+
+        self.podcasts.replace(pod_id, podcast);
+        self.update_filters(&self.filters, true);
+    }
+
+    /// Archives or unarchives a podcast. Archived podcasts keep their
+    /// episodes and history, but are hidden from the menu by default and
+    /// are not synced.
+    pub fn archive(&self, pod_id: i64, archived: bool) {
+        let mut podcast = match self.podcasts.clone_podcast(pod_id) {
+            Some(pod) => pod,
+            None => return,
+        };
+        if self.db.set_archived(pod_id, archived).is_err() {
+            self.notif_to_ui("Error: Could not archive podcast.".to_string(), true);
+            return;
+        }
+        podcast.archived = archived;
+        let title = podcast.title.clone();
+        self.podcasts.replace(pod_id, podcast);
+        self.update_filters(&self.filters, true);
+        if archived {
+            self.notif_to_ui(format!("Archived {title}."), false);
+        } else {
+            self.notif_to_ui(format!("Unarchived {title}."), false);
+        }
     }
 
     /// Given a podcast, it marks all episodes for that podcast as
     /// played/unplayed, sending this info to the database and updating
-    /// in self.podcasts
-    pub fn mark_all_played(&self, pod_id: i64, played: bool) {
+    /// in self.podcasts. This can be undone.
+    pub fn mark_all_played(&mut self, pod_id: i64, played: bool) {
+        self.load_episodes(pod_id);
         let podcast = self.podcasts.clone_podcast(pod_id).unwrap();
-        {
-            let borrowed_ep_list = podcast.episodes.borrow_order();
-            for ep in borrowed_ep_list.iter() {
-                let _ = self.db.set_played_status(*ep, played);
+        let previous = podcast.episodes.map(|ep| (ep.id, ep.played), false);
+        let durations = podcast.episodes.map(|ep| ep.duration, false);
+        let mut actions = Vec::new();
+        for ((ep_id, was_played), duration) in previous.iter().zip(durations) {
+            let _ = self.db.set_played_status(*ep_id, played);
+            if *was_played != played {
+                if let Some(action_id) = self.record_played(*ep_id, duration, played) {
+                    actions.push((*ep_id, action_id));
+                }
             }
         }
         podcast.episodes.replace_all(
             self.db
-                .get_episodes(podcast.id, false)
+                .get_episodes(podcast.id, self.show_hidden())
                 .expect("Error retrieving info from database."),
         );
 
         self.podcasts.replace(pod_id, podcast);
-        self.update_filters(self.filters, true);
+        self.update_filters(&self.filters, true);
+        self.push_undo(UndoAction::MarkAllPlayed {
+            pod_id: pod_id,
+            played: previous,
+            actions: actions,
+        });
+    }
+
+    /// Saves the playback settings for a podcast, sending this info to
+    /// the database and updating in self.podcasts. They are used the
+    /// next time an episode of the podcast is played.
+    pub fn set_podcast_settings(&self, pod_id: i64, settings: PodcastSettings) {
+        let mut podcast = match self.podcasts.clone_podcast(pod_id) {
+            Some(pod) => pod,
+            None => return,
+        };
+        if self.db.set_podcast_settings(pod_id, &settings).is_err() {
+            self.notif_to_ui("Error: Could not save podcast settings.".to_string(), true);
+            return;
+        }
+        podcast.settings = settings;
+        let title = podcast.title.clone();
+        self.podcasts.replace(pod_id, podcast);
+        self.notif_to_ui(format!("Saved playback settings for {title}."), false);
+    }
+
+    /// Replaces the tags given to a podcast, sending this info to the
+    /// database and updating in self.podcasts.
+    pub fn set_tags(&self, pod_id: i64, tags: Vec<String>) {
+        let mut podcast = match self.podcasts.clone_podcast(pod_id) {
+            Some(pod) => pod,
+            None => return,
+        };
+        if self.db.set_tags(pod_id, &tags).is_err() {
+            self.notif_to_ui("Error: Could not save tags.".to_string(), true);
+            return;
+        }
+        podcast.tags = tags;
+        let message = if podcast.tags.is_empty() {
+            format!("Removed tags from {}.", podcast.title)
+        } else {
+            format!("Tagged {}: {}", podcast.title, podcast.tags.join(", "))
+        };
+        self.podcasts.replace(pod_id, podcast);
+        // the podcast may now be hidden by (or shown by) the tag filter
+        self.update_filters(&self.filters, true);
+        self.notif_to_ui(message, false);
+    }
+
+    /// Moves the tag filter on to the next tag, in alphabetical order,
+    /// after showing all podcasts; after the last tag, all podcasts are
+    /// shown again. Returns a description of the new filter.
+    pub fn next_tag_filter(&mut self) -> String {
+        let mut all_tags: Vec<String> = self
+            .podcasts
+            .map(|pod| pod.tags.clone(), false)
+            .into_iter()
+            .flatten()
+            .collect();
+        all_tags.sort();
+        all_tags.dedup();
+
+        self.filters.tag = match &self.filters.tag {
+            None => all_tags.first().cloned(),
+            Some(current) => all_tags.iter().find(|tag| *tag > current).cloned(),
+        };
+        return match &self.filters.tag {
+            Some(tag) => format!("Tag \"{tag}\" only"),
+            None if all_tags.is_empty() => "No podcasts have tags".to_string(),
+            None => "All tags".to_string(),
+        };
     }
 
     /// Given a podcast index (and not an episode index), this will send
@@ -472,6 +1483,9 @@ impl MainController {
     /// the podcast. If given an episode index as well, it will download
     /// just that episode.
     pub fn download(&mut self, pod_id: i64, ep_id: Option<i64>) {
+        // new episodes can be downloaded right after syncing, before
+        // their podcast has been opened
+        self.load_episodes(pod_id);
         let pod_title;
         let mut ep_data = Vec::new();
         {
@@ -528,6 +1542,12 @@ impl MainController {
         // don't needlessly download them again
         ep_data.retain(|ep| !self.download_tracker.contains(&ep.id));
 
+        if self.config.prioritize_queued_downloads {
+            // jobs are picked up in the order they are sent, so put
+            // queued episodes first
+            ep_data.sort_by_key(|ep| !self.queue.iter().any(|(_, id)| *id == ep.id));
+        }
+
         if !ep_data.is_empty() {
             // add directory for podcast, create if it does not exist
             let dir_name = sanitize_with_options(&pod_title, Options {
@@ -575,6 +1595,10 @@ impl MainController {
             episode.path = Some(file_path);
             podcast.episodes.replace(ep_data.id, episode);
         }
+        self.record_action(ep_data.id, ActionKind::Download);
+        if self.queue.iter().any(|(_, id)| *id == ep_data.id) {
+            self.send_queue();
+        }
 
         self.download_tracker.remove(&ep_data.id);
         self.update_tracker_notif();
@@ -582,7 +1606,7 @@ impl MainController {
             self.notif_to_ui("Downloads complete.".to_string(), false);
         }
 
-        self.update_filters(self.filters, true);
+        self.update_filters(&self.filters, true);
     }
 
     /// Given a podcast title, creates a download directory for that
@@ -597,39 +1621,70 @@ impl MainController {
     }
 
     /// Deletes a downloaded file for an episode from the user's local
-    /// system.
-    pub fn delete_file(&self, pod_id: i64, ep_id: i64) {
-        let borrowed_map = self.podcasts.borrow_map();
-        let podcast = borrowed_map.get(&pod_id).unwrap();
+    /// system. The file is moved to the trash folder, so that this can
+    /// be undone.
+    pub fn delete_file(&mut self, pod_id: i64, ep_id: i64) {
+        if let Some(file) = self.trash_file(pod_id, ep_id) {
+            self.push_undo(UndoAction::DeleteFiles {
+                pod_id: pod_id,
+                files: vec![file],
+            });
+        }
+    }
 
-        let mut episode = podcast.episodes.clone_episode(ep_id).unwrap();
-        if episode.path.is_some() {
-            let title = episode.title.clone();
-            match fs::remove_file(episode.path.unwrap()) {
-                Ok(_) => {
-                    let res = self.db.remove_file(episode.id);
-                    if res.is_err() {
-                        self.notif_to_ui(
-                            format!("Could not remove file from database: {title}"),
-                            true,
-                        );
-                        return;
-                    }
-                    episode.path = None;
-                    podcast.episodes.replace(ep_id, episode);
+    /// Deletes all downloaded files for a given podcast from the user's
+    /// local system. If `keep_starred` is set, files for starred
+    /// episodes are kept. The files are moved to the trash folder, so
+    /// that this can be undone.
+    pub fn delete_files(&mut self, pod_id: i64, keep_starred: bool) {
+        let files = self.trash_files(pod_id, keep_starred);
+        if !files.is_empty() {
+            self.push_undo(UndoAction::DeleteFiles {
+                pod_id: pod_id,
+                files: files,
+            });
+        }
+    }
 
-                    self.update_filters(self.filters, true);
-                    self.notif_to_ui(format!("Deleted \"{title}\""), false);
-                }
-                Err(_) => self.notif_to_ui(format!("Error deleting \"{title}\""), true),
+    /// Moves the downloaded file for an episode to the trash folder, and
+    /// removes it from the database. Returns where the file went, or
+    /// None if the episode was not downloaded or the file could not be
+    /// moved.
+    fn trash_file(&self, pod_id: i64, ep_id: i64) -> Option<TrashedFile> {
+        let podcast = self.podcasts.clone_podcast(pod_id)?;
+        let mut episode = podcast.episodes.clone_episode(ep_id)?;
+        let path = episode.path.clone()?;
+        let title = episode.title.clone();
+        let file = match undo::trash_file(&undo::trash_dir(&self.config.download_path), ep_id, &path) {
+            Ok(file) => file,
+            Err(_) => {
+                self.notif_to_ui(format!("Error deleting \"{title}\""), true);
+                return None;
             }
+        };
+        if self.db.remove_file(ep_id).is_err() {
+            // the database still lists the file, so put it back
+            let _ = undo::restore_file(&file);
+            self.notif_to_ui(format!("Could not remove file from database: {title}"), true);
+            return None;
         }
+        episode.path = None;
+        podcast.episodes.replace(ep_id, episode);
+        self.podcasts.replace(pod_id, podcast);
+        self.record_action(ep_id, ActionKind::Delete);
+
+        self.update_filters(&self.filters, true);
+        self.notif_to_ui(format!("Deleted \"{title}\""), false);
+        return Some(file);
     }
 
-    /// Deletes all downloaded files for a given podcast from the user's
-    /// local system.
-    pub fn delete_files(&self, pod_id: i64) {
-        let mut eps_to_remove = Vec::new();
+    /// Moves all downloaded files for a podcast to the trash folder, and
+    /// removes them from the database, keeping the files of starred
+    /// episodes if `keep_starred` is set. Returns where the files went.
+    fn trash_files(&self, pod_id: i64, keep_starred: bool) -> Vec<TrashedFile> {
+        self.load_episodes(pod_id);
+        let trash_dir = undo::trash_dir(&self.config.download_path);
+        let mut files = Vec::new();
         let mut success = true;
         {
             let borrowed_map = self.podcasts.borrow_map();
@@ -637,13 +1692,14 @@ impl MainController {
             let mut borrowed_ep_map = podcast.episodes.borrow_map();
 
             for (_, ep) in borrowed_ep_map.iter_mut() {
-                if ep.path.is_some() {
-                    let mut episode = ep.clone();
-                    match fs::remove_file(episode.path.unwrap()) {
-                        Ok(_) => {
-                            eps_to_remove.push(episode.id);
-                            episode.path = None;
-                            *ep = episode;
+                if let Some(path) = &ep.path {
+                    if keep_starred && ep.starred {
+                        continue;
+                    }
+                    match undo::trash_file(&trash_dir, ep.id, path) {
+                        Ok(file) => {
+                            files.push(file);
+                            ep.path = None;
                         }
                         Err(_) => success = false,
                     }
@@ -651,118 +1707,367 @@ impl MainController {
             }
         }
 
-        let res = self.db.remove_files(&eps_to_remove);
+        let ep_ids: Vec<i64> = files.iter().map(|file| file.ep_id).collect();
+        let res = self.db.remove_files(&ep_ids);
         if res.is_err() {
             success = false;
         }
-        self.update_filters(self.filters, true);
+        for ep_id in ep_ids.iter() {
+            self.record_action(*ep_id, ActionKind::Delete);
+        }
+        self.update_filters(&self.filters, true);
 
         if success {
             self.notif_to_ui("Files successfully deleted.".to_string(), false);
         } else {
             self.notif_to_ui("Error while deleting files".to_string(), true);
         }
+        return files;
     }
 
     /// Removes a podcast from the list, optionally deleting local files
-    /// first
+    /// first, in which case the database is backed up beforehand. The
+    /// podcast is kept in the trash, so that this can be undone.
     pub fn remove_podcast(&mut self, pod_id: i64, delete_files: bool) {
+        let mut files = Vec::new();
         if delete_files {
-            self.delete_files(pod_id);
+            // keep the podcast's history, notes, etc. even after it
+            // can no longer be undone
+            if self.db.auto_backup(self.config.auto_backups).is_err() {
+                self.notif_to_ui(
+                    "Error: Could not back up the database, so the podcast was not removed."
+                        .to_string(),
+                    true,
+                );
+                return;
+            }
+            files = self.trash_files(pod_id, false);
         }
 
-        let pod_id = self.podcasts.map_single(pod_id, |pod| pod.id).unwrap();
-        let res = self.db.remove_podcast(pod_id);
-        if res.is_err() {
-            self.notif_to_ui("Could not remove podcast from database".to_string(), true);
-            return;
-        }
-        {
-            self.podcasts.replace_all(
-                self.db
-                    .get_podcasts()
-                    .expect("Error retrieving info from database."),
-            );
+        let title = self.podcasts.map_single(pod_id, |pod| pod.title.clone()).unwrap();
+        let trash_id = match self.db.trash_podcast(pod_id) {
+            Ok(trash_id) => trash_id,
+            Err(_) => {
+                self.notif_to_ui("Could not remove podcast from database".to_string(), true);
+                return;
+            }
+        };
+        self.podcasts.remove(pod_id);
+        if self.queue.iter().any(|(id, _)| *id == pod_id) {
+            self.save_queue();
         }
-        self.tx_to_ui
-            .send(MainMessage::UiUpdateMenus)
-            .expect("Thread messaging error");
+        self.update_filters(&self.filters, true);
+        self.push_undo(UndoAction::RemovePodcast {
+            trash_id: trash_id,
+            title: title,
+            files: files,
+        });
     }
 
     /// Removes an episode from the list, optionally deleting local files
-    /// first
-    pub fn remove_episode(&self, pod_id: i64, ep_id: i64, delete_files: bool) {
+    /// first. This can be undone.
+    pub fn remove_episode(&mut self, pod_id: i64, ep_id: i64, delete_files: bool) {
+        let mut files = Vec::new();
         if delete_files {
-            self.delete_file(pod_id, ep_id);
+            files.extend(self.trash_file(pod_id, ep_id));
         }
 
         let _ = self.db.hide_episode(ep_id, true);
-        {
-            let mut borrowed_map = self.podcasts.borrow_map();
-            let podcast = borrowed_map.get_mut(&pod_id).unwrap();
+        self.reload_episodes(pod_id);
+        if self.queue.iter().any(|(id, _)| *id == pod_id) {
+            self.save_queue();
+        }
+        self.push_undo(UndoAction::RemoveEpisodes {
+            pod_id: pod_id,
+            ep_ids: vec![ep_id],
+            files: files,
+        });
+    }
+
+    /// Removes all episodes for a podcast from the list, optionally
+    /// deleting local files first. Starred episodes are kept. This can
+    /// be undone.
+    pub fn remove_all_episodes(&mut self, pod_id: i64, delete_files: bool) {
+        self.load_episodes(pod_id);
+        let mut files = Vec::new();
+        if delete_files {
+            files = self.trash_files(pod_id, true);
+        }
+
+        let podcast = self.podcasts.clone_podcast(pod_id).unwrap();
+        let episodes = podcast.episodes.map(|ep| (ep.id, ep.starred, ep.hidden), false);
+        let mut removed = Vec::new();
+        let mut n_starred = 0;
+        for (ep_id, starred, hidden) in episodes.into_iter() {
+            if starred {
+                n_starred += 1;
+            } else if !hidden {
+                let _ = self.db.hide_episode(ep_id, true);
+                removed.push(ep_id);
+            }
+        }
+        if n_starred > 0 {
+            self.notif_to_ui(format!("Kept {n_starred} starred episodes."), false);
+        }
+        self.reload_episodes(pod_id);
+
+        if self.queue.iter().any(|(id, _)| *id == pod_id) {
+            self.save_queue();
+        }
+        self.push_undo(UndoAction::RemoveEpisodes {
+            pod_id: pod_id,
+            ep_ids: removed,
+            files: files,
+        });
+    }
+
+    /// Puts a hidden (i.e., removed) episode back in the podcast's list.
+    pub fn unhide(&self, pod_id: i64, ep_id: i64) {
+        let title = match self.podcasts.clone_episode(pod_id, ep_id) {
+            Some(ep) => ep.title,
+            None => return,
+        };
+        if self.db.hide_episode(ep_id, false).is_err() {
+            self.notif_to_ui("Error: Could not unhide episode.".to_string(), true);
+            return;
+        }
+        self.reload_episodes(pod_id);
+        self.notif_to_ui(format!("Unhid \"{title}\""), false);
+    }
+
+    /// Returns whether hidden episodes are shown, in which case they are
+    /// loaded along with the rest.
+    fn show_hidden(&self) -> bool {
+        return !matches!(self.filters.hidden, FilterStatus::NegativeCases);
+    }
+
+    /// Re-reads the episodes of a podcast from the database, and updates
+    /// the menus.
+    fn reload_episodes(&self, pod_id: i64) {
+        if let Some(mut podcast) = self.podcasts.clone_podcast(pod_id) {
             podcast.episodes.replace_all(
                 self.db
-                    .get_episodes(pod_id, false)
+                    .get_episodes(pod_id, self.show_hidden())
                     .expect("Error retrieving info from database."),
             );
+            podcast.loaded = true;
+            self.podcasts.replace(pod_id, podcast);
         }
-        self.tx_to_ui
-            .send(MainMessage::UiUpdateMenus)
-            .expect("Thread messaging error");
+        self.update_filters(&self.filters, true);
     }
 
-    /// Removes all episodes for a podcast from the list, optionally
-    /// deleting local files first
-    pub fn remove_all_episodes(&self, pod_id: i64, delete_files: bool) {
-        if delete_files {
-            self.delete_files(pod_id);
+    /// Loads the episodes of a podcast from the database if they have
+    /// not been loaded yet, and filters them. Returns true if they were
+    /// loaded just now.
+    fn load_episodes(&self, pod_id: i64) -> bool {
+        let mut podcast = match self.podcasts.clone_podcast(pod_id) {
+            Some(pod) if !pod.loaded => pod,
+            _ => return false,
+        };
+        let episodes = match self.db.get_episodes(pod_id, self.show_hidden()) {
+            Ok(episodes) => episodes,
+            Err(_) => {
+                self.notif_to_ui("Error retrieving info from database.".to_string(), true);
+                return false;
+            }
+        };
+        podcast.episodes.replace_all(episodes);
+        podcast.loaded = true;
+        let filtered = Self::filter_episodes(&podcast, &self.filters);
+        *podcast.episodes.borrow_filtered_order() = filtered;
+        self.podcasts.replace(pod_id, podcast);
+        return true;
+    }
+
+    /// Re-reads the list of podcasts from the database. Episodes are
+    /// only loaded again for the podcasts that had them loaded before.
+    fn reload_podcasts(&self) {
+        let loaded = self.podcasts.filter_map(|pod| pod.loaded.then_some(pod.id));
+        match self.db.get_podcast_summaries(self.show_hidden()) {
+            Ok(podcasts) => self.podcasts.replace_all(podcasts),
+            Err(_) => {
+                self.notif_to_ui("Error retrieving info from database.".to_string(), true);
+                return;
+            }
+        }
+        for pod_id in loaded.into_iter() {
+            self.load_episodes(pod_id);
         }
+    }
 
-        let mut podcast = self.podcasts.clone_podcast(pod_id).unwrap();
-        podcast.episodes.map(
-            |ep| {
-                let _ = self.db.hide_episode(ep.id, true);
-            },
-            false,
-        );
-        podcast.episodes = LockVec::new(Vec::new());
-        self.podcasts.replace(pod_id, podcast);
+    /// Adds an action to the undo stack. If the stack is full, the
+    /// oldest action is forgotten, and anything it moved to the trash is
+    /// deleted for good.
+    fn push_undo(&mut self, action: UndoAction) {
+        self.undo_stack.push(action);
+        if self.undo_stack.len() > UNDO_LIMIT {
+            let oldest = self.undo_stack.remove(0);
+            self.forget_undo(&oldest);
+        }
+    }
 
-        self.tx_to_ui
-            .send(MainMessage::UiUpdateMenus)
-            .expect("Thread messaging error");
+    /// Permanently deletes what an action moved to the trash.
+    fn forget_undo(&self, action: &UndoAction) {
+        undo::purge_files(action.files());
+        if let UndoAction::RemovePodcast { trash_id, .. } = action {
+            let _ = self.db.remove_trash(*trash_id);
+        }
+    }
+
+    /// Empties the trash table and the trash folder. Nothing in them can
+    /// be undone once the undo stack is gone, i.e., when the program
+    /// starts or quits.
+    pub fn empty_trash(&mut self) {
+        self.undo_stack.clear();
+        let _ = self.db.empty_trash();
+        let _ = undo::empty_trash_dir(&undo::trash_dir(&self.config.download_path));
+    }
+
+    /// Reverses the most recent action on the undo stack.
+    pub fn undo(&mut self) {
+        let action = match self.undo_stack.pop() {
+            Some(action) => action,
+            None => {
+                self.notif_to_ui("Nothing to undo.".to_string(), false);
+                return;
+            }
+        };
+        let mut success = true;
+        let message;
+        match &action {
+            UndoAction::DeleteFiles { pod_id, files } => {
+                success = self.restore_files(files);
+                self.reload_episodes(*pod_id);
+                message = format!("Restored {} deleted files.", files.len());
+            }
+            UndoAction::RemoveEpisodes {
+                pod_id,
+                ep_ids,
+                files,
+            } => {
+                for ep_id in ep_ids.iter() {
+                    success &= self.db.hide_episode(*ep_id, false).is_ok();
+                }
+                success &= self.restore_files(files);
+                self.reload_episodes(*pod_id);
+                message = format!("Restored {} removed episodes.", ep_ids.len());
+            }
+            UndoAction::RemovePodcast {
+                trash_id,
+                title,
+                files,
+            } => {
+                let pod_id = match self.db.restore_podcast(*trash_id) {
+                    Ok(pod_id) => pod_id,
+                    Err(_) => {
+                        // the podcast has been added again since, and
+                        // the trash entry would be of no use any more
+                        self.forget_undo(&action);
+                        self.notif_to_ui(format!("Error: Could not restore {title}."), true);
+                        return;
+                    }
+                };
+                success = self.restore_files(files);
+                match self.db.get_podcast_summary(pod_id, self.show_hidden()) {
+                    Ok(podcast) => self.podcasts.add_podcast(podcast),
+                    Err(_) => success = false,
+                }
+                if let Ok(queue) = self.db.get_queue() {
+                    self.queue = queue;
+                    self.send_queue();
+                }
+                self.update_filters(&self.filters, true);
+                message = format!("Restored {title}.");
+            }
+            UndoAction::MarkAllPlayed {
+                pod_id,
+                played,
+                actions,
+            } => {
+                for (ep_id, was_played) in played.iter() {
+                    success &= self.db.set_played_status(*ep_id, *was_played).is_ok();
+                }
+                // actions that haven't been synced yet are dropped; for
+                // those that have, the server is told about the undo
+                for (ep_id, action_id) in actions.iter() {
+                    match self.db.remove_episode_action(*action_id) {
+                        Ok(true) => (),
+                        Ok(false) => {
+                            let was_played = played.iter().any(|(id, p)| id == ep_id && *p);
+                            let duration = self
+                                .podcasts
+                                .clone_episode(*pod_id, *ep_id)
+                                .and_then(|ep| ep.duration);
+                            self.record_played(*ep_id, duration, was_played);
+                        }
+                        Err(_) => success = false,
+                    }
+                }
+                self.reload_episodes(*pod_id);
+                message = "Restored played status of episodes.".to_string();
+            }
+        }
+        if success {
+            self.notif_to_ui(message, false);
+        } else {
+            self.notif_to_ui("Error: Could not undo everything.".to_string(), true);
+        }
+    }
+
+    /// Moves files from the trash folder back to where they were, and
+    /// adds them back to the database. Returns false if any of them
+    /// could not be restored.
+    fn restore_files(&self, files: &[TrashedFile]) -> bool {
+        let mut success = true;
+        for file in files.iter() {
+            if undo::restore_file(file).is_err() || self.db.insert_file(file.ep_id, &file.path).is_err() {
+                success = false;
+            }
+        }
+        return success;
     }
 
     /// Updates the user-selected filters to show only played/unplayed
     /// or downloaded/not downloaded episodes.
-    pub fn update_filters(&self, filters: Filters, update_menus: bool) {
+    pub fn update_filters(&self, filters: &Filters, update_menus: bool) {
+        // podcasts whose episodes have not been loaded are checked in
+        // the database instead, which is only needed if the filters
+        // apply to episodes
+        let any_unloaded = self.podcasts.map(|pod| !pod.loaded, false).contains(&true);
+        let unloaded_filter = if any_unloaded && filters.filters_episodes() {
+            self.db.get_filtered_podcast_ids(filters).ok()
+        } else {
+            None
+        };
         {
             let (pod_map, pod_order, mut pod_filtered_order) = self.podcasts.borrow();
             let mut new_filtered_pods = Vec::new();
             for pod_id in pod_order.iter() {
                 let pod = pod_map.get(pod_id).unwrap();
-                let new_filter = pod.episodes.filter_map(|ep| {
-                    let play_filter = match filters.played {
-                        FilterStatus::All => false,
-                        FilterStatus::PositiveCases => !ep.is_played(),
-                        FilterStatus::NegativeCases => ep.is_played(),
-                    };
-                    let download_filter = match filters.downloaded {
-                        FilterStatus::All => false,
-                        FilterStatus::PositiveCases => ep.path.is_none(),
-                        FilterStatus::NegativeCases => ep.path.is_some(),
-                    };
-                    if !(play_filter | download_filter) {
-                        return Some(ep.id);
-                    } else {
-                        return None;
+                let has_episodes = if pod.loaded {
+                    let new_filter = Self::filter_episodes(pod, filters);
+                    let has_episodes = !new_filter.is_empty();
+                    let mut filtered_order = pod.episodes.borrow_filtered_order();
+                    *filtered_order = new_filter;
+                    has_episodes
+                } else {
+                    match &unloaded_filter {
+                        Some(ids) => ids.contains(&pod.id),
+                        None => pod.counts.total > 0,
                     }
-                });
-                if !new_filter.is_empty() {
+                };
+                let tag_filter = match &filters.tag {
+                    Some(tag) => !pod.has_tag(tag),
+                    None => false,
+                };
+                let archive_filter = match filters.archived {
+                    FilterStatus::All => false,
+                    FilterStatus::PositiveCases => !pod.archived,
+                    FilterStatus::NegativeCases => pod.archived,
+                };
+                if has_episodes && !tag_filter && !archive_filter {
                     new_filtered_pods.push(pod.id);
                 }
-                let mut filtered_order = pod.episodes.borrow_filtered_order();
-                *filtered_order = new_filter;
             }
             *pod_filtered_order = new_filtered_pods;
         }
@@ -772,4 +2077,384 @@ impl MainController {
                 .expect("Thread messaging error");
         }
     }
+
+    /// Returns the ids of the episodes of a loaded podcast that pass
+    /// the played, downloaded, starred, and hidden filters, in order.
+    fn filter_episodes(pod: &Podcast, filters: &Filters) -> Vec<i64> {
+        return pod.episodes.filter_map(|ep| {
+            let play_filter = match filters.played {
+                FilterStatus::All => false,
+                FilterStatus::PositiveCases => !ep.is_played(),
+                FilterStatus::NegativeCases => ep.is_played(),
+            };
+            let download_filter = match filters.downloaded {
+                FilterStatus::All => false,
+                FilterStatus::PositiveCases => ep.path.is_none(),
+                FilterStatus::NegativeCases => ep.path.is_some(),
+            };
+            let star_filter = match filters.starred {
+                FilterStatus::All => false,
+                FilterStatus::PositiveCases => !ep.starred,
+                FilterStatus::NegativeCases => ep.starred,
+            };
+            let hidden_filter = match filters.hidden {
+                FilterStatus::All => false,
+                FilterStatus::PositiveCases => !ep.hidden,
+                FilterStatus::NegativeCases => ep.hidden,
+            };
+            if !(play_filter | download_filter | star_filter | hidden_filter) {
+                return Some(ep.id);
+            } else {
+                return None;
+            }
+        });
+    }
+}
+
+
+// TESTS -----------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{GpodderConfig, SyncApi};
+    use crate::keymap::UserAction;
+    use crate::test_helpers::{make_feed, temp_dir};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    /// Sets up a controller on a fresh database in `dir`, without the
+    /// UI. Messages meant for the UI go to the returned receiver.
+    fn make_controller(dir: &Path) -> (MainController, mpsc::Receiver<MainMessage>) {
+        return make_controller_with(dir, "");
+    }
+
+    /// Sets up a controller as `make_controller()` does, with `settings`
+    /// added to its config file.
+    fn make_controller_with(
+        dir: &Path,
+        settings: &str,
+    ) -> (MainController, mpsc::Receiver<MainMessage>) {
+        let config_path = dir.join("config.toml");
+        std::fs::write(
+            &config_path,
+            format!(
+                "download_path = {:?}\n{settings}",
+                dir.join("downloads").to_string_lossy()
+            ),
+        )
+        .unwrap();
+        let config = Config::new(&config_path).unwrap();
+        let db_inst = Database::connect(dir).unwrap();
+        let (tx_to_ui, rx_from_main) = mpsc::channel();
+        let (tx_to_main, rx_to_main) = mpsc::channel();
+        let controller = MainController {
+            threadpool: Threadpool::new(config.simultaneous_downloads),
+            config: config,
+            podcasts: LockVec::new(db_inst.get_podcast_summaries(false).unwrap()),
+            queue: db_inst.get_queue().unwrap(),
+            db_version: db_inst.data_version().unwrap(),
+            db: db_inst,
+            filters: Filters::default(),
+            ui_thread: thread::spawn(|| ()),
+            sync_counter: 0,
+            sync_tracker: Vec::new(),
+            download_tracker: HashSet::new(),
+            player: None,
+            now_playing: None,
+            player_counter: 0,
+            play_sessions: AHashMap::new(),
+            sleep_timer: None,
+            sleep_timer_counter: 0,
+            stopped_by_timer: None,
+            undo_stack: Vec::new(),
+            tx_to_ui: tx_to_ui,
+            tx_to_main: tx_to_main,
+            rx_to_main: rx_to_main,
+        };
+        return (controller, rx_from_main);
+    }
+
+    /// Adds a podcast with the given episodes to the controller's
+    /// database and reloads its list of podcasts. Returns the podcast
+    /// id and the episode ids, in the order given. Episodes are given
+    /// newest first, so their ids are in descending order.
+    fn add_podcast(controller: &mut MainController, episodes: &[&str]) -> (i64, Vec<i64>) {
+        let mut feed = make_feed("Show", &episodes.iter().map(|ep| (*ep, "")).collect::<Vec<_>>());
+        for ep in feed.episodes.iter_mut() {
+            ep.duration = Some(1000);
+        }
+        let result = controller.db.insert_podcast(feed).unwrap();
+        // episodes are added oldest first
+        let ep_ids = result.added.iter().rev().map(|ep| ep.id).collect();
+        controller.podcasts.replace_all(controller.db.get_podcast_summaries(false).unwrap());
+        return (result.pod_id, ep_ids);
+    }
+
+    /// Returns the ids of the podcast's episodes that pass the filters,
+    /// sorted by id, as the episodes have no dates to order them by.
+    fn shown(controller: &MainController, pod_id: i64) -> Vec<i64> {
+        let podcast = controller.podcasts.clone_podcast(pod_id).unwrap();
+        let mut ids = podcast.episodes.map(|ep| ep.id, true);
+        ids.sort_unstable();
+        return ids;
+    }
+
+    /// Writes a file for each episode in the downloads folder and adds
+    /// it to the database. Returns the paths.
+    fn download(controller: &MainController, ep_ids: &[i64]) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        for ep_id in ep_ids.iter() {
+            let path = controller.config.download_path.join("Show").join(format!("{ep_id}.mp3"));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, b"audio").unwrap();
+            controller.db.insert_file(*ep_id, &path).unwrap();
+            paths.push(path);
+        }
+        return paths;
+    }
+
+    /// Plays an episode with the configured play command, and returns
+    /// where playback started.
+    fn play(controller: &mut MainController, pod_id: i64, ep_id: i64) -> i64 {
+        let counter = controller.player_counter;
+        controller.play_file(pod_id, ep_id, None);
+        assert_eq!(controller.player_counter, counter + 1);
+        return controller.play_sessions[&controller.player_counter].1;
+    }
+
+    /// Handles the player exiting at `position` seconds into the
+    /// episode, and returns whether that finished it.
+    fn stop(controller: &MainController, pod_id: i64, ep_id: i64, position: i64) -> bool {
+        return controller.playback_stopped(&PlaybackEnd {
+            pod_id: pod_id,
+            ep_id: ep_id,
+            success: true,
+            exit_code: Some(0),
+            position: Some((position, Some(1000))),
+        });
+    }
+
+    fn queued(controller: &MainController) -> Vec<i64> {
+        let ids: Vec<i64> = controller.queue.iter().map(|(_, ep_id)| *ep_id).collect();
+        let saved: Vec<i64> =
+            controller.db.get_queue().unwrap().iter().map(|(_, ep_id)| *ep_id).collect();
+        assert_eq!(ids, saved);
+        return ids;
+    }
+
+    #[test]
+    fn enqueue_and_move() {
+        let dir = temp_dir("controller-queue");
+        let (mut controller, _rx) = make_controller(&dir);
+        let (pod_id, ep) = add_podcast(&mut controller, &["One", "Two", "Three"]);
+
+        controller.enqueue(pod_id, ep[0], false);
+        controller.enqueue(pod_id, ep[1], false);
+        controller.enqueue(pod_id, ep[2], true);
+        assert_eq!(queued(&controller), vec![ep[2], ep[0], ep[1]]);
+        // enqueuing again moves the episode rather than adding it twice
+        controller.enqueue(pod_id, ep[2], false);
+        assert_eq!(queued(&controller), vec![ep[0], ep[1], ep[2]]);
+
+        // the first and last episodes can't move any further
+        controller.move_in_queue(ep[0], -1);
+        controller.move_in_queue(ep[2], 1);
+        assert_eq!(queued(&controller), vec![ep[0], ep[1], ep[2]]);
+        controller.move_in_queue(ep[0], 1);
+        assert_eq!(queued(&controller), vec![ep[1], ep[0], ep[2]]);
+        // offsets past either end stop there
+        controller.move_in_queue(ep[1], 10);
+        assert_eq!(queued(&controller), vec![ep[0], ep[2], ep[1]]);
+        controller.move_in_queue(ep[1], -10);
+        assert_eq!(queued(&controller), vec![ep[1], ep[0], ep[2]]);
+        // episodes that aren't queued are ignored
+        controller.dequeue(ep[2]);
+        controller.move_in_queue(ep[2], -1);
+        controller.move_in_queue(-1, 1);
+        assert_eq!(queued(&controller), vec![ep[1], ep[0]]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn star_and_filter() {
+        let dir = temp_dir("controller-star");
+        let (mut controller, _rx) = make_controller(&dir);
+        let (pod_id, ep) = add_podcast(&mut controller, &["One", "Two", "Three"]);
+        controller.load_episodes(pod_id);
+
+        controller.star(pod_id, ep[1], true);
+        assert!(controller.podcasts.clone_episode(pod_id, ep[1]).unwrap().starred);
+        let saved = controller.db.get_episodes(pod_id, false).unwrap();
+        let starred: Vec<i64> = saved.iter().filter(|e| e.starred).map(|e| e.id).collect();
+        assert_eq!(starred, vec![ep[1]]);
+
+        // "4" cycles between starred episodes only and all of them
+        let key = KeyEvent::new(KeyCode::Char('4'), KeyModifiers::NONE);
+        assert_eq!(
+            controller.config.keybindings.get_from_input(key),
+            Some(&UserAction::FilterStarred)
+        );
+        controller.change_filter(FilterType::Starred);
+        assert_eq!(shown(&controller, pod_id), vec![ep[1]]);
+        controller.star(pod_id, ep[2], true);
+        assert_eq!(shown(&controller, pod_id), vec![ep[2], ep[1]]);
+        controller.star(pod_id, ep[1], false);
+        assert_eq!(shown(&controller, pod_id), vec![ep[2]]);
+        controller.change_filter(FilterType::Starred);
+        assert_eq!(shown(&controller, pod_id), vec![ep[2], ep[1], ep[0]]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn starred_episodes_are_kept() {
+        let dir = temp_dir("controller-keep-starred");
+        let (mut controller, _rx) = make_controller(&dir);
+        let (pod_id, ep) = add_podcast(&mut controller, &["One", "Two", "Three"]);
+        let paths = download(&controller, &ep);
+        controller.load_episodes(pod_id);
+        controller.star(pod_id, ep[0], true);
+
+        controller.delete_files(pod_id, true);
+        assert!(paths[0].exists());
+        assert!(!paths[1].exists() && !paths[2].exists());
+        // deleted files wait in the trash, inside the download folder
+        let trash = undo::trash_dir(&controller.config.download_path);
+        assert_eq!(std::fs::read_dir(&trash).unwrap().count(), 2);
+        let episodes = controller.db.get_episodes(pod_id, false).unwrap();
+        let downloaded: Vec<i64> =
+            episodes.iter().filter(|e| e.path.is_some()).map(|e| e.id).collect();
+        assert_eq!(downloaded, vec![ep[0]]);
+
+        controller.remove_all_episodes(pod_id, true);
+        assert!(paths[0].exists());
+        assert_eq!(shown(&controller, pod_id), vec![ep[0]]);
+        let episodes = controller.db.get_episodes(pod_id, false).unwrap();
+        assert_eq!(episodes.iter().map(|e| e.id).collect::<Vec<i64>>(), vec![ep[0]]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn undo_mark_all_played() {
+        let dir = temp_dir("controller-undo-played");
+        let (mut controller, _rx) = make_controller(&dir);
+        controller.config.gpodder = Some(GpodderConfig {
+            server: "http://localhost".to_string(),
+            username: "alice".to_string(),
+            password: "secret".to_string(),
+            device: "shellcaster".to_string(),
+            api: SyncApi::Gpodder,
+        });
+        let (pod_id, ep) = add_podcast(&mut controller, &["One", "Two", "Three"]);
+        controller.load_episodes(pod_id);
+        controller.mark_played(pod_id, ep[0], true);
+        controller.db.remove_episode_actions(i64::MAX).unwrap();
+
+        // actions that haven't been synced are dropped
+        controller.mark_all_played(pod_id, true);
+        assert_eq!(controller.db.get_episode_actions().unwrap().len(), 2);
+        controller.undo();
+        assert!(controller.db.get_episode_actions().unwrap().is_empty());
+        let is_played = |ep_id: i64| controller.podcasts.clone_episode(pod_id, ep_id).unwrap().played;
+        assert!(is_played(ep[0]) && !is_played(ep[1]) && !is_played(ep[2]));
+
+        // once they have been synced, the server is told about the undo
+        controller.mark_all_played(pod_id, true);
+        controller.db.remove_episode_actions(i64::MAX).unwrap();
+        controller.undo();
+        let actions = controller.db.get_episode_actions().unwrap();
+        assert_eq!(actions.len(), 2);
+        assert!(actions.iter().all(|(_, action)| action.position == Some(0)));
+        assert!(actions.iter().all(|(_, action)| !action.episode.ends_with("One.mp3")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn hide_and_unhide() {
+        let dir = temp_dir("controller-hidden");
+        let (mut controller, _rx) = make_controller(&dir);
+        let (pod_id, ep) = add_podcast(&mut controller, &["One", "Two", "Three"]);
+        for ep_id in ep.iter() {
+            controller.enqueue(pod_id, *ep_id, false);
+        }
+
+        // removed episodes are dropped from the queue
+        controller.remove_episode(pod_id, ep[1], false);
+        assert_eq!(shown(&controller, pod_id), vec![ep[2], ep[0]]);
+        assert_eq!(queued(&controller), vec![ep[0], ep[2]]);
+
+        // the filter goes from visible to all, to hidden only, and back
+        assert!(matches!(controller.filters.hidden, FilterStatus::NegativeCases));
+        controller.change_filter(FilterType::Hidden);
+        assert!(matches!(controller.filters.hidden, FilterStatus::All));
+        assert_eq!(shown(&controller, pod_id), vec![ep[2], ep[1], ep[0]]);
+        controller.change_filter(FilterType::Hidden);
+        assert!(matches!(controller.filters.hidden, FilterStatus::PositiveCases));
+        assert_eq!(shown(&controller, pod_id), vec![ep[1]]);
+
+        controller.unhide(pod_id, ep[1]);
+        assert!(!controller.podcasts.clone_episode(pod_id, ep[1]).unwrap().hidden);
+        assert!(shown(&controller, pod_id).is_empty());
+        controller.change_filter(FilterType::Hidden);
+        assert!(matches!(controller.filters.hidden, FilterStatus::NegativeCases));
+        assert_eq!(shown(&controller, pod_id), vec![ep[2], ep[1], ep[0]]);
+        // unhiding doesn't put an episode back in the queue
+        assert_eq!(queued(&controller), vec![ep[0], ep[2]]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resume_after_launch() {
+        // episodes are marked as played as soon as they start by default
+        let dir = temp_dir("controller-resume");
+        let (mut controller, _rx) = make_controller_with(&dir, "play_command = \"true\"\n");
+        assert!(controller.config.mark_as_played == MarkPlayed::Launch);
+        let (pod_id, ep) = add_podcast(&mut controller, &["One"]);
+
+        assert_eq!(play(&mut controller, pod_id, ep[0]), 0);
+        assert!(!stop(&controller, pod_id, ep[0], 300));
+        let episode = controller.podcasts.clone_episode(pod_id, ep[0]).unwrap();
+        assert!(episode.played);
+        assert!(episode.in_progress());
+        assert_eq!(play(&mut controller, pod_id, ep[0]), 300);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resume_until_threshold() {
+        let dir = temp_dir("controller-threshold");
+        let (mut controller, _rx) = make_controller_with(
+            &dir,
+            "play_command = \"true\"\nmark_as_played = \"position\"\nplayed_threshold = 90\n",
+        );
+        let (pod_id, ep) = add_podcast(&mut controller, &["One", "Two"]);
+        let saved = |controller: &MainController, ep_id: i64| {
+            let episode = controller.podcasts.clone_episode(pod_id, ep_id).unwrap();
+            return (episode.position, episode.played);
+        };
+
+        // below the threshold, where playback stopped is saved
+        assert_eq!(play(&mut controller, pod_id, ep[0]), 0);
+        assert!(!stop(&controller, pod_id, ep[0], 850));
+        assert_eq!(saved(&controller, ep[0]), (850, false));
+        assert_eq!(play(&mut controller, pod_id, ep[0]), 850);
+        // past it, the episode is finished and starts over next time
+        assert!(stop(&controller, pod_id, ep[0], 900));
+        assert_eq!(saved(&controller, ep[0]), (0, true));
+        assert_eq!(play(&mut controller, pod_id, ep[0]), 0);
+
+        // skipping the outro counts the episode as ending that much
+        // earlier, and skipping the intro starts it later
+        controller.set_podcast_settings(pod_id, PodcastSettings {
+            speed: 1.0,
+            skip_intro: 30,
+            skip_outro: 100,
+        });
+        assert_eq!(play(&mut controller, pod_id, ep[1]), 30);
+        assert!(!stop(&controller, pod_id, ep[1], 800));
+        assert_eq!(saved(&controller, ep[1]), (800, false));
+        assert_eq!(play(&mut controller, pod_id, ep[1]), 800);
+        assert!(stop(&controller, pod_id, ep[1], 850));
+        assert_eq!(saved(&controller, ep[1]), (0, true));
+        assert_eq!(play(&mut controller, pod_id, ep[1]), 30);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,106 @@
+use crate::play_file::parse_timestamp;
+use crate::types::*;
+
+/// Writes the user's notes as a Markdown document, with a heading for
+/// each podcast and each episode that has notes, and the notes listed
+/// under their episode. `podcasts` holds the title and episodes of each
+/// podcast, in the order they should appear.
+pub fn to_markdown(podcasts: &[(String, Vec<Episode>)]) -> String {
+    let mut out = "# Podcast notes\n".to_string();
+    for (pod_title, episodes) in podcasts.iter() {
+        let episodes: Vec<&Episode> = episodes.iter().filter(|ep| !ep.notes.is_empty()).collect();
+        if episodes.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("\n## {pod_title}\n"));
+        for ep in episodes {
+            match ep.pubdate {
+                Some(pd) => out.push_str(&format!("\n### {} ({})\n\n", ep.title, pd.format("%F"))),
+                None => out.push_str(&format!("\n### {}\n\n", ep.title)),
+            }
+            for note in ep.notes.iter() {
+                // keep notes that span several lines inside their item
+                let text = note.format().replace('\n', "\n  ");
+                out.push_str(&format!("- {}\n", text.trim_end()));
+            }
+        }
+    }
+    return out;
+}
+
+/// Splits a note typed by the user into the time in the episode it
+/// refers to, if it starts with one, and the text of the note. A dash
+/// between the time and the text is dropped, so "42:10 – big claim"
+/// is read as a note at 2530 seconds saying "big claim".
+pub fn parse_note(input: &str) -> (Option<i64>, String) {
+    let input = input.trim();
+    let (first, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    return match parse_timestamp(first) {
+        Some(position) => {
+            let text = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '-' || c == '–');
+            (Some(position), text.to_string())
+        }
+        None => (None, input.to_string()),
+    };
+}
+
+
+// TESTS -----------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::make_episode;
+    use chrono::{TimeZone, Utc};
+
+    fn make_note(id: i64, position: Option<i64>, text: &str) -> Note {
+        return Note {
+            id: id,
+            ep_id: 1,
+            position: position,
+            text: text.to_string(),
+            created: Utc.ymd(2021, 3, 1).and_hms(12, 0, 0),
+        };
+    }
+
+    fn make_notes_episode(id: i64, title: &str, notes: Vec<Note>) -> Episode {
+        return Episode {
+            pubdate: Some(Utc.ymd(2021, 2, 14).and_hms(8, 0, 0)),
+            notes: notes,
+            ..make_episode(id, 1, title)
+        };
+    }
+
+    #[test]
+    fn markdown_export() {
+        let podcasts = vec![
+            ("Research Hour".to_string(), vec![
+                make_notes_episode(1, "Claims", vec![
+                    make_note(1, Some(2530), "interesting claim about X"),
+                    make_note(2, Some(4000), ""),
+                    make_note(3, None, "Check the sources\nin the show notes"),
+                ]),
+                make_notes_episode(2, "No notes here", Vec::new()),
+            ]),
+            ("Quiet Podcast".to_string(), vec![make_notes_episode(3, "Silence", Vec::new())]),
+        ];
+        assert_eq!(
+            to_markdown(&podcasts),
+            "# Podcast notes\n\
+            \n## Research Hour\n\
+            \n### Claims (2021-02-14)\n\n\
+            - [42:10] interesting claim about X\n\
+            - [1:06:40]\n\
+            - Check the sources\n  in the show notes\n"
+        );
+    }
+
+    #[test]
+    fn parse_notes() {
+        assert_eq!(
+            parse_note("42:10 – interesting claim about X"),
+            (Some(2530), "interesting claim about X".to_string())
+        );
+        assert_eq!(parse_note(" 1:02:03 "), (Some(3723), String::new()));
+        assert_eq!(parse_note("10:30am meeting"), (None, "10:30am meeting".to_string()));
+    }
+}
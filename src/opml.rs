@@ -1,4 +1,6 @@
 use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+
 use chrono::Utc;
 use opml::{Body, Head, Outline, OPML};
 
@@ -6,37 +8,85 @@ use crate::feeds::PodcastFeed;
 use crate::types::*;
 
 /// Import a list of podcast feeds from an OPML file. Supports
-/// v1.0, v1.1, and v2.0 OPML files.
+/// v1.0, v1.1, and v2.0 OPML files. Tags are read from the `category`
+/// attribute of each feed, as a comma-separated list, and from the
+/// folders (outlines without a feed of their own) that feeds are in.
 pub fn import(xml: String) -> Result<Vec<PodcastFeed>> {
     return match OPML::from_str(&xml) {
         Err(err) => Err(anyhow!(err)),
         Ok(opml) => {
             let mut feeds = Vec::new();
-            for pod in opml.body.outlines.into_iter() {
-                if pod.xml_url.is_some() {
-                    // match against title attribute first -- if this is
-                    // not set or empty, then match against the text
-                    // attribute; this must be set, but can be empty
-                    let temp_title = pod.title.filter(|t| !t.is_empty());
-                    let title = match temp_title {
-                        Some(t) => Some(t),
-                        None => {
-                            if pod.text.is_empty() {
-                                None
-                            } else {
-                                Some(pod.text)
-                            }
-                        }
-                    };
-                    feeds.push(PodcastFeed::new(None, pod.xml_url.unwrap(), title));
-                }
-            }
+            import_outlines(opml.body.outlines, "", &mut feeds);
             Ok(feeds)
         }
     };
 }
 
-/// Converts the current set of podcast feeds to the OPML format
+/// Adds the feeds among `outlines` to `feeds`, going into folders as
+/// well. Feeds in a folder are tagged with its path within the file,
+/// e.g., "News/Daily", matching how categories are written as paths. A
+/// feed that appears in more than one folder is added once, with all of
+/// their tags. Feeds nested within another feed's outline are added as
+/// if they were next to it, in the same folder.
+fn import_outlines(outlines: Vec<Outline>, folder: &str, feeds: &mut Vec<PodcastFeed>) {
+    for pod in outlines.into_iter() {
+        // match against title attribute first -- if this is not set or
+        // empty, then match against the text attribute; this must be
+        // set, but can be empty
+        let temp_title = pod.title.filter(|t| !t.is_empty());
+        let title = match temp_title {
+            Some(t) => Some(t),
+            None => {
+                if pod.text.is_empty() {
+                    None
+                } else {
+                    Some(pod.text)
+                }
+            }
+        };
+
+        match pod.xml_url {
+            Some(xml_url) => {
+                let mut tags = match pod.category {
+                    Some(category) => parse_tags(&category),
+                    None => Vec::new(),
+                };
+                if !folder.is_empty() {
+                    tags.push(folder.to_string());
+                }
+                let feed = match feeds.iter_mut().position(|feed| feed.url == xml_url) {
+                    Some(index) => &mut feeds[index],
+                    None => {
+                        feeds.push(PodcastFeed::new(None, xml_url, title));
+                        feeds.last_mut().unwrap()
+                    }
+                };
+                feed.tags.extend(tags);
+                feed.tags.sort();
+                feed.tags.dedup();
+                import_outlines(pod.outlines, folder, feeds);
+            }
+            None => {
+                let name = title.unwrap_or_default();
+                let name = name.trim().trim_matches('/').trim();
+                let path = if name.is_empty() {
+                    folder.to_string()
+                } else if folder.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{folder}/{name}")
+                };
+                import_outlines(pod.outlines, &path, feeds);
+            }
+        }
+    }
+}
+
+/// Converts the current set of podcast feeds to the OPML format. Tags
+/// are written to the `category` attribute, separated by commas. Each
+/// podcast with tags is also put in a folder named after its first tag,
+/// with tags such as "News/Daily" making nested folders, so that other
+/// podcast apps can group them.
 pub fn export(podcasts: Vec<Podcast>) -> OPML {
     let date = Utc::now();
     let mut opml = OPML {
@@ -48,21 +98,198 @@ pub fn export(podcasts: Vec<Podcast>) -> OPML {
         ..Default::default()
     };
 
-    let mut outlines = Vec::new();
+    let mut root = Folder::default();
 
     for pod in podcasts.iter() {
         // opml.add_feed(&pod.title, &pod.url);
-        outlines.push(Outline {
+        let outline = Outline {
             text: pod.title.clone(),
             r#type: Some("rss".to_string()),
             xml_url: Some(pod.url.clone()),
             title: Some(pod.title.clone()),
+            category: if pod.tags.is_empty() {
+                None
+            } else {
+                Some(pod.tags.join(","))
+            },
             ..Outline::default()
-        });
+        };
+        let mut folder = &mut root;
+        if let Some(tag) = pod.tags.first() {
+            for name in tag.split('/').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+                folder = folder.folders.entry(name.to_string()).or_default();
+            }
+        }
+        folder.feeds.push(outline);
     }
 
     opml.body = Body {
-        outlines: outlines,
+        outlines: root.into_outlines(),
     };
     return opml;
 }
+
+/// A folder of feeds being exported, along with the folders inside it,
+/// in alphabetical order.
+#[derive(Default)]
+struct Folder {
+    folders: BTreeMap<String, Folder>,
+    feeds: Vec<Outline>,
+}
+
+impl Folder {
+    /// Converts the folder's contents to outlines, with its folders
+    /// ahead of its feeds.
+    fn into_outlines(self) -> Vec<Outline> {
+        let mut outlines: Vec<Outline> = self
+            .folders
+            .into_iter()
+            .map(|(name, folder)| Outline {
+                text: name.clone(),
+                title: Some(name),
+                outlines: folder.into_outlines(),
+                ..Outline::default()
+            })
+            .collect();
+        outlines.extend(self.feeds);
+        return outlines;
+    }
+}
+
+// TESTS -----------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::make_podcast;
+
+    fn make_tagged_podcast(title: &str, url: &str, tags: &[&str]) -> Podcast {
+        return Podcast {
+            url: url.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..make_podcast(0, title, Vec::new())
+        };
+    }
+
+    #[test]
+    fn tags_round_trip() {
+        let podcasts = vec![
+            make_tagged_podcast("Daily News", "http://example.com/news", &["daily", "news"]),
+            make_tagged_podcast("Untagged", "http://example.com/other", &[]),
+        ];
+        let xml = export(podcasts).to_string().unwrap();
+        let feeds = import(xml).unwrap();
+        assert_eq!(feeds.len(), 2);
+        assert_eq!(feeds[0].url, "http://example.com/news");
+        assert_eq!(feeds[0].title, Some("Daily News".to_string()));
+        assert_eq!(feeds[0].tags, vec!["daily".to_string(), "news".to_string()]);
+        assert!(feeds[1].tags.is_empty());
+    }
+
+    #[test]
+    fn import_category_paths() {
+        let xml = r#"<?xml version="1.0"?>
+            <opml version="2.0">
+                <head><title>Feeds</title></head>
+                <body>
+                    <outline text="Tech" type="rss" xmlUrl="http://example.com/tech"
+                        category="/Tech, /Science/Space,, tech" />
+                </body>
+            </opml>"#;
+        let feeds = import(xml.to_string()).unwrap();
+        assert_eq!(feeds[0].tags, vec![
+            "Science/Space".to_string(),
+            "Tech".to_string(),
+            "tech".to_string()
+        ]);
+    }
+
+    #[test]
+    fn import_feeds_nested_in_feeds() {
+        let xml = r#"<?xml version="1.0"?>
+            <opml version="2.0">
+                <head><title>Feeds</title></head>
+                <body>
+                    <outline text="News">
+                        <outline text="Daily" type="rss" xmlUrl="http://example.com/daily">
+                            <outline text="Weekly" type="rss" xmlUrl="http://example.com/weekly" />
+                        </outline>
+                    </outline>
+                </body>
+            </opml>"#;
+        let feeds: Vec<(String, Vec<String>)> = import(xml.to_string())
+            .unwrap()
+            .into_iter()
+            .map(|feed| (feed.url, feed.tags))
+            .collect();
+        assert_eq!(feeds, vec![
+            ("http://example.com/daily".to_string(), vec!["News".to_string()]),
+            ("http://example.com/weekly".to_string(), vec!["News".to_string()]),
+        ]);
+    }
+
+    fn import_file(path: &str) -> Vec<(String, Vec<String>)> {
+        let xml = std::fs::read_to_string(path).unwrap();
+        return import(xml)
+            .unwrap()
+            .into_iter()
+            .map(|feed| (feed.url, feed.tags))
+            .collect();
+    }
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        return tags.iter().map(|t| t.to_string()).collect();
+    }
+
+    #[test]
+    fn import_nested_folders() {
+        let feeds = import_file("./tests/opml_nested.xml");
+        assert_eq!(feeds, vec![
+            ("http://example.com/morning".to_string(), tags(&["News/Daily"])),
+            ("http://example.com/evening".to_string(), tags(&["News/Daily"])),
+            ("http://example.com/weekly".to_string(), tags(&["News"])),
+            ("http://example.com/space".to_string(), tags(&["Science"])),
+        ]);
+    }
+
+    #[test]
+    fn import_mixed_folders() {
+        // feeds in more than one folder are only added once
+        let feeds = import_file("./tests/opml_mixed.xml");
+        assert_eq!(feeds, vec![
+            ("http://example.com/loose".to_string(), Vec::new()),
+            ("http://example.com/funny".to_string(), tags(&["Comedy", "Favourites"])),
+            ("http://example.com/techcomedy".to_string(), tags(&["Comedy", "Tech"])),
+            ("http://example.com/gadgets".to_string(), tags(&["Tech"])),
+            ("http://example.com/loose2".to_string(), Vec::new()),
+        ]);
+    }
+
+    #[test]
+    fn export_nested_folders() {
+        let podcasts = vec![
+            make_tagged_podcast("Morning Briefing", "http://example.com/morning", &["News/Daily"]),
+            make_tagged_podcast("Space Talk", "http://example.com/space", &["Science", "Space"]),
+            make_tagged_podcast("Untagged", "http://example.com/other", &[]),
+            make_tagged_podcast("Weekly Review", "http://example.com/weekly", &["News"]),
+        ];
+        let opml = export(podcasts);
+        let outlines = &opml.body.outlines;
+        assert_eq!(outlines.len(), 3);
+        assert_eq!(outlines[0].text, "News");
+        assert_eq!(outlines[0].outlines[0].text, "Daily");
+        assert_eq!(
+            outlines[0].outlines[0].outlines[0].xml_url.as_deref(),
+            Some("http://example.com/morning")
+        );
+        assert_eq!(outlines[0].outlines[1].text, "Weekly Review");
+        assert_eq!(outlines[1].text, "Science");
+        assert_eq!(outlines[2].text, "Untagged");
+
+        // the folders come back as the same tags
+        let feeds = import(opml.to_string().unwrap()).unwrap();
+        assert_eq!(feeds[0].tags, tags(&["News/Daily"]));
+        assert_eq!(feeds[1].tags, tags(&["News"]));
+        assert_eq!(feeds[2].tags, tags(&["Science", "Space"]));
+        assert!(feeds[3].tags.is_empty());
+    }
+}
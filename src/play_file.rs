@@ -1,27 +1,628 @@
 use anyhow::{anyhow, Result};
-use std::process::{Command, Stdio};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// How many bytes at the end of the player's output are kept around to
+/// look for its last position.
+const OUTPUT_TAIL: usize = 4096;
+
+lazy_static! {
+    /// Regex for mpv's status line, e.g. "(Paused) A: 00:12:34 / 01:00:00 (20%)"
+    static ref RE_STATUS_LINE: Regex =
+        Regex::new(r"^(?:\([A-Za-z ]+\) )*(?:A|V|AV): (\S+) / (\S+)(?: |$)").expect("Regex error.");
+}
+
+/// Data about the episode being played, used to fill in the
+/// placeholders in the play command. `target` is the local file if the
+/// episode has been downloaded, or the URL otherwise. `start` is where
+/// playback should start, in seconds, and `speed` and `skip_outro` come
+/// from the podcast's settings.
+#[derive(Debug, Clone, Default)]
+pub struct PlayData {
+    pub target: String,
+    pub title: String,
+    pub pod_title: String,
+    pub url: String,
+    pub path: Option<String>,
+    pub duration: Option<i64>,
+    pub start: i64,
+    pub speed: f64,
+    pub skip_outro: i64,
+}
+
+impl PlayData {
+    /// Returns the value for a placeholder in the play command, e.g.,
+    /// 't' for "%t", or None if it is not a valid placeholder.
+    fn placeholder(&self, key: char) -> Option<String> {
+        return match key {
+            's' => Some(self.target.clone()),
+            't' => Some(self.title.clone()),
+            'p' => Some(self.pod_title.clone()),
+            'u' => Some(self.url.clone()),
+            'f' => Some(self.path.clone().unwrap_or_default()),
+            'd' => Some(self.duration.map(|d| d.to_string()).unwrap_or_default()),
+            'b' => Some(self.start.to_string()),
+            'r' => Some(self.speed.to_string()),
+            'e' => Some(self.skip_outro.to_string()),
+            _ => None,
+        };
+    }
+}
+
+/// Environment variables holding the placeholder values when the play
+/// command is run through the shell, in the same order as
+/// `PLACEHOLDERS`.
+const SHELL_VARS: [&str; 9] = [
+    "SHELLCASTER_FILE",
+    "SHELLCASTER_TITLE",
+    "SHELLCASTER_PODCAST",
+    "SHELLCASTER_URL",
+    "SHELLCASTER_PATH",
+    "SHELLCASTER_DURATION",
+    "SHELLCASTER_START",
+    "SHELLCASTER_SPEED",
+    "SHELLCASTER_SKIP_END",
+];
+
+/// Placeholders that can be used in the play command.
+const PLACEHOLDERS: [char; 9] = ['s', 't', 'p', 'u', 'f', 'd', 'b', 'r', 'e'];
+
+/// Placeholders that are empty when the episode has no value for them,
+/// i.e., the path of an episode that hasn't been downloaded, and the
+/// duration if it is unknown.
+const OPTIONAL_PLACEHOLDERS: [char; 2] = ['f', 'd'];
 
 /// Execute an external shell command to play an episode file and/or URL.
-pub fn execute(command: &str, path: &str) -> Result<()> {
-    // Command expects a command and then optional arguments (giving
-    // everything to it in a string doesn't work), so we need to split
-    // on white space and treat everything after the first word as args
-    let cmd_string = command.to_string();
-    let mut parts = cmd_string.trim().split_whitespace();
+/// If the player is known to support it, it is told where to start,
+/// how fast to play, and where to stop (see `player_args`). The player's
+/// output is captured, so that its last position can be found with
+/// `wait`.
+pub fn execute(command: &str, data: &PlayData, shell: bool) -> Result<Child> {
+    let mut cmd = build_command(command, data, shell)?;
+    if !shell {
+        cmd.args(player_args(command, data));
+    }
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    return cmd.spawn().map_err(|err| anyhow!(err));
+}
+
+/// Builds the external command used to play an episode file and/or
+/// URL, without starting it. The command is split into arguments
+/// following the same quoting rules as the shell, and placeholders are
+/// filled in; if there is no placeholder for the file or URL (or only
+/// "%f", and the episode hasn't been downloaded), it is added to the
+/// end of the command.
+///
+/// If `shell` is true, the command is instead run with `sh -c`, so
+/// pipes, redirection, etc. can be used. In that case, placeholders are
+/// replaced with environment variables holding their values, so that
+/// episode titles and the like are never interpreted by the shell.
+/// Placeholders that may be empty, such as "%f", expand to nothing at
+/// all rather than an empty argument when they are empty, whether or
+/// not they are in double quotes.
+pub fn build_command(command: &str, data: &PlayData, shell: bool) -> Result<Command> {
+    let has_target = has_target(command, data);
+
+    if shell {
+        // the quotes go inside the expansion, so that the argument is
+        // only there if the value isn't empty
+        let mut command = command.to_string();
+        for key in OPTIONAL_PLACEHOLDERS.iter() {
+            command = command.replace(&format!("\"%{key}\""), &format!("%{key}"));
+        }
+        let mut script = fill_placeholders(&command, |key| {
+            let var = SHELL_VARS[PLACEHOLDERS.iter().position(|p| *p == key)?];
+            if OPTIONAL_PLACEHOLDERS.contains(&key) {
+                return Some(format!("${{{var}:+\"${var}\"}}"));
+            }
+            return Some(format!("${{{var}}}"));
+        });
+        if !has_target {
+            script.push_str(&format!(" \"${{{}}}\"", SHELL_VARS[0]));
+        }
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script);
+        for (var, key) in SHELL_VARS.iter().zip(PLACEHOLDERS.iter()) {
+            cmd.env(var, data.placeholder(*key).unwrap_or_default());
+        }
+        return Ok(cmd);
+    }
+
+    // arguments that are left empty by their placeholders (e.g., "%f"
+    // when the episode hasn't been downloaded) are dropped
+    let mut parts = split_command(command)?.into_iter().filter_map(|part| {
+        let filled = fill_placeholders(&part, |key| data.placeholder(key));
+        (filled.is_empty() == part.is_empty()).then_some(filled)
+    });
     let base_cmd = parts.next().ok_or_else(|| anyhow!("Invalid command."))?;
     let mut cmd = Command::new(base_cmd);
+    cmd.args(parts);
+    if !has_target {
+        cmd.arg(&data.target);
+    }
+    return Ok(cmd);
+}
+
+/// Splits a command into separate arguments, following the quoting
+/// rules of the shell: arguments are separated by white space, unless
+/// it is inside single or double quotes, or escaped with a backslash.
+/// Within double quotes, a backslash only escapes `"`, `\`, `$` and `` ` ``.
+pub fn split_command(command: &str) -> Result<Vec<String>> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(ch) => current.push(ch),
+                        None => return Err(anyhow!("Unterminated quote in command.")),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(ch @ ('"' | '\\' | '$' | '`')) => current.push(ch),
+                            Some(ch) => {
+                                current.push('\\');
+                                current.push(ch);
+                            }
+                            None => return Err(anyhow!("Unterminated quote in command.")),
+                        },
+                        Some(ch) => current.push(ch),
+                        None => return Err(anyhow!("Unterminated quote in command.")),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(ch) = chars.next() {
+                    current.push(ch);
+                }
+            }
+            ch if ch.is_whitespace() => {
+                if in_word {
+                    parts.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            ch => {
+                in_word = true;
+                current.push(ch);
+            }
+        }
+    }
+    if in_word {
+        parts.push(current);
+    }
+    return Ok(parts);
+}
+
+/// Checks whether the command has a placeholder for the file or URL
+/// that will be filled in with something, in which case the target does
+/// not need to be added to the end. An escaped "%%s" doesn't count, and
+/// neither does "%f" for an episode that hasn't been downloaded.
+fn has_target(command: &str, data: &PlayData) -> bool {
+    let found = Cell::new(false);
+    fill_placeholders(command, |key| {
+        let value = data.placeholder(key);
+        if ['s', 'f', 'u'].contains(&key) && value.as_ref().is_some_and(|v| !v.is_empty()) {
+            found.set(true);
+        }
+        value
+    });
+    return found.get();
+}
+
+/// Replaces placeholders like "%t" in a string with the value returned
+/// by `value` for that character. "%%" becomes a single "%", and
+/// anything else that is not a known placeholder is left alone.
+fn fill_placeholders<F>(input: &str, value: F) -> String
+where F: Fn(char) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('%') => {
+                out.push('%');
+                chars.next();
+            }
+            Some(key) => match value(*key) {
+                Some(val) => {
+                    out.push_str(&val);
+                    chars.next();
+                }
+                None => out.push('%'),
+            },
+            None => out.push('%'),
+        }
+    }
+    return out;
+}
+
+/// Picks the play command to use for an episode, from the commands set
+/// for different file types in `by_type`. Keys with a "/" are matched
+/// against the episode's MIME type, either exactly (e.g., "audio/mpeg")
+/// or by category (e.g., "video/*"); other keys are matched against the
+/// extension of the file or URL (e.g., "m4b"). Returns None if nothing
+/// matches.
+pub fn command_for_type<'a>(
+    by_type: &'a HashMap<String, String>,
+    mime_type: Option<&str>,
+    target: &str,
+) -> Option<&'a str> {
+    if let Some(mime) = mime_type.map(|m| m.to_lowercase()) {
+        if let Some(cmd) = by_type.get(&mime) {
+            return Some(cmd);
+        }
+        if let Some((category, _)) = mime.split_once('/') {
+            if let Some(cmd) = by_type.get(&format!("{category}/*")) {
+                return Some(cmd);
+            }
+        }
+    }
+
+    // ignore any query string or fragment in URLs
+    let file = target.split(['?', '#']).next().unwrap_or(target);
+    let file = file.rsplit(['/', '\\']).next().unwrap_or(file);
+    if let Some((_, ext)) = file.rsplit_once('.') {
+        return by_type.get(&ext.to_lowercase()).map(|cmd| cmd.as_str());
+    }
+    return None;
+}
+
+/// Returns the arguments needed to make the player start at
+/// `data.start`, play at `data.speed`, and stop `data.skip_outro`
+/// seconds before the end, for the players we know about. Other
+/// players just play the whole episode at normal speed.
+pub fn player_args(command: &str, data: &PlayData) -> Vec<String> {
+    let parts = split_command(command).unwrap_or_default();
+    let base_cmd = parts
+        .first()
+        .and_then(|cmd| cmd.rsplit(['/', '\\']).next())
+        .unwrap_or("");
+    return match base_cmd {
+        "mpv" => mpv_args(command, data),
+        "vlc" | "cvlc" => {
+            let (start, speed, skip_outro) = wanted_args(command, data);
+            let mut args = Vec::new();
+            if let Some(secs) = start {
+                args.push(format!("--start-time={secs}"));
+            }
+            if let Some(speed) = speed {
+                args.push(format!("--rate={speed}"));
+            }
+            // VLC needs an absolute time to stop at
+            if let (Some(secs), Some(dur)) = (skip_outro, data.duration) {
+                if dur > secs {
+                    args.push(format!("--stop-time={}", dur - secs));
+                }
+            }
+            args
+        }
+        _ => Vec::new(),
+    };
+}
+
+/// Returns the arguments for mpv to start at `data.start`, play at
+/// `data.speed`, and stop `data.skip_outro` seconds before the end.
+pub fn mpv_args(command: &str, data: &PlayData) -> Vec<String> {
+    let (start, speed, skip_outro) = wanted_args(command, data);
+    let mut args = Vec::new();
+    if let Some(secs) = start {
+        args.push(format!("--start={secs}"));
+    }
+    if let Some(speed) = speed {
+        args.push(format!("--speed={speed}"));
+    }
+    if let Some(secs) = skip_outro {
+        args.push(format!("--end=-{secs}"));
+    }
+    return args;
+}
+
+/// Returns the start position, speed and seconds to skip at the end
+/// that need to be passed to the player. Each one is left out if it
+/// would not change anything, or if the command already uses the
+/// matching placeholder ("%b", "%r" or "%e").
+fn wanted_args(command: &str, data: &PlayData) -> (Option<i64>, Option<f64>, Option<i64>) {
+    let start = (data.start > 0 && !command.contains("%b")).then_some(data.start);
+    let speed = (data.speed != 1.0 && !command.contains("%r")).then_some(data.speed);
+    let skip_outro = (data.skip_outro > 0 && !command.contains("%e")).then_some(data.skip_outro);
+    return (start, speed, skip_outro);
+}
+
+/// Waits for the player to exit, and returns its exit status, along
+/// with the last position (and duration, if given) that it printed, in
+/// seconds. The position is None if the player never printed anything
+/// that looked like a position.
+pub fn wait(mut child: Child) -> (Option<ExitStatus>, Option<(i64, Option<i64>)>) {
+    // both streams need to be drained at the same time, or the player
+    // may block writing to one of them
+    let stdout = child.stdout.take().map(|out| thread::spawn(move || read_tail(out)));
+    let mut output = child.stderr.take().map(read_tail).unwrap_or_default();
+    if let Some(handle) = stdout {
+        output.push('\n');
+        output.push_str(&handle.join().unwrap_or_default());
+    }
+    let status = child.wait().ok();
+    return (status, parse_position(&output));
+}
+
+/// Reads a stream to the end, keeping only the last few kilobytes.
+fn read_tail<R: Read>(mut stream: R) -> String {
+    let mut tail = Vec::new();
+    let mut buf = [0; 1024];
+    while let Ok(n) = stream.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        tail.extend_from_slice(&buf[..n]);
+        if tail.len() > OUTPUT_TAIL {
+            tail.drain(..tail.len() - OUTPUT_TAIL);
+        }
+    }
+    return String::from_utf8_lossy(&tail).to_string();
+}
+
+/// Finds the last status line in a player's output in the form mpv
+/// prints it, e.g., "A: 00:12:34 / 01:00:00 (20%)", possibly preceded
+/// by "(Paused)" or similar, and returns the position and duration from
+/// it. Anything else the player printed is ignored, even if it contains
+/// something that looks like a timestamp.
+pub fn parse_position(output: &str) -> Option<(i64, Option<i64>)> {
+    for line in output.rsplit(['\r', '\n']) {
+        if let Some(cap) = RE_STATUS_LINE.captures(line.trim()) {
+            if let (Some(pos), Some(dur)) = (parse_timestamp(&cap[1]), parse_timestamp(&cap[2])) {
+                return Some((pos, Some(dur)));
+            }
+        }
+    }
+    return None;
+}
+
+/// Parses a timestamp in HH:MM:SS or MM:SS format into seconds.
+pub fn parse_timestamp(word: &str) -> Option<i64> {
+    let parts: Vec<&str> = word.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        return None;
+    }
+    let mut secs = 0;
+    for part in parts.iter() {
+        if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        secs = secs * 60 + part.parse::<i64>().ok()?;
+    }
+    return Some(secs);
+}
+
+
+// TESTS -----------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mpv_status() {
+        let output = "(+) Audio --aid=1 (mp3 2ch 44100Hz)\n\
+            AO: [pulse] 44100Hz stereo 2ch float\n\
+            A: 00:00:01 / 01:00:00 (0%)\rA: 00:12:34 / 01:00:00 (20%)\r\n\
+            Exiting... (Quit)\n";
+        assert_eq!(parse_position(output), Some((754, Some(3600))));
+    }
+
+    #[test]
+    fn parse_paused_status() {
+        let output = "A: 00:00:29 / 00:10:00 (4%)\r(Paused) A: 00:00:30 / 00:10:00 (5%)\n";
+        assert_eq!(parse_position(output), Some((30, Some(600))));
+        // when streaming, mpv adds the state of its cache
+        let output = "A: 00:00:05 / 01:00:00 (0%) Cache: 9.8s/1MB\r";
+        assert_eq!(parse_position(output), Some((5, Some(3600))));
+    }
+
+    #[test]
+    fn ignore_other_output() {
+        assert_eq!(parse_position("nothing to see here"), None);
+        assert_eq!(parse_position("stopped at 1:05"), None);
+        assert_eq!(parse_position("Chapter 2 starts 10:00 / 20:00"), None);
+        assert_eq!(parse_position("A: 00:01:05"), None);
+        assert_eq!(parse_position("A: 00:01:05 / unknown"), None);
+        assert_eq!(parse_position("A: 00:01:0x / 00:10:00"), None);
+        // a garbled last line doesn't hide an earlier good one
+        assert_eq!(
+            parse_position("A: 00:01:00 / 00:10:00\nA: 00:02:00 / ??"),
+            Some((60, Some(600)))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn wait_reports_exit() {
+        let child = Command::new("sh")
+            .args(["-c", "echo 'A: 00:01:00 / 00:10:00' >&2; exit 3"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let (status, position) = wait(child);
+        assert_eq!(status.and_then(|s| s.code()), Some(3));
+        assert_eq!(position, Some((60, Some(600))));
+    }
+
+    fn make_data() -> PlayData {
+        return PlayData {
+            target: "/downloads/ep 1.mp3".to_string(),
+            title: "Ep 1: \"Quotes\" & $things".to_string(),
+            pod_title: "Pod".to_string(),
+            url: "http://example.com/ep1.mp3".to_string(),
+            path: Some("/downloads/ep 1.mp3".to_string()),
+            duration: Some(3600),
+            start: 0,
+            speed: 1.0,
+            skip_outro: 0,
+        };
+    }
+
+    fn get_args(cmd: &Command) -> Vec<String> {
+        return cmd
+            .get_args()
+            .map(|a| a.to_string_lossy().to_string())
+            .collect();
+    }
+
+    #[test]
+    fn split_quoted() {
+        assert_eq!(
+            split_command(r#"mpv --title="My Player" 'it''s' a\ b "x\"y""#).unwrap(),
+            vec!["mpv", "--title=My Player", "its", "a b", "x\"y"]
+        );
+        assert!(split_command("mpv \"oops").is_err());
+    }
+
+    #[test]
+    fn placeholders() {
+        let data = make_data();
+        let cmd = build_command("mpv --title=\"%p - %t\" --length=%d %u 100%%", &data, false)
+            .unwrap();
+        assert_eq!(cmd.get_program(), "mpv");
+        assert_eq!(get_args(&cmd), vec![
+            "--title=Pod - Ep 1: \"Quotes\" & $things",
+            "--length=3600",
+            "http://example.com/ep1.mp3",
+            "100%",
+        ]);
+
+        let mut data = data;
+        data.start = 90;
+        data.speed = 1.25;
+        let cmd = build_command("mplayer -ss %b -speed %r", &data, false).unwrap();
+        assert_eq!(get_args(&cmd), vec![
+            "-ss",
+            "90",
+            "-speed",
+            "1.25",
+            "/downloads/ep 1.mp3"
+        ]);
+
+        // file is added to the end if there is no placeholder for it
+        let cmd = build_command("vlc --play-and-exit", &data, false).unwrap();
+        assert_eq!(get_args(&cmd), vec!["--play-and-exit", "/downloads/ep 1.mp3"]);
+        // an escaped "%" is not a placeholder
+        let cmd = build_command("echo 100%%s", &data, false).unwrap();
+        assert_eq!(get_args(&cmd), vec!["100%s", "/downloads/ep 1.mp3"]);
+    }
+
+    #[test]
+    fn stream_when_not_downloaded() {
+        let mut data = make_data();
+        data.target = data.url.clone();
+        data.path = None;
+        // "%f" is empty, so the URL is played instead
+        let cmd = build_command("mpv --save-position-on-quit %f", &data, false).unwrap();
+        assert_eq!(get_args(&cmd), vec![
+            "--save-position-on-quit",
+            "http://example.com/ep1.mp3"
+        ]);
+        // in the shell, an empty "%f" leaves out the argument
+        for command in ["mpv \"%f\"", "mpv %f"] {
+            let cmd = build_command(command, &data, true).unwrap();
+            assert_eq!(get_args(&cmd), vec![
+                "-c",
+                "mpv ${SHELLCASTER_PATH:+\"$SHELLCASTER_PATH\"} \"${SHELLCASTER_FILE}\"",
+            ]);
+            let printf = command.replace("mpv", "printf '[%%s]'");
+            let output = build_command(&printf, &data, true).unwrap().output().unwrap();
+            assert_eq!(output.stdout, b"[http://example.com/ep1.mp3]");
+        }
+    }
+
+    #[test]
+    fn shell_command() {
+        let data = make_data();
+        let cmd = build_command("mpv --title=\"%t\" \"%s\" | tee log", &data, true).unwrap();
+        assert_eq!(cmd.get_program(), "sh");
+        assert_eq!(get_args(&cmd), vec![
+            "-c",
+            "mpv --title=\"${SHELLCASTER_TITLE}\" \"${SHELLCASTER_FILE}\" | tee log",
+        ]);
+        let title = cmd
+            .get_envs()
+            .find(|(k, _)| *k == "SHELLCASTER_TITLE")
+            .and_then(|(_, v)| v);
+        assert_eq!(title, Some(std::ffi::OsStr::new(&data.title)));
+    }
+
+    #[test]
+    fn command_by_type() {
+        let mut by_type = HashMap::new();
+        by_type.insert("video/*".to_string(), "mpv %s".to_string());
+        by_type.insert("video/webm".to_string(), "vlc %s".to_string());
+        by_type.insert("m4b".to_string(), "mpv --speed=1.5 %s".to_string());
+
+        assert_eq!(command_for_type(&by_type, Some("video/mp4"), "a.mp4"), Some("mpv %s"));
+        assert_eq!(command_for_type(&by_type, Some("Video/WebM"), "a.webm"), Some("vlc %s"));
+        assert_eq!(
+            command_for_type(&by_type, Some("audio/mp4"), "http://x.com/b.M4B?id=1"),
+            Some("mpv --speed=1.5 %s")
+        );
+        assert_eq!(command_for_type(&by_type, None, "/dir.v2/file"), None);
+        assert_eq!(command_for_type(&by_type, Some("audio/mpeg"), "c.mp3"), None);
+    }
+
+    #[test]
+    fn args_for_known_players() {
+        let mut data = make_data();
+        assert!(player_args("mpv %s", &data).is_empty());
 
-    if cmd_string.contains("%s") {
-        // if command contains "%s", replace the path with that value
-        cmd.args(parts.map(|a| if a == "%s" { path } else { a }));
-    } else {
-        // otherwise, add path to the end of the command
-        cmd.args(parts.chain(vec![path].into_iter()));
+        data.start = 90;
+        data.speed = 1.5;
+        data.skip_outro = 30;
+        assert_eq!(player_args("mpv --no-video %s", &data), vec![
+            "--start=90",
+            "--speed=1.5",
+            "--end=-30"
+        ]);
+        assert_eq!(player_args("/usr/bin/vlc %s", &data), vec![
+            "--start-time=90",
+            "--rate=1.5",
+            "--stop-time=3570"
+        ]);
+        assert_eq!(player_args("mpv --speed=%r %s", &data), vec!["--start=90", "--end=-30"]);
+        assert!(player_args("mplayer", &data).is_empty());
     }
 
-    cmd.stdout(Stdio::null()).stderr(Stdio::null());
-    match cmd.spawn() {
-        Ok(_) => Ok(()),
-        Err(err) => Err(anyhow!(err)),
+    #[test]
+    fn speed_args() {
+        let mut data = make_data();
+        data.speed = 0.8;
+        assert_eq!(player_args("mpv %s", &data), vec!["--speed=0.8"]);
+        assert_eq!(player_args("cvlc --play-and-exit", &data), vec!["--rate=0.8"]);
+        // with a placeholder for it, the speed is only passed once
+        assert!(player_args("mpv --speed=%r", &data).is_empty());
+        let cmd = build_command("mpv --speed=%r", &data, false).unwrap();
+        assert_eq!(get_args(&cmd), vec!["--speed=0.8", "/downloads/ep 1.mp3"]);
+        // normal speed doesn't need to be passed at all
+        data.speed = 1.0;
+        assert!(player_args("vlc", &data).is_empty());
     }
 }
@@ -0,0 +1,495 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+use crate::play_file::{self, PlayData};
+use crate::types::Message;
+
+/// How often, in milliseconds, the player is asked for its current
+/// status.
+const POLL_RATE: u64 = 500;
+
+/// How long, in milliseconds, to wait for the player to open its IPC
+/// socket after it has been started.
+const CONNECT_TIMEOUT: u64 = 5000;
+
+/// Commands that can be sent to a running player.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlayerCommand {
+    TogglePause,
+    Seek(i64),
+    SeekTo(i64),
+    ChangeSpeed(f64),
+    Pause,
+    Stop,
+}
+
+/// When the sleep timer will stop playback: either at a set time, or
+/// when the episode that is playing ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SleepTimer {
+    At(Instant),
+    EndOfEpisode,
+}
+
+impl SleepTimer {
+    /// Formats the time left into a short piece of text for display,
+    /// e.g., "Sleep: 25m", rounding up to the next minute.
+    pub fn format(&self) -> String {
+        return match self {
+            SleepTimer::At(time) => {
+                let secs = time.saturating_duration_since(Instant::now()).as_secs();
+                if secs < 60 {
+                    format!("Sleep: {secs}s")
+                } else {
+                    format!("Sleep: {}m", secs.div_ceil(60))
+                }
+            }
+            SleepTimer::EndOfEpisode => "Sleep: end of episode".to_string(),
+        };
+    }
+}
+
+/// Struct holding the current status of the player, as last reported
+/// by the player itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NowPlaying {
+    pub pod_id: i64,
+    pub ep_id: i64,
+    pub title: String,
+    pub position: f64,
+    pub duration: Option<f64>,
+    pub paused: bool,
+    pub speed: f64,
+}
+
+impl NowPlaying {
+    /// Formats the status into a short line for display, e.g.,
+    /// "Playing: Title [00:12:34/01:00:00] 1.25x".
+    pub fn format(&self) -> String {
+        let state = if self.paused { "Paused" } else { "Playing" };
+        let position = format_time(self.position);
+        let duration = match self.duration {
+            Some(dur) => format_time(dur),
+            None => "--:--:--".to_string(),
+        };
+        let mut out = format!("{state}: {} [{position}/{duration}]", self.title);
+        if (self.speed - 1.0).abs() > 0.001 {
+            out.push_str(&format!(" {:.2}x", self.speed));
+        }
+        return out;
+    }
+}
+
+/// Struct describing how playback of an episode ended. `exit_code` is
+/// None if the player was killed by a signal, or if its status could
+/// not be read. `position` holds where the player stopped and the
+/// length of the episode, in seconds, if the player told us.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackEnd {
+    pub pod_id: i64,
+    pub ep_id: i64,
+    pub success: bool,
+    pub exit_code: Option<i32>,
+    pub position: Option<(i64, Option<i64>)>,
+}
+
+/// Enum used for communicating back to the main controller from the
+/// threads monitoring players. The i64 value identifies which player
+/// instance the message relates to. `Status` is only sent by players
+/// controlled through the player integration, while `Exited` is sent
+/// whenever a player started by shellcaster exits. `SleepTimer` is
+/// sent when a sleep timer runs out; its value identifies the timer
+/// rather than a player.
+#[derive(Debug)]
+pub enum PlayerMsg {
+    Status(i64, NowPlaying),
+    Exited(i64, PlaybackEnd),
+    SleepTimer(i64),
+}
+
+/// A connection to mpv's JSON IPC interface. Each request is written as
+/// a single line of JSON with a unique `request_id`, and the matching
+/// reply is read back; any event messages mpv sends in between are
+/// skipped.
+pub struct MpvIpc {
+    #[cfg(unix)]
+    reader: BufReader<UnixStream>,
+    #[cfg(unix)]
+    writer: UnixStream,
+    next_id: i64,
+}
+
+impl MpvIpc {
+    /// Connects to the IPC socket at the given path.
+    #[cfg(unix)]
+    pub fn connect(path: &Path) -> Result<MpvIpc> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(Duration::from_millis(1000)))?;
+        return Ok(MpvIpc {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            next_id: 1,
+        });
+    }
+
+    /// Connects to the IPC socket at the given path.
+    #[cfg(not(unix))]
+    pub fn connect(_path: &Path) -> Result<MpvIpc> {
+        return Err(anyhow!(
+            "The mpv integration is not supported on this operating system."
+        ));
+    }
+
+    /// Sends a command to mpv, and returns the `data` field of the reply.
+    pub fn command(&mut self, args: Value) -> Result<Value> {
+        let request_id = self.next_id;
+        self.next_id += 1;
+        let request = json!({
+            "command": args,
+            "request_id": request_id,
+        });
+        self.write_line(&request.to_string())?;
+
+        loop {
+            let line = self.read_line()?;
+            let reply: Value = match serde_json::from_str(&line) {
+                Ok(val) => val,
+                Err(_) => continue,
+            };
+            if reply.get("request_id").and_then(Value::as_i64) != Some(request_id) {
+                // an event, or a reply to someone else
+                continue;
+            }
+            return match reply.get("error").and_then(Value::as_str) {
+                Some("success") => Ok(reply.get("data").cloned().unwrap_or(Value::Null)),
+                Some(err) => Err(anyhow!("mpv error: {err}")),
+                None => Err(anyhow!("Invalid reply from mpv.")),
+            };
+        }
+    }
+
+    /// Gets the value of a property from mpv.
+    pub fn get_property(&mut self, name: &str) -> Result<Value> {
+        return self.command(json!(["get_property", name]));
+    }
+
+    #[cfg(unix)]
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        self.writer.write_all(format!("{line}\n").as_bytes())?;
+        return Ok(());
+    }
+
+    #[cfg(not(unix))]
+    fn write_line(&mut self, _line: &str) -> Result<()> {
+        return Err(anyhow!("Not connected."));
+    }
+
+    #[cfg(unix)]
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("Connection to mpv closed."));
+        }
+        return Ok(line);
+    }
+
+    #[cfg(not(unix))]
+    fn read_line(&mut self) -> Result<String> {
+        return Err(anyhow!("Not connected."));
+    }
+}
+
+/// Handle to an instance of mpv started by shellcaster and controlled
+/// over IPC. A separate thread keeps track of the player's status and
+/// reports back to the main controller, until the player exits.
+pub struct MpvPlayer {
+    pub id: i64,
+    ipc: MpvIpc,
+}
+
+impl MpvPlayer {
+    /// Starts the play command for the given episode, with mpv's
+    /// IPC server enabled, and connects to it. `id` is used to tell
+    /// messages from different player instances apart. The start
+    /// position, speed and outro to skip are taken from `data`.
+    pub fn spawn(
+        id: i64,
+        command: &str,
+        data: &PlayData,
+        now_playing: NowPlaying,
+        tx_to_main: Sender<Message>,
+    ) -> Result<MpvPlayer> {
+        let socket = socket_path(id);
+        let _ = std::fs::remove_file(&socket);
+
+        let mut cmd = play_file::build_command(command, data, false)?;
+        cmd.arg(format!("--input-ipc-server={}", socket.to_string_lossy()));
+        cmd.args(play_file::mpv_args(command, data));
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        let mut child = cmd.spawn().with_context(|| "Could not start player.")?;
+
+        // mpv takes a moment to set up its socket
+        let start = Instant::now();
+        let ipc = loop {
+            match MpvIpc::connect(&socket) {
+                Ok(ipc) => break ipc,
+                Err(err) => {
+                    if let Ok(Some(_)) = child.try_wait() {
+                        let _ = std::fs::remove_file(&socket);
+                        return Err(anyhow!("Player exited before it could be controlled."));
+                    }
+                    if start.elapsed() > Duration::from_millis(CONNECT_TIMEOUT) {
+                        stop_child(&mut child, &socket);
+                        return Err(err).with_context(|| "Could not connect to mpv.");
+                    }
+                    thread::sleep(Duration::from_millis(50));
+                }
+            }
+        };
+        let monitor_ipc = match MpvIpc::connect(&socket) {
+            Ok(monitor_ipc) => monitor_ipc,
+            Err(err) => {
+                stop_child(&mut child, &socket);
+                return Err(err).with_context(|| "Could not connect to mpv.");
+            }
+        };
+
+        thread::spawn(move || monitor(id, child, monitor_ipc, socket, now_playing, tx_to_main));
+
+        return Ok(MpvPlayer {
+            id: id,
+            ipc: ipc,
+        });
+    }
+
+    /// Sends a command to the player.
+    pub fn send(&mut self, command: PlayerCommand) -> Result<()> {
+        let args = match command {
+            PlayerCommand::TogglePause => json!(["cycle", "pause"]),
+            PlayerCommand::Seek(secs) => json!(["seek", secs, "relative"]),
+            PlayerCommand::SeekTo(secs) => json!(["seek", secs, "absolute"]),
+            PlayerCommand::ChangeSpeed(step) => json!(["add", "speed", step]),
+            PlayerCommand::Pause => json!(["set_property", "pause", true]),
+            PlayerCommand::Stop => json!(["quit"]),
+        };
+        self.ipc.command(args)?;
+        return Ok(());
+    }
+}
+
+/// Kills a player that could not be set up, waiting for it to exit so
+/// it does not linger, and removes its socket.
+fn stop_child(child: &mut Child, socket: &Path) {
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = std::fs::remove_file(socket);
+}
+
+/// Runs in its own thread, asking the player for its status at regular
+/// intervals and passing it on to the main controller, until the player
+/// exits.
+fn monitor(
+    id: i64,
+    mut child: Child,
+    mut ipc: MpvIpc,
+    socket: PathBuf,
+    mut status: NowPlaying,
+    tx_to_main: Sender<Message>,
+) {
+    loop {
+        if let Ok(Some(exit_status)) = child.try_wait() {
+            let _ = std::fs::remove_file(&socket);
+            let end = PlaybackEnd {
+                pod_id: status.pod_id,
+                ep_id: status.ep_id,
+                success: exit_status.success(),
+                exit_code: exit_status.code(),
+                position: Some((
+                    status.position as i64,
+                    status.duration.map(|dur| dur as i64),
+                )),
+            };
+            let _ = tx_to_main.send(Message::Player(PlayerMsg::Exited(id, end)));
+            break;
+        }
+
+        if poll_status(&mut ipc, &mut status).is_ok()
+            && tx_to_main
+                .send(Message::Player(PlayerMsg::Status(id, status.clone())))
+                .is_err()
+        {
+            // main controller has shut down
+            break;
+        }
+        thread::sleep(Duration::from_millis(POLL_RATE));
+    }
+}
+
+/// Updates `status` with the current state of the player.
+fn poll_status(ipc: &mut MpvIpc, status: &mut NowPlaying) -> Result<()> {
+    // these are always available, so an error here means we have lost
+    // the connection
+    status.paused = ipc.get_property("pause")?.as_bool().unwrap_or(false);
+    status.speed = ipc.get_property("speed")?.as_f64().unwrap_or(1.0);
+
+    // these are unavailable while the file is still loading
+    if let Some(pos) = ipc.get_property("time-pos").ok().and_then(|v| v.as_f64()) {
+        status.position = pos;
+    }
+    status.duration = ipc.get_property("duration").ok().and_then(|v| v.as_f64());
+    return Ok(());
+}
+
+/// Returns the path to use for the IPC socket of the player with the
+/// given id.
+fn socket_path(id: i64) -> PathBuf {
+    let mut path = std::env::temp_dir();
+    path.push(format!("shellcaster-mpv-{}-{id}.sock", std::process::id()));
+    return path;
+}
+
+/// Formats a number of seconds into an HH:MM:SS format.
+fn format_time(secs: f64) -> String {
+    let mut seconds = secs.max(0.0) as i64;
+    let hours = seconds / 3600;
+    seconds -= hours * 3600;
+    let minutes = seconds / 60;
+    seconds -= minutes * 60;
+    return format!("{hours:02}:{minutes:02}:{seconds:02}");
+}
+
+
+// TESTS -----------------------------------------------------------------
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    /// Starts a fake mpv IPC server that answers each request with the
+    /// result of `respond`, sending an unrelated event first. Returns
+    /// the socket path and a handle that yields the received requests.
+    fn fake_server<F>(
+        name: &str,
+        n_requests: usize,
+        respond: F,
+    ) -> (PathBuf, thread::JoinHandle<Vec<Value>>)
+    where F: Fn(&Value) -> Value + Send + 'static {
+        let mut path = std::env::temp_dir();
+        path.push(format!("shellcaster-test-{name}-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut requests = Vec::new();
+            for _ in 0..n_requests {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let request: Value = serde_json::from_str(&line).unwrap();
+                let mut reply = json!({
+                    "error": "success",
+                    "request_id": request["request_id"],
+                });
+                reply["data"] = respond(&request["command"]);
+                writer
+                    .write_all(b"{\"event\":\"playback-restart\"}\n")
+                    .unwrap();
+                writer
+                    .write_all(format!("{reply}\n").as_bytes())
+                    .unwrap();
+                requests.push(request["command"].clone());
+            }
+            requests
+        });
+        return (path, handle);
+    }
+
+    #[test]
+    fn get_property_skips_events() {
+        let (path, handle) = fake_server("mpv-property", 1, |_| json!(12.5));
+        let mut ipc = MpvIpc::connect(&path).unwrap();
+        let pos = ipc.get_property("time-pos").unwrap();
+        assert_eq!(pos.as_f64(), Some(12.5));
+        let requests = handle.join().unwrap();
+        assert_eq!(requests[0], json!(["get_property", "time-pos"]));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn poll_reads_status() {
+        let (path, handle) = fake_server("mpv-poll", 4, |cmd| match cmd[1].as_str() {
+            Some("time-pos") => json!(30.0),
+            Some("duration") => json!(3600.0),
+            Some("pause") => json!(true),
+            Some("speed") => json!(1.5),
+            _ => Value::Null,
+        });
+        let mut ipc = MpvIpc::connect(&path).unwrap();
+        let mut status = NowPlaying {
+            pod_id: 1,
+            ep_id: 2,
+            title: "Episode".to_string(),
+            position: 0.0,
+            duration: None,
+            paused: false,
+            speed: 1.0,
+        };
+        poll_status(&mut ipc, &mut status).unwrap();
+        handle.join().unwrap();
+        assert_eq!(status.position, 30.0);
+        assert_eq!(status.duration, Some(3600.0));
+        assert!(status.paused);
+        assert_eq!(status.format(), "Paused: Episode [00:00:30/01:00:00] 1.50x");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn error_reply() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("shellcaster-test-mpv-error-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let request: Value = serde_json::from_str(&line).unwrap();
+            let reply = json!({
+                "error": "property unavailable",
+                "request_id": request["request_id"],
+            });
+            let mut writer = stream;
+            writer
+                .write_all(format!("{reply}\n").as_bytes())
+                .unwrap();
+        });
+        let mut ipc = MpvIpc::connect(&path).unwrap();
+        assert!(ipc.get_property("duration").is_err());
+        handle.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn sleep_timer_format() {
+        let timer = SleepTimer::At(Instant::now() + Duration::from_secs(25 * 60 - 10));
+        assert_eq!(timer.format(), "Sleep: 25m");
+        let timer = SleepTimer::At(Instant::now() + Duration::from_millis(30_500));
+        assert_eq!(timer.format(), "Sleep: 30s");
+        assert_eq!(SleepTimer::EndOfEpisode.format(), "Sleep: end of episode");
+    }
+}
@@ -0,0 +1,290 @@
+use ahash::AHashMap;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+
+use crate::types::*;
+
+/// Number of weeks of listening time shown, including the current one.
+const STATS_WEEKS: i64 = 8;
+
+/// Number of podcasts shown in each of the lists of podcasts.
+const STATS_PODCASTS: usize = 10;
+
+/// Struct holding statistics about the user's listening, as shown by
+/// the stats window and the `stats` subcommand. Times are in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub total_listened: i64,
+    pub per_podcast: Vec<(String, i64)>,
+    pub per_week: Vec<(NaiveDate, i64)>,
+    pub finished: usize,
+    pub backlog: usize,
+    pub backlog_duration: i64,
+    pub least_recent: Vec<(String, Option<DateTime<Utc>>)>,
+}
+
+impl Stats {
+    /// Works out the statistics from the listening history, the list of
+    /// podcasts (for titles and the backlog), the episodes that have been
+    /// marked as played, and the last time anything was played from each
+    /// podcast. Episodes count as finished if they were played to the end
+    /// according to the history, or were marked as played. Weeks start
+    /// on Monday, in UTC. Archived podcasts are left out of the backlog.
+    pub fn new(
+        podcasts: &[Podcast],
+        history: &[HistoryEntry],
+        played: &[i64],
+        last_played: &AHashMap<i64, DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Stats {
+        let titles: AHashMap<i64, &str> = podcasts
+            .iter()
+            .map(|pod| (pod.id, pod.title.as_str()))
+            .collect();
+
+        let this_week = week_start(now);
+        let mut per_week: Vec<(NaiveDate, i64)> = (0..STATS_WEEKS)
+            .rev()
+            .map(|i| (this_week - Duration::weeks(i), 0))
+            .collect();
+        let mut by_podcast: AHashMap<i64, i64> = AHashMap::new();
+        let mut total_listened = 0;
+        let mut finished_eps = Vec::new();
+        for entry in history.iter() {
+            total_listened += entry.listened;
+            *by_podcast.entry(entry.pod_id).or_insert(0) += entry.listened;
+            let week = week_start(entry.started_at);
+            if let Some((_, secs)) = per_week.iter_mut().find(|(w, _)| *w == week) {
+                *secs += entry.listened;
+            }
+            if entry.finished {
+                finished_eps.push(entry.ep_id);
+            }
+        }
+        finished_eps.extend_from_slice(played);
+        finished_eps.sort_unstable();
+        finished_eps.dedup();
+
+        let mut per_podcast: Vec<(String, i64)> = by_podcast
+            .into_iter()
+            .filter(|(_, secs)| *secs > 0)
+            .filter_map(|(id, secs)| titles.get(&id).map(|title| (title.to_string(), secs)))
+            .collect();
+        per_podcast.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        per_podcast.truncate(STATS_PODCASTS);
+
+        let mut backlog = 0;
+        let mut backlog_duration = 0;
+        let mut least_recent = Vec::new();
+        // archived podcasts still count towards listening time, but the
+        // user is done with what is left of them
+        for pod in podcasts.iter().filter(|pod| !pod.archived) {
+            let unplayed = pod.episodes.map(
+                |ep| {
+                    if ep.played {
+                        None
+                    } else {
+                        Some(ep.duration.map(|dur| (dur - ep.position).max(0)).unwrap_or(0))
+                    }
+                },
+                false,
+            );
+            let unplayed: Vec<i64> = unplayed.into_iter().flatten().collect();
+            if !unplayed.is_empty() {
+                backlog += unplayed.len();
+                backlog_duration += unplayed.iter().sum::<i64>();
+                least_recent.push((pod.title.clone(), last_played.get(&pod.id).copied()));
+            }
+        }
+        // podcasts that were never played come first
+        least_recent.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        least_recent.truncate(STATS_PODCASTS);
+
+        return Stats {
+            total_listened: total_listened,
+            per_podcast: per_podcast,
+            per_week: per_week,
+            finished: finished_eps.len(),
+            backlog: backlog,
+            backlog_duration: backlog_duration,
+            least_recent: least_recent,
+        };
+    }
+
+    /// Formats the statistics into lines of text for display.
+    pub fn format(&self, now: DateTime<Utc>) -> Vec<String> {
+        let mut lines = vec![
+            format!("Total listening time: {}", format_duration(self.total_listened)),
+            format!("Episodes finished: {}", self.finished),
+            format!(
+                "Backlog: {} unplayed episodes ({} left to listen to)",
+                self.backlog,
+                format_duration(self.backlog_duration)
+            ),
+            String::new(),
+            "Listening time per week:".to_string(),
+        ];
+        for (week, secs) in self.per_week.iter().rev() {
+            lines.push(format!("  Week of {week}: {}", format_duration(*secs)));
+        }
+
+        lines.push(String::new());
+        lines.push("Listening time per podcast:".to_string());
+        if self.per_podcast.is_empty() {
+            lines.push("  Nothing played yet.".to_string());
+        }
+        for (title, secs) in self.per_podcast.iter() {
+            lines.push(format!("  {title}: {}", format_duration(*secs)));
+        }
+
+        lines.push(String::new());
+        lines.push("Longest since last played:".to_string());
+        if self.least_recent.is_empty() {
+            lines.push("  No unplayed episodes.".to_string());
+        }
+        for (title, date) in self.least_recent.iter() {
+            let last = match date {
+                Some(date) => {
+                    let ago = match (now - *date).num_days() {
+                        0 => "today".to_string(),
+                        1 => "1 day ago".to_string(),
+                        days => format!("{days} days ago"),
+                    };
+                    format!("{} ({ago})", date.format("%Y-%m-%d"))
+                }
+                None => "never".to_string(),
+            };
+            lines.push(format!("  {title}: {last}"));
+        }
+        return lines;
+    }
+}
+
+/// Works out how much of an episode was heard during one play, in
+/// seconds. `start` is where playback started. If the player told us
+/// where it stopped, that is used; otherwise, we go by the episode's
+/// stored `duration`: all of the rest of the episode if it was
+/// finished, or the time it was playing for, up to the end of the
+/// episode.
+pub fn listened_time(
+    start: i64,
+    position: Option<i64>,
+    duration: Option<i64>,
+    elapsed: i64,
+    finished: bool,
+) -> i64 {
+    let listened = match (position, duration) {
+        (Some(pos), _) => pos - start,
+        (None, Some(dur)) if finished => dur - start,
+        (None, Some(dur)) => elapsed.min(dur - start),
+        (None, None) => elapsed,
+    };
+    return listened.max(0);
+}
+
+/// Returns the Monday starting the week that `date` falls in.
+fn week_start(date: DateTime<Utc>) -> NaiveDate {
+    let day = date.naive_utc().date();
+    return day - Duration::days(day.weekday().num_days_from_monday() as i64);
+}
+
+/// Formats a number of seconds as hours and minutes, e.g., "3h 05m".
+fn format_duration(secs: i64) -> String {
+    let mins = secs.max(0) / 60;
+    if mins < 60 {
+        return format!("{mins}m");
+    }
+    return format!("{}h {:02}m", mins / 60, mins % 60);
+}
+
+
+// TESTS -----------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{make_episode, make_podcast};
+    use chrono::TimeZone;
+
+    fn make_stats_episode(id: i64, pod_id: i64, played: bool, duration: Option<i64>) -> Episode {
+        return Episode {
+            duration: duration,
+            played: played,
+            ..make_episode(id, pod_id, &format!("Episode {id}"))
+        };
+    }
+
+    fn make_entry(ep_id: i64, pod_id: i64, started_at: DateTime<Utc>, listened: i64) -> HistoryEntry {
+        return HistoryEntry {
+            ep_id: ep_id,
+            pod_id: pod_id,
+            started_at: started_at,
+            ended_at: started_at + Duration::seconds(listened),
+            listened: listened,
+            finished: true,
+        };
+    }
+
+    #[test]
+    fn listened_from_position_or_duration() {
+        assert_eq!(listened_time(60, Some(600), Some(3600), 900, false), 540);
+        assert_eq!(listened_time(60, None, Some(3600), 900, true), 3540);
+        assert_eq!(listened_time(60, None, Some(3600), 900, false), 900);
+        assert_eq!(listened_time(3000, None, Some(3600), 900, false), 600);
+        assert_eq!(listened_time(0, None, None, 120, false), 120);
+        assert_eq!(listened_time(600, Some(30), None, 10, false), 0);
+    }
+
+    #[test]
+    fn compute_stats() {
+        // a Wednesday
+        let now = Utc.ymd(2022, 3, 16).and_hms(12, 0, 0);
+        let mut podcasts = vec![
+            make_podcast(1, "Alpha", vec![
+                make_stats_episode(1, 1, true, Some(1800)),
+                make_stats_episode(2, 1, false, Some(3600)),
+            ]),
+            make_podcast(2, "Beta", vec![make_stats_episode(3, 2, false, None)]),
+            make_podcast(3, "Gamma", vec![make_stats_episode(4, 3, true, Some(600))]),
+        ];
+        // archived podcasts are left out of the backlog
+        let mut delta = make_podcast(4, "Delta", vec![make_stats_episode(5, 4, false, Some(900))]);
+        delta.archived = true;
+        podcasts.push(delta);
+        let history = vec![
+            make_entry(1, 1, Utc.ymd(2022, 3, 7).and_hms(9, 0, 0), 1800),
+            make_entry(4, 3, Utc.ymd(2022, 3, 14).and_hms(9, 0, 0), 600),
+            make_entry(1, 1, Utc.ymd(2022, 3, 15).and_hms(9, 0, 0), 300),
+        ];
+        // episode 6 was marked as played elsewhere, so it has no history
+        let played = vec![1, 4, 6];
+        let mut last_played = AHashMap::new();
+        last_played.insert(1, Utc.ymd(2022, 3, 15).and_hms(9, 5, 0));
+
+        let stats = Stats::new(&podcasts, &history, &played, &last_played, now);
+        assert_eq!(stats.total_listened, 2700);
+        assert_eq!(stats.finished, 3);
+        assert_eq!(stats.per_podcast, vec![
+            ("Alpha".to_string(), 2100),
+            ("Gamma".to_string(), 600)
+        ]);
+        assert_eq!(stats.per_week.len(), STATS_WEEKS as usize);
+        assert_eq!(
+            stats.per_week.last(),
+            Some(&(NaiveDate::from_ymd(2022, 3, 14), 900))
+        );
+        assert_eq!(
+            stats.per_week[stats.per_week.len() - 2],
+            (NaiveDate::from_ymd(2022, 3, 7), 1800)
+        );
+        assert_eq!(stats.backlog, 2);
+        assert_eq!(stats.backlog_duration, 3600);
+        assert_eq!(stats.least_recent, vec![
+            ("Beta".to_string(), None),
+            ("Alpha".to_string(), last_played.get(&1).copied()),
+        ]);
+
+        let lines = stats.format(now);
+        assert_eq!(lines[0], "Total listening time: 45m");
+        assert!(lines.contains(&"  Week of 2022-03-14: 15m".to_string()));
+        assert!(lines.contains(&"  Alpha: 2022-03-15 (1 day ago)".to_string()));
+    }
+}
@@ -0,0 +1,90 @@
+//! Fixtures shared by the unit tests in the other modules.
+
+use std::path::PathBuf;
+
+use chrono::Utc;
+
+use crate::types::*;
+
+/// Creates an empty directory for a test to work in, unique to the
+/// test run. Anything left over from a previous run is removed first.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let mut dir = std::env::temp_dir();
+    dir.push(format!("shellcaster-test-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    return dir;
+}
+
+/// Turns a title into the URL used for it by these fixtures.
+fn make_url(title: &str) -> String {
+    return format!("http://example.com/{}", title.to_lowercase().replace(' ', "-"));
+}
+
+/// Creates a feed as it would come from syncing a podcast, with one
+/// episode for each (title, description) pair, newest first. Episode
+/// URLs and GUIDs are built from the feed URL and the episode title.
+pub fn make_feed(title: &str, episodes: &[(&str, &str)]) -> PodcastNoId {
+    let url = make_url(title);
+    return PodcastNoId {
+        title: title.to_string(),
+        url: url.clone(),
+        description: None,
+        author: None,
+        explicit: None,
+        last_checked: Utc::now(),
+        episodes: episodes
+            .iter()
+            .map(|(ep_title, description)| EpisodeNoId {
+                title: ep_title.to_string(),
+                url: format!("{url}/{ep_title}.mp3"),
+                guid: format!("{url}/{ep_title}"),
+                description: description.to_string(),
+                pubdate: None,
+                duration: None,
+                mime_type: None,
+            })
+            .collect(),
+    };
+}
+
+/// Creates an unplayed, undownloaded episode with nothing else set.
+pub fn make_episode(id: i64, pod_id: i64, title: &str) -> Episode {
+    return Episode {
+        id: id,
+        pod_id: pod_id,
+        title: title.to_string(),
+        url: "".to_string(),
+        guid: "".to_string(),
+        description: "".to_string(),
+        pubdate: None,
+        duration: None,
+        path: None,
+        played: false,
+        position: 0,
+        mime_type: None,
+        starred: false,
+        hidden: false,
+        notes: Vec::new(),
+    };
+}
+
+/// Creates a podcast with the given episodes and default settings.
+pub fn make_podcast(id: i64, title: &str, episodes: Vec<Episode>) -> Podcast {
+    return Podcast {
+        id: id,
+        title: title.to_string(),
+        sort_title: title.to_lowercase(),
+        url: make_url(title),
+        description: None,
+        author: None,
+        explicit: None,
+        last_checked: Utc::now(),
+        settings: PodcastSettings::default(),
+        tags: Vec::new(),
+        archived: false,
+        loaded: true,
+        counts: EpisodeCounts::default(),
+        episodes: LockVec::new(episodes),
+    };
+}
@@ -8,9 +8,11 @@ use chrono::{DateTime, Utc};
 use lazy_static::lazy_static;
 use nohash_hasher::BuildNoHashHasher;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use crate::downloads::DownloadMsg;
 use crate::feeds::FeedMsg;
+use crate::player::PlayerMsg;
 use crate::ui::UiMsg;
 
 lazy_static! {
@@ -28,7 +30,8 @@ pub trait Menuable {
 }
 
 /// Struct holding data about an individual podcast feed. This includes a
-/// (possibly empty) vector of episodes.
+/// (possibly empty) vector of episodes, which may not have been loaded
+/// from the database yet, in which case `counts` stands in for them.
 #[derive(Debug, Clone)]
 pub struct Podcast {
     pub id: i64,
@@ -39,18 +42,93 @@ pub struct Podcast {
     pub author: Option<String>,
     pub explicit: Option<bool>,
     pub last_checked: DateTime<Utc>,
+    pub settings: PodcastSettings,
+    pub tags: Vec<String>,
+    pub archived: bool,
+    pub loaded: bool,
+    pub counts: EpisodeCounts,
     pub episodes: LockVec<Episode>,
 }
 
 impl Podcast {
+    /// Checks whether the podcast has been given the tag.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        return self.tags.iter().any(|t| t == tag);
+    }
+
     /// Counts and returns the number of unplayed episodes in the podcast.
     fn num_unplayed(&self) -> usize {
+        if !self.loaded {
+            return self.counts.unplayed;
+        }
         return self
             .episodes
             .map(|ep| !ep.is_played() as usize, false)
             .iter()
             .sum();
     }
+
+    /// Returns the number of episodes in the podcast.
+    fn num_episodes(&self) -> usize {
+        if !self.loaded {
+            return self.counts.total;
+        }
+        return self.episodes.len(false);
+    }
+
+    /// Checks whether any episodes of the podcast have been downloaded.
+    pub fn any_downloaded(&self) -> bool {
+        if !self.loaded {
+            return self.counts.downloaded > 0;
+        }
+        return self.episodes.borrow_map().values().any(|ep| ep.path.is_some());
+    }
+}
+
+/// Numbers of episodes in a podcast, as counted by the database, so
+/// that podcasts can be listed before their episodes are loaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EpisodeCounts {
+    pub total: usize,
+    pub unplayed: usize,
+    pub downloaded: usize,
+}
+
+/// Playback settings chosen by the user for a single podcast.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PodcastSettings {
+    /// Playback speed, where 1.0 is normal speed.
+    pub speed: f64,
+    /// Number of seconds to skip at the start of each episode.
+    pub skip_intro: i64,
+    /// Number of seconds to skip at the end of each episode.
+    pub skip_outro: i64,
+}
+
+impl Default for PodcastSettings {
+    fn default() -> Self {
+        return PodcastSettings {
+            speed: 1.0,
+            skip_intro: 0,
+            skip_outro: 0,
+        };
+    }
+}
+
+/// Splits a comma-separated list of tags, as typed by the user or read
+/// from an OPML file, into a sorted list of tags without blanks or
+/// duplicates. Leading slashes are dropped, since OPML categories are
+/// written as paths, e.g., "/News".
+pub fn parse_tags(input: &str) -> Vec<String> {
+    let mut tags: Vec<String> = input
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches('/').trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    return tags;
 }
 
 impl Menuable for Podcast {
@@ -62,21 +140,26 @@ impl Menuable for Podcast {
     /// Returns the title for the podcast, up to length characters.
     fn get_title(&self, length: usize) -> String {
         let mut title_length = length;
+        let title = if self.archived {
+            format!("[A] {}", self.title)
+        } else {
+            self.title.clone()
+        };
 
         // if the size available is big enough, we add the unplayed data
         // to the end
         if length > crate::config::PODCAST_UNPLAYED_TOTALS_LENGTH {
-            let meta_str = format!("({}/{})", self.num_unplayed(), self.episodes.len(false));
+            let meta_str = format!("({}/{})", self.num_unplayed(), self.num_episodes());
             title_length = length - meta_str.chars().count() - 3;
 
-            let out = self.title.substr(0, title_length);
+            let out = title.substr(0, title_length);
 
             return format!(
                 " {out} {meta_str:>width$} ",
                 width = length - out.grapheme_len() - 3
             ); // this pads spaces between title and totals
         } else {
-            return format!(" {} ", self.title.substr(0, title_length - 2));
+            return format!(" {} ", title.substr(0, title_length - 2));
         }
     }
 
@@ -109,6 +192,9 @@ impl Ord for Podcast {
 /// is metadata, but if the episode has been downloaded to the local
 /// machine, the filepath will be included here as well. `played`
 /// indicates whether the podcast has been marked as played or unplayed.
+/// `position` is the playback position (in seconds) where the user
+/// last stopped listening. `mime_type` is the type of the enclosure, as
+/// given by the RSS feed.
 #[derive(Debug, Clone)]
 pub struct Episode {
     pub id: i64,
@@ -121,6 +207,11 @@ pub struct Episode {
     pub duration: Option<i64>,
     pub path: Option<PathBuf>,
     pub played: bool,
+    pub position: i64,
+    pub mime_type: Option<String>,
+    pub starred: bool,
+    pub hidden: bool,
+    pub notes: Vec<Note>,
 }
 
 impl Episode {
@@ -138,6 +229,23 @@ impl Episode {
             None => "--:--:--".to_string(),
         };
     }
+
+    /// Returns whether the user has started, but not finished,
+    /// listening to the episode. The position is reset once the episode
+    /// is finished, so this does not depend on whether it is marked as
+    /// played, which may happen as soon as it starts playing.
+    pub fn in_progress(&self) -> bool {
+        return self.position > 0;
+    }
+
+    /// Formats how far the user has listened into the episode, as a
+    /// percentage if the duration is known, or in minutes otherwise.
+    pub fn format_progress(&self) -> String {
+        return match self.duration {
+            Some(dur) if dur > 0 => format!("{}%", (self.position * 100 / dur).min(99)),
+            _ => format!("{}m", self.position / 60),
+        };
+    }
 }
 
 impl Menuable for Episode {
@@ -148,12 +256,25 @@ impl Menuable for Episode {
 
     /// Returns the title for the episode, up to length characters.
     fn get_title(&self, length: usize) -> String {
-        let out = match self.path {
-            Some(_) => {
-                let title = self.title.substr(0, length - 4);
-                format!("[D] {title}")
-            }
-            None => self.title.substr(0, length),
+        let mut prefix = String::new();
+        if self.hidden {
+            prefix.push_str("[H] ");
+        }
+        if self.starred {
+            prefix.push_str("[*] ");
+        }
+        if self.path.is_some() {
+            prefix.push_str("[D] ");
+        }
+        if self.in_progress() {
+            prefix.push_str(&format!("[{}] ", self.format_progress()));
+        }
+        let prefix_len = prefix.chars().count();
+        let out = if prefix_len < length {
+            let title = self.title.substr(0, length - prefix_len);
+            format!("{prefix}{title}")
+        } else {
+            self.title.substr(0, length)
         };
         if length > crate::config::EPISODE_PUBDATE_LENGTH {
             let dur = self.format_duration();
@@ -221,6 +342,7 @@ pub struct EpisodeNoId {
     pub description: String,
     pub pubdate: Option<DateTime<Utc>>,
     pub duration: Option<i64>,
+    pub mime_type: Option<String>,
 }
 
 /// Struct holding data about an individual podcast episode, specifically
@@ -266,6 +388,150 @@ impl Menuable for NewEpisode {
     }
 }
 
+/// Struct holding a record of one time an episode was played.
+/// `listened` is how much of the episode was heard, in seconds, and
+/// `finished` is whether the user got to the end of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub ep_id: i64,
+    pub pod_id: i64,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub listened: i64,
+    pub finished: bool,
+}
+
+/// Struct holding a note the user has written about an episode.
+/// `position` is the point in the episode the note refers to, in
+/// seconds, if the user gave one; such notes double as bookmarks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub id: i64,
+    pub ep_id: i64,
+    pub position: Option<i64>,
+    pub text: String,
+    pub created: DateTime<Utc>,
+}
+
+impl Note {
+    /// Formats the position of the note as MM:SS, or H:MM:SS for
+    /// positions past the first hour, e.g., "42:10".
+    pub fn format_position(&self) -> Option<String> {
+        return self.position.map(|pos| {
+            let hours = pos / 3600;
+            let minutes = (pos % 3600) / 60;
+            let seconds = pos % 60;
+            if hours > 0 {
+                format!("{hours}:{minutes:02}:{seconds:02}")
+            } else {
+                format!("{minutes:02}:{seconds:02}")
+            }
+        });
+    }
+
+    /// Formats the note as a single line of text, with its position in
+    /// brackets if it has one.
+    pub fn format(&self) -> String {
+        return match self.format_position() {
+            Some(pos) => format!("[{pos}] {}", self.text),
+            None => self.text.clone(),
+        };
+    }
+}
+
+impl Menuable for Note {
+    /// Returns the database ID for the note.
+    fn get_id(&self) -> i64 {
+        return self.id;
+    }
+
+    /// Returns the note, up to length characters.
+    fn get_title(&self, length: usize) -> String {
+        let full_string = format!(" {} ", self.format());
+        return full_string.substr(0, length);
+    }
+
+    fn is_played(&self) -> bool {
+        return true;
+    }
+}
+
+/// Struct holding data about an episode in the play queue, for display
+/// in the queue popup window.
+#[derive(Debug, Clone)]
+pub struct QueueItem {
+    pub id: i64,
+    pub pod_id: i64,
+    pub title: String,
+    pub pod_title: String,
+    pub downloaded: bool,
+}
+
+impl Menuable for QueueItem {
+    /// Returns the database ID for the episode.
+    fn get_id(&self) -> i64 {
+        return self.id;
+    }
+
+    /// Returns the title for the episode, up to length characters.
+    fn get_title(&self, length: usize) -> String {
+        let downloaded = if self.downloaded { "[D] " } else { "" };
+        let full_string = format!(" {}{} ({}) ", downloaded, self.title, self.pod_title);
+        return full_string.substr(0, length);
+    }
+
+    fn is_played(&self) -> bool {
+        return true;
+    }
+}
+
+/// Struct holding data about an episode found by searching, for
+/// display in the search popup window.
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub id: i64,
+    pub pod_id: i64,
+    pub title: String,
+    pub pod_title: String,
+    pub pubdate: Option<DateTime<Utc>>,
+    pub played: bool,
+    pub hidden: bool,
+}
+
+impl SearchResult {
+    /// Formats the result as a single line of text, e.g., for printing
+    /// to the terminal. Hidden episodes are marked with "[H]".
+    pub fn format(&self) -> String {
+        let prefix = if self.hidden { "[H] " } else { "" };
+        return match self.pubdate {
+            Some(pd) => format!(
+                "{prefix}{} ({}, {})",
+                self.title,
+                self.pod_title,
+                pd.format("%F")
+            ),
+            None => format!("{prefix}{} ({})", self.title, self.pod_title),
+        };
+    }
+}
+
+impl Menuable for SearchResult {
+    /// Returns the database ID for the episode.
+    fn get_id(&self) -> i64 {
+        return self.id;
+    }
+
+    /// Returns the title for the episode, up to length characters.
+    fn get_title(&self, length: usize) -> String {
+        let full_string = format!(" {} ", self.format());
+        return full_string.substr(0, length);
+    }
+
+    fn is_played(&self) -> bool {
+        return self.played;
+    }
+}
+
 /// Struct used to hold a vector of data inside a reference-counted
 /// mutex, to allow for multiple owners of mutable data.
 /// Primarily, the LockVec is used to provide methods that abstract
@@ -308,17 +574,17 @@ impl<T: Clone + Menuable> LockVec<T> {
     }
 
     /// Lock the LockVec hashmap for reading/writing.
-    pub fn borrow_map(&self) -> MutexGuard<HashMap<i64, T, BuildNoHashHasher<i64>>> {
+    pub fn borrow_map(&self) -> MutexGuard<'_, HashMap<i64, T, BuildNoHashHasher<i64>>> {
         return self.data.lock().expect("Mutex error");
     }
 
     /// Lock the LockVec order vector for reading/writing.
-    pub fn borrow_order(&self) -> MutexGuard<Vec<i64>> {
+    pub fn borrow_order(&self) -> MutexGuard<'_, Vec<i64>> {
         return self.order.lock().expect("Mutex error");
     }
 
     /// Lock the LockVec filtered order vector for reading/writing.
-    pub fn borrow_filtered_order(&self) -> MutexGuard<Vec<i64>> {
+    pub fn borrow_filtered_order(&self) -> MutexGuard<'_, Vec<i64>> {
         return self.filtered_order.lock().expect("Mutex error");
     }
 
@@ -327,9 +593,9 @@ impl<T: Clone + Menuable> LockVec<T> {
     pub fn borrow(
         &self,
     ) -> (
-        MutexGuard<HashMap<i64, T, BuildNoHashHasher<i64>>>,
-        MutexGuard<Vec<i64>>,
-        MutexGuard<Vec<i64>>,
+        MutexGuard<'_, HashMap<i64, T, BuildNoHashHasher<i64>>>,
+        MutexGuard<'_, Vec<i64>>,
+        MutexGuard<'_, Vec<i64>>,
     ) {
         return (
             self.data.lock().expect("Mutex error"),
@@ -345,6 +611,14 @@ impl<T: Clone + Menuable> LockVec<T> {
         borrowed.insert(id, t);
     }
 
+    /// Removes the item with the given id, if there is one.
+    pub fn remove(&self, id: i64) {
+        let (mut map, mut order, mut filtered_order) = self.borrow();
+        map.remove(&id);
+        order.retain(|i| *i != id);
+        filtered_order.retain(|i| *i != id);
+    }
+
     /// Empty out and replace all the data in the LockVec.
     pub fn replace_all(&self, data: Vec<T>) {
         let (mut map, mut order, mut filtered_order) = self.borrow();
@@ -416,7 +690,7 @@ impl<T: Clone + Menuable> LockVec<T> {
     /// *not* the sense of the word here.
     pub fn filter_map<B, F>(&self, mut f: F) -> Vec<B>
     where F: FnMut(&T) -> Option<B> {
-        let (map, order, _) = self.borrow();
+        let (map, order, _filtered_order) = self.borrow();
         return order
             .iter()
             .filter_map(|id| f(map.get(id).expect("Index error in LockVec")))
@@ -449,6 +723,21 @@ impl<T: Clone + Menuable> Clone for LockVec<T> {
 }
 
 impl LockVec<Podcast> {
+    /// Adds a podcast in its place in the sorted order, or replaces
+    /// it if it is already in the list. It is filtered in once the
+    /// filters are next updated.
+    pub fn add_podcast(&self, podcast: Podcast) {
+        let (mut map, mut order, _filtered_order) = self.borrow();
+        if !map.contains_key(&podcast.id) {
+            let index = order
+                .iter()
+                .position(|id| map.get(id).is_some_and(|pod| *pod > podcast))
+                .unwrap_or(order.len());
+            order.insert(index, podcast.id);
+        }
+        map.insert(podcast.id, podcast);
+    }
+
     /// This clones the podcast with the given id.
     pub fn clone_podcast(&self, id: i64) -> Option<Podcast> {
         let pod_map = self.borrow_map();
@@ -486,6 +775,7 @@ pub enum Message {
     Ui(UiMsg),
     Feed(FeedMsg),
     Dl(DownloadMsg),
+    Player(PlayerMsg),
 }
 
 
@@ -503,13 +793,33 @@ pub enum FilterStatus {
 pub enum FilterType {
     Played,
     Downloaded,
+    Starred,
+    Tag,
+    Archived,
+    Hidden,
 }
 
-/// Struct holding information about all active filters.
-#[derive(Debug, Clone, Copy)]
+/// Struct holding information about all active filters. If `tag` is
+/// set, only podcasts with that tag are shown.
+#[derive(Debug, Clone)]
 pub struct Filters {
     pub played: FilterStatus,
     pub downloaded: FilterStatus,
+    pub starred: FilterStatus,
+    pub tag: Option<String>,
+    pub archived: FilterStatus,
+    pub hidden: FilterStatus,
+}
+
+impl Filters {
+    /// Checks whether any of the filters apply to individual episodes,
+    /// rather than to whole podcasts.
+    pub fn filters_episodes(&self) -> bool {
+        return !matches!(self.played, FilterStatus::All)
+            || !matches!(self.downloaded, FilterStatus::All)
+            || !matches!(self.starred, FilterStatus::All)
+            || matches!(self.hidden, FilterStatus::PositiveCases);
+    }
 }
 
 impl Default for Filters {
@@ -517,6 +827,10 @@ impl Default for Filters {
         return Self {
             played: FilterStatus::All,
             downloaded: FilterStatus::All,
+            starred: FilterStatus::All,
+            tag: None,
+            archived: FilterStatus::NegativeCases,
+            hidden: FilterStatus::NegativeCases,
         };
     }
 }
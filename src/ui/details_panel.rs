@@ -27,9 +27,12 @@ pub struct Details {
     pub pubdate: Option<DateTime<Utc>>,
     pub duration: Option<String>,
     pub explicit: Option<bool>,
+    pub tags: Option<String>,
+    pub notes: Vec<String>,
     pub description: Option<String>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct DetailsPanel {
     pub panel: Panel,
@@ -194,8 +197,32 @@ impl DetailsPanel {
                 ));
             }
 
+            // tags
+            if let Some(tags) = &details.tags {
+                self.content.push(DetailsLine::KeyValueLine(
+                    ("Tags".to_string(), Some(underlined)),
+                    (tags.clone(), None),
+                ));
+            }
+
             self.content.push(DetailsLine::Blank); // blank line
 
+            // notes
+            if !details.notes.is_empty() {
+                let wrapper = textwrap::wrap("Notes:", num_cols);
+                for line in wrapper {
+                    self.content
+                        .push(DetailsLine::Line(line.to_string(), Some(bold)));
+                }
+                for note in details.notes.iter() {
+                    let wrapper = textwrap::wrap(note, num_cols);
+                    for line in wrapper {
+                        self.content.push(DetailsLine::Line(line.to_string(), None));
+                    }
+                }
+                self.content.push(DetailsLine::Blank); // blank line
+            }
+
             // description
             match &details.description {
                 Some(desc) => {
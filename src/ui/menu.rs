@@ -68,7 +68,7 @@ impl<T: Clone + Menuable> Menu<T> {
             self.selected = self.start_row;
         }
 
-        let (map, _, order) = self.items.borrow();
+        let (map, _order, order) = self.items.borrow();
         if !order.is_empty() {
             // update selected item if list has gotten shorter
             let current_selected = self.get_menu_idx(self.selected);
@@ -268,6 +268,23 @@ impl<T: Clone + Menuable> Menu<T> {
         self.redraw();
     }
 
+    /// Moves the cursor to the item at `index` in the (filtered) list,
+    /// scrolling the list if needed so that the item is on screen. Does
+    /// not redraw the menu.
+    pub fn select_index(&mut self, index: usize) {
+        if index >= self.items.len(true) {
+            return;
+        }
+        let visible_rows = (self.panel.get_rows() - self.start_row) as usize;
+        let top_row = if index < visible_rows {
+            0
+        } else {
+            index + 1 - visible_rows
+        };
+        self.top_row = top_row as u16;
+        self.selected = self.start_row + (index - top_row) as u16;
+    }
+
     /// Given a row on the panel, this translates it into the
     /// corresponding menu item it represents. Note that this does not
     /// do any checks to ensure `screen_y` is between 0 and `n_rows`,
@@ -284,7 +301,7 @@ impl Menu<Podcast> {
     /// currently selected podcast.
     pub fn get_episodes(&self) -> LockVec<Episode> {
         let index = self.get_menu_idx(self.selected);
-        let (borrowed_map, _, borrowed_order) = self.items.borrow();
+        let (borrowed_map, _order, borrowed_order) = self.items.borrow();
         let pod_id = borrowed_order
             .get(index)
             .expect("Could not retrieve podcast.");
@@ -319,6 +336,35 @@ impl Menu<Episode> {
     }
 }
 
+impl Menu<QueueItem> {
+    /// Returns the currently selected episode in the play queue, if
+    /// the queue is not empty.
+    pub fn get_selected(&self) -> Option<QueueItem> {
+        return self
+            .items
+            .map_single_by_index(self.get_menu_idx(self.selected), |item| item.clone());
+    }
+}
+
+impl Menu<SearchResult> {
+    /// Returns the currently selected search result, if there are any
+    /// results.
+    pub fn get_selected(&self) -> Option<SearchResult> {
+        return self
+            .items
+            .map_single_by_index(self.get_menu_idx(self.selected), |item| item.clone());
+    }
+}
+
+impl Menu<Note> {
+    /// Returns the currently selected note, if there are any notes.
+    pub fn get_selected(&self) -> Option<Note> {
+        return self
+            .items
+            .map_single_by_index(self.get_menu_idx(self.selected), |item| item.clone());
+    }
+}
+
 impl Menu<NewEpisode> {
     /// Changes the status of the currently highlighted episode -- if it
     /// was selected to be downloaded, it will be unselected, and vice
@@ -353,7 +399,7 @@ impl Menu<NewEpisode> {
     fn change_item_selections(&mut self, indexes: Vec<usize>, selection: Option<bool>) -> bool {
         let mut changed = false;
         {
-            let (mut borrowed_map, borrowed_order, _) = self.items.borrow();
+            let (mut borrowed_map, borrowed_order, _filtered_order) = self.items.borrow();
             for idx in indexes {
                 if let Some(ep_id) = borrowed_order.get(idx) {
                     if let Entry::Occupied(mut ep) = borrowed_map.entry(*ep_id) {
@@ -381,7 +427,7 @@ mod tests {
 
     fn create_menu(n_row: u16, n_col: u16, top_row: u16, selected: u16) -> Menu<Episode> {
         let colors = Rc::new(crate::ui::AppColors::default());
-        let titles = vec![
+        let titles = [
             "A Very Cool Episode",
             "This is a very long episode title but we'll get through it together",
             "An episode with le Unicodé",
@@ -404,6 +450,11 @@ mod tests {
                 duration: Some(12345),
                 path: None,
                 played: played,
+                position: 0,
+                mime_type: None,
+                starred: false,
+                hidden: false,
+                notes: Vec::new(),
             });
         }
 
@@ -5,6 +5,7 @@ use crossterm::style;
 use super::AppColors;
 
 /// Struct holding the raw data used for building the details panel.
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct Panel {
     pub buffer: Vec<String>,
@@ -76,7 +77,7 @@ impl Panel {
     ) -> u16 {
         let mut row = start_y;
         let max_row = self.get_rows();
-        let wrapper = textwrap::wrap(&string, self.get_cols() as usize);
+        let wrapper = textwrap::wrap(string, self.get_cols() as usize);
         for line in wrapper {
             self.write_line(row, line.to_string(), None);
             row += 1;
@@ -2,8 +2,9 @@ use std::io::{self, Write};
 use std::rc::Rc;
 use std::sync::mpsc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use chrono::Utc;
 use crossterm::{
     self, cursor,
     event::{self, Event},
@@ -30,8 +31,11 @@ use self::panel::Panel;
 use self::popup::PopupWin;
 
 use super::MainMessage;
-use crate::config::Config;
+use crate::config::{Config, MAX_SPEED, MIN_SPEED};
 use crate::keymap::{Keybindings, UserAction};
+use crate::notes;
+use crate::play_file;
+use crate::player::{PlayerCommand, SleepTimer};
 use crate::types::*;
 
 /// Amount of time between ticks in the event loop
@@ -60,6 +64,9 @@ pub enum UiMsg {
     Play(i64, i64),
     MarkPlayed(i64, i64, bool),
     MarkAllPlayed(i64, bool),
+    Star(i64, i64, bool),
+    Archive(i64, bool),
+    Unhide(i64, i64),
     Sync(i64),
     SyncAll,
     Download(i64, i64),
@@ -71,6 +78,25 @@ pub enum UiMsg {
     RemoveEpisode(i64, i64, bool),
     RemoveAllEpisodes(i64, bool),
     FilterChange(FilterType),
+    Player(PlayerCommand),
+    QueueAdd(i64, i64, bool),
+    QueueRemove(i64),
+    QueueMove(i64, i64),
+    PodcastSettings(i64, PodcastSettings),
+    SetTags(i64, Vec<String>),
+    SleepTimer(Option<SleepTimer>),
+    ShowStats,
+    Search(String),
+    AddNote(i64, i64, Option<i64>, String),
+    RemoveNote(i64, i64, i64),
+    JumpToNote(i64, i64, i64),
+    LoadEpisodes(i64),
+    Undo,
+    // these are handled by the UI itself, and never reach the main
+    // controller
+    SearchPrompt,
+    GoToEpisode(i64, i64),
+    NotePrompt(i64, i64),
     Quit,
     Noop,
 }
@@ -98,6 +124,9 @@ pub struct Ui<'a> {
     n_row: u16,
     n_col: u16,
     keymap: &'a Keybindings,
+    seek_length: i64,
+    sleep_presets: Vec<u64>,
+    sleep_cycle: usize,
     colors: Rc<AppColors>,
     podcast_menu: Menu<Podcast>,
     episode_menu: Menu<Episode>,
@@ -105,6 +134,7 @@ pub struct Ui<'a> {
     active_panel: ActivePanel,
     notif_win: NotifWin,
     popup_win: PopupWin<'a>,
+    load_requested: Option<i64>,
 }
 
 impl<'a> Ui<'a> {
@@ -126,6 +156,14 @@ impl<'a> Ui<'a> {
             loop {
                 ui.notif_win.check_notifs();
 
+                // this comes before any input, so that the episodes are
+                // loaded before anything is done with them
+                if let Some(input) = ui.request_episodes() {
+                    tx_to_main
+                        .send(Message::Ui(input))
+                        .expect("Thread messaging error");
+                }
+
                 match ui.getch() {
                     UiMsg::Noop => (),
                     input => tx_to_main
@@ -135,7 +173,10 @@ impl<'a> Ui<'a> {
 
                 if let Some(message) = message_iter.next() {
                     match message {
-                        MainMessage::UiUpdateMenus => ui.update_menus(),
+                        MainMessage::UiUpdateMenus => {
+                            ui.update_menus();
+                            ui.update_notes_win();
+                        }
                         MainMessage::UiSpawnNotif(msg, duration, error) => {
                             ui.timed_notif(msg, error, duration)
                         }
@@ -150,6 +191,24 @@ impl<'a> Ui<'a> {
                         MainMessage::UiSpawnDownloadPopup(episodes, selected) => {
                             ui.popup_win.spawn_download_win(episodes, selected);
                         }
+                        MainMessage::UiUpdateNowPlaying(now_playing) => {
+                            ui.notif_win.set_status(now_playing.map(|np| np.format()));
+                        }
+                        MainMessage::UiUpdateSleepTimer(timer) => {
+                            if timer.is_none() {
+                                ui.sleep_cycle = 0;
+                            }
+                            ui.notif_win.set_sleep_timer(timer);
+                        }
+                        MainMessage::UiShowStats(stats) => {
+                            ui.popup_win.spawn_stats_win(stats.format(Utc::now()));
+                        }
+                        MainMessage::UiSearchResults(query, results) => {
+                            ui.popup_win.spawn_search_win(query, results);
+                        }
+                        MainMessage::UiUpdateQueue(queue) => {
+                            ui.popup_win.update_queue(queue);
+                        }
                     }
                 }
 
@@ -179,7 +238,7 @@ impl<'a> Ui<'a> {
         let (n_col, n_row) = terminal::size().expect("Can't get terminal size");
         let (pod_col, ep_col, det_col) = Self::calculate_sizes(n_col);
 
-        let first_pod = match items.borrow_filtered_order().get(0) {
+        let first_pod = match items.borrow_filtered_order().first() {
             Some(first_id) => match items.borrow_map().get(first_id) {
                 Some(pod) => pod.episodes.clone(),
                 None => LockVec::new(Vec::new()),
@@ -231,6 +290,9 @@ impl<'a> Ui<'a> {
             n_row: n_row,
             n_col: n_col,
             keymap: &config.keybindings,
+            seek_length: config.seek_length,
+            sleep_presets: config.sleep_timer_presets.clone(),
+            sleep_cycle: 0,
             colors: colors,
             podcast_menu: podcast_menu,
             episode_menu: episode_menu,
@@ -238,6 +300,7 @@ impl<'a> Ui<'a> {
             active_panel: ActivePanel::PodcastMenu,
             notif_win: notif_win,
             popup_win: popup_win,
+            load_requested: None,
         };
     }
 
@@ -266,6 +329,7 @@ impl<'a> Ui<'a> {
     /// some greater degree of abstraction; for example, input to add a
     /// new podcast feed spawns a UI window to capture the feed URL, and
     /// only then passes this data back to the main controller.
+    #[allow(clippy::collapsible_match)]
     pub fn getch(&mut self) -> UiMsg {
         if event::poll(Duration::from_secs(0)).expect("Can't poll for inputs") {
             match event::read().expect("Can't read inputs") {
@@ -283,7 +347,19 @@ impl<'a> Ui<'a> {
                     // welcome window which takes no input), then
                     // redirect user input there
                     if self.popup_win.is_non_welcome_popup_active() {
-                        let popup_msg = self.popup_win.handle_input(input);
+                        let mut popup_msg = self.popup_win.handle_input(input);
+                        match popup_msg {
+                            UiMsg::SearchPrompt => popup_msg = self.search_prompt(),
+                            UiMsg::GoToEpisode(pod_id, ep_id) => {
+                                self.go_to_episode(pod_id, ep_id);
+                                popup_msg = UiMsg::Noop;
+                            }
+                            UiMsg::NotePrompt(pod_id, ep_id) => {
+                                popup_msg =
+                                    self.add_note(Some(pod_id), Some(ep_id)).unwrap_or(UiMsg::Noop);
+                            }
+                            _ => (),
+                        }
 
                         // need to check if popup window is still active,
                         // as handling character input above may involve
@@ -349,6 +425,25 @@ impl<'a> Ui<'a> {
                                     return ui_msg;
                                 }
                             }
+                            Some(UserAction::Star) => {
+                                if let ActivePanel::EpisodeMenu = self.active_panel {
+                                    if let Some(ui_msg) = self.star(curr_pod_id, curr_ep_id) {
+                                        return ui_msg;
+                                    }
+                                }
+                            }
+                            Some(UserAction::Unhide) => {
+                                if let ActivePanel::EpisodeMenu = self.active_panel {
+                                    if let Some(ui_msg) = self.unhide(curr_pod_id, curr_ep_id) {
+                                        return ui_msg;
+                                    }
+                                }
+                            }
+                            Some(UserAction::Archive) => {
+                                if let Some(ui_msg) = self.archive(curr_pod_id) {
+                                    return ui_msg;
+                                }
+                            }
 
                             Some(UserAction::Download) => {
                                 if let Some(pod_id) = curr_pod_id {
@@ -412,6 +507,92 @@ impl<'a> Ui<'a> {
                             Some(UserAction::FilterDownloaded) => {
                                 return UiMsg::FilterChange(FilterType::Downloaded);
                             }
+                            Some(UserAction::FilterStarred) => {
+                                return UiMsg::FilterChange(FilterType::Starred);
+                            }
+                            Some(UserAction::FilterTag) => {
+                                return UiMsg::FilterChange(FilterType::Tag);
+                            }
+                            Some(UserAction::FilterArchived) => {
+                                return UiMsg::FilterChange(FilterType::Archived);
+                            }
+                            Some(UserAction::FilterHidden) => {
+                                return UiMsg::FilterChange(FilterType::Hidden);
+                            }
+
+                            Some(UserAction::PlayPause) => {
+                                return UiMsg::Player(PlayerCommand::TogglePause);
+                            }
+                            Some(UserAction::SeekForward) => {
+                                return UiMsg::Player(PlayerCommand::Seek(self.seek_length));
+                            }
+                            Some(UserAction::SeekBackward) => {
+                                return UiMsg::Player(PlayerCommand::Seek(-self.seek_length));
+                            }
+                            Some(UserAction::SpeedUp) => {
+                                return UiMsg::Player(PlayerCommand::ChangeSpeed(
+                                    crate::config::SPEED_STEP,
+                                ));
+                            }
+                            Some(UserAction::SpeedDown) => {
+                                return UiMsg::Player(PlayerCommand::ChangeSpeed(
+                                    -crate::config::SPEED_STEP,
+                                ));
+                            }
+                            Some(UserAction::StopPlayback) => {
+                                return UiMsg::Player(PlayerCommand::Stop);
+                            }
+                            Some(UserAction::SleepTimer) => {
+                                return UiMsg::SleepTimer(self.next_sleep_timer());
+                            }
+                            Some(UserAction::SleepTimerCustom) => {
+                                if let Some(ui_msg) = self.custom_sleep_timer() {
+                                    return ui_msg;
+                                }
+                            }
+
+                            Some(UserAction::Enqueue) => {
+                                if let Some(pod_id) = curr_pod_id {
+                                    if let Some(ep_id) = curr_ep_id {
+                                        return UiMsg::QueueAdd(pod_id, ep_id, false);
+                                    }
+                                }
+                            }
+                            Some(UserAction::EnqueueNext) => {
+                                if let Some(pod_id) = curr_pod_id {
+                                    if let Some(ep_id) = curr_ep_id {
+                                        return UiMsg::QueueAdd(pod_id, ep_id, true);
+                                    }
+                                }
+                            }
+                            Some(UserAction::ShowQueue) => self.popup_win.spawn_queue_win(),
+                            // these only apply in the queue window
+                            Some(UserAction::MoveUp) | Some(UserAction::MoveDown) => (),
+
+                            Some(UserAction::ShowStats) => return UiMsg::ShowStats,
+                            Some(UserAction::Search) => return self.search_prompt(),
+
+                            Some(UserAction::AddNote) => {
+                                if let Some(ui_msg) = self.add_note(curr_pod_id, curr_ep_id) {
+                                    return ui_msg;
+                                }
+                            }
+                            Some(UserAction::ShowNotes) => {
+                                self.show_notes(curr_pod_id, curr_ep_id);
+                            }
+
+                            Some(UserAction::PodcastSettings) => {
+                                if let Some(ui_msg) = self.edit_podcast_settings(curr_pod_id) {
+                                    return ui_msg;
+                                }
+                            }
+                            Some(UserAction::EditTags) => {
+                                if let Some(ui_msg) = self.edit_tags(curr_pod_id) {
+                                    return ui_msg;
+                                }
+                            }
+
+                            Some(UserAction::Undo) => return UiMsg::Undo,
 
                             Some(UserAction::Help) => self.popup_win.spawn_help_win(),
 
@@ -473,6 +654,7 @@ impl<'a> Ui<'a> {
     }
 
     /// Move the menu cursor around and redraw menus when necessary.
+    #[allow(clippy::collapsible_match)]
     pub fn move_cursor(
         &mut self,
         action: &UserAction,
@@ -613,6 +795,33 @@ impl<'a> Ui<'a> {
         return None;
     }
 
+    /// Star or unstar an episode (opposite of its current status).
+    pub fn star(&mut self, curr_pod_id: Option<i64>, curr_ep_id: Option<i64>) -> Option<UiMsg> {
+        let pod_id = curr_pod_id?;
+        let ep_id = curr_ep_id?;
+        let starred = self.episode_menu.items.map_single(ep_id, |ep| ep.starred)?;
+        return Some(UiMsg::Star(pod_id, ep_id, !starred));
+    }
+
+    /// Unhide an episode that was removed from the list. Only hidden
+    /// episodes, which are shown with the hidden filter, can be unhidden.
+    pub fn unhide(&mut self, curr_pod_id: Option<i64>, curr_ep_id: Option<i64>) -> Option<UiMsg> {
+        let pod_id = curr_pod_id?;
+        let ep_id = curr_ep_id?;
+        let hidden = self.episode_menu.items.map_single(ep_id, |ep| ep.hidden)?;
+        if !hidden {
+            return None;
+        }
+        return Some(UiMsg::Unhide(pod_id, ep_id));
+    }
+
+    /// Archive or unarchive a podcast (opposite of its current status).
+    pub fn archive(&mut self, curr_pod_id: Option<i64>) -> Option<UiMsg> {
+        let pod_id = curr_pod_id?;
+        let archived = self.podcast_menu.items.map_single(pod_id, |pod| pod.archived)?;
+        return Some(UiMsg::Archive(pod_id, !archived));
+    }
+
     /// Mark all episodes for a given podcast as played or unplayed. If
     /// there are any unplayed episodes, this will convert all episodes
     /// to played; if all are played already, only then will it convert
@@ -630,6 +839,165 @@ impl<'a> Ui<'a> {
         return None;
     }
 
+    /// Moves on to the next setting for the sleep timer, going through
+    /// each of the presets, then the end of the episode, then off.
+    fn next_sleep_timer(&mut self) -> Option<SleepTimer> {
+        self.sleep_cycle = (self.sleep_cycle + 1) % (self.sleep_presets.len() + 2);
+        return match self.sleep_cycle {
+            0 => None,
+            i if i <= self.sleep_presets.len() => {
+                let mins = self.sleep_presets[i - 1];
+                Some(SleepTimer::At(Instant::now() + Duration::from_secs(mins * 60)))
+            }
+            _ => Some(SleepTimer::EndOfEpisode),
+        };
+    }
+
+    /// Asks the user how long until the sleep timer should stop
+    /// playback, in minutes or as hours and minutes (e.g., "1:30").
+    fn custom_sleep_timer(&mut self) -> Option<UiMsg> {
+        let input = self.spawn_input_notif(
+            "Sleep after minutes (or H:MM, \"e\" for end of episode, 0 to turn off): ",
+        );
+        let input = input.trim();
+        if input.is_empty() {
+            return None;
+        }
+        if input.eq_ignore_ascii_case("e") {
+            self.sleep_cycle = self.sleep_presets.len() + 1;
+            return Some(UiMsg::SleepTimer(Some(SleepTimer::EndOfEpisode)));
+        }
+        match parse_sleep_minutes(input) {
+            Some(0) => return Some(UiMsg::SleepTimer(None)),
+            Some(mins) => {
+                // start the presets from the beginning again after this
+                self.sleep_cycle = 0;
+                let time = Instant::now() + Duration::from_secs(mins * 60);
+                return Some(UiMsg::SleepTimer(Some(SleepTimer::At(time))));
+            }
+            None => {
+                self.timed_notif(
+                    "Enter a number of minutes, or a time like 1:30.".to_string(),
+                    5000,
+                    true,
+                );
+                return None;
+            }
+        }
+    }
+
+    /// Asks the user for the playback speed and the number of seconds
+    /// to skip at the start and end of episodes for the current
+    /// podcast. Leaving an answer empty keeps the current value.
+    pub fn edit_podcast_settings(&mut self, curr_pod_id: Option<i64>) -> Option<UiMsg> {
+        let pod_id = curr_pod_id?;
+        let mut settings = self.podcast_menu.items.map_single(pod_id, |pod| pod.settings)?;
+
+        let input = self.spawn_input_notif(&format!("Playback speed [{}]: ", settings.speed));
+        if !input.trim().is_empty() {
+            match input.trim().parse::<f64>() {
+                Ok(speed) if (MIN_SPEED..=MAX_SPEED).contains(&speed) => settings.speed = speed,
+                _ => {
+                    self.timed_notif(
+                        format!("Speed must be a number from {MIN_SPEED} to {MAX_SPEED}."),
+                        5000,
+                        true,
+                    );
+                    return None;
+                }
+            }
+        }
+
+        let prompts = [
+            ("Seconds to skip at start", &mut settings.skip_intro),
+            ("Seconds to skip at end", &mut settings.skip_outro),
+        ];
+        for (prompt, value) in prompts {
+            let input = self.spawn_input_notif(&format!("{prompt} [{value}]: "));
+            let input = input.trim();
+            if input.is_empty() {
+                continue;
+            }
+            // accept either plain seconds or a timestamp like 1:30
+            match input.parse::<i64>().ok().or_else(|| play_file::parse_timestamp(input)) {
+                Some(secs) if secs >= 0 => *value = secs,
+                _ => {
+                    self.timed_notif(
+                        "Enter a number of seconds, or a time like 1:30.".to_string(),
+                        5000,
+                        true,
+                    );
+                    return None;
+                }
+            }
+        }
+        return Some(UiMsg::PodcastSettings(pod_id, settings));
+    }
+
+    /// Asks the user for the tags to give the selected podcast, as a
+    /// comma-separated list that replaces its current tags. Leaving the
+    /// answer empty keeps the current tags, and "-" removes them all.
+    pub fn edit_tags(&mut self, curr_pod_id: Option<i64>) -> Option<UiMsg> {
+        let pod_id = curr_pod_id?;
+        let tags = self
+            .podcast_menu
+            .items
+            .map_single(pod_id, |pod| pod.tags.join(", "))?;
+
+        let input = self.spawn_input_notif(&format!("Tags [{tags}] (\"-\" for none): "));
+        let input = input.trim();
+        if input.is_empty() {
+            return None;
+        }
+        if input == "-" {
+            return Some(UiMsg::SetTags(pod_id, Vec::new()));
+        }
+        return Some(UiMsg::SetTags(pod_id, parse_tags(input)));
+    }
+
+    /// Asks the user for a note to add to the selected episode. If the
+    /// note starts with a time like 42:10, it is saved as a bookmark at
+    /// that point in the episode.
+    pub fn add_note(&mut self, curr_pod_id: Option<i64>, curr_ep_id: Option<i64>) -> Option<UiMsg> {
+        let pod_id = curr_pod_id?;
+        let ep_id = curr_ep_id?;
+
+        let input = self.spawn_input_notif("Note (start with a time like 42:10 to bookmark it): ");
+        let input = input.trim();
+        if input.is_empty() {
+            return None;
+        }
+        let (position, text) = notes::parse_note(input);
+        return Some(UiMsg::AddNote(pod_id, ep_id, position, text));
+    }
+
+    /// Opens a window listing the notes on the selected episode.
+    pub fn show_notes(&mut self, curr_pod_id: Option<i64>, curr_ep_id: Option<i64>) {
+        if let (Some(pod_id), Some(ep_id)) = (curr_pod_id, curr_ep_id) {
+            if let Some((title, notes)) = self
+                .episode_menu
+                .items
+                .map_single(ep_id, |ep| (ep.title.clone(), ep.notes.clone()))
+            {
+                self.popup_win.spawn_notes_win(pod_id, ep_id, title, notes);
+            }
+        }
+    }
+
+    /// Refreshes the notes window, if it is open, after notes have been
+    /// added or removed.
+    pub fn update_notes_win(&mut self) {
+        if let Some((pod_id, ep_id)) = self.popup_win.notes_episode() {
+            let notes = self
+                .podcast_menu
+                .items
+                .clone_episode(pod_id, ep_id)
+                .map(|ep| ep.notes)
+                .unwrap_or_default();
+            self.popup_win.update_notes(notes);
+        }
+    }
+
     /// Remove a podcast from the list.
     pub fn remove_podcast(&mut self, curr_pod_id: Option<i64>) -> Option<UiMsg> {
         let confirm = self.ask_for_confirmation("Are you sure you want to remove the podcast?");
@@ -700,6 +1068,73 @@ impl<'a> Ui<'a> {
     }
 
 
+    /// Asks the user what to search for. Returns a message asking the
+    /// main controller to run the search, or `UiMsg::Noop` if the user
+    /// cancelled.
+    pub fn search_prompt(&self) -> UiMsg {
+        let query = self.spawn_input_notif("Search: ");
+        if query.trim().is_empty() {
+            return UiMsg::Noop;
+        }
+        return UiMsg::Search(query.trim().to_string());
+    }
+
+    /// Selects the given podcast and episode in the menus, and moves
+    /// the focus to the episode menu. If the current filters hide the
+    /// podcast or the episode, a notification says so instead.
+    pub fn go_to_episode(&mut self, pod_id: i64, ep_id: i64) {
+        let pod_index = self
+            .podcast_menu
+            .items
+            .borrow_filtered_order()
+            .iter()
+            .position(|id| *id == pod_id);
+        let pod_index = match pod_index {
+            Some(index) => index,
+            None => {
+                self.timed_notif(
+                    "That podcast is hidden by the current filters.".to_string(),
+                    crate::config::MESSAGE_TIME,
+                    true,
+                );
+                return;
+            }
+        };
+        self.podcast_menu.select_index(pod_index);
+        self.episode_menu.items = self.podcast_menu.get_episodes();
+        self.episode_menu.top_row = 0;
+        self.episode_menu.selected = 0;
+
+        let ep_index = self
+            .episode_menu
+            .items
+            .borrow_filtered_order()
+            .iter()
+            .position(|id| *id == ep_id);
+        match ep_index {
+            Some(index) => {
+                self.episode_menu.select_index(index);
+                self.active_panel = ActivePanel::EpisodeMenu;
+                self.podcast_menu.active = false;
+                self.episode_menu.active = true;
+            }
+            None => {
+                self.active_panel = ActivePanel::PodcastMenu;
+                self.podcast_menu.active = true;
+                self.episode_menu.active = false;
+                self.timed_notif(
+                    "That episode is hidden by the current filters.".to_string(),
+                    crate::config::MESSAGE_TIME,
+                    true,
+                );
+            }
+        }
+        self.podcast_menu.redraw();
+        self.episode_menu.redraw();
+        self.highlight_items();
+        self.update_details_panel();
+    }
+
     /// Based on the current selected value of the podcast and episode
     /// menus, returns the IDs of the current podcast and episode (if
     /// they exist).
@@ -731,11 +1166,11 @@ impl<'a> Ui<'a> {
         let ep_col;
         let det_col;
         if n_col > crate::config::DETAILS_PANEL_LENGTH {
-            pod_col = (n_col + 2) / 3;
-            ep_col = (n_col + 2) / 3;
+            pod_col = n_col.div_ceil(3);
+            ep_col = n_col.div_ceil(3);
             det_col = n_col + 2 - pod_col - ep_col;
         } else {
-            pod_col = (n_col + 1) / 2;
+            pod_col = n_col.div_ceil(2);
             ep_col = n_col + 1 - pod_col;
             det_col = 0;
         }
@@ -745,21 +1180,27 @@ impl<'a> Ui<'a> {
     /// Checks whether the user has downloaded any episodes for the
     /// given podcast to their local system.
     pub fn check_for_local_files(&self, pod_id: i64) -> bool {
-        let mut any_downloaded = false;
         let borrowed_map = self.podcast_menu.items.borrow_map();
         let borrowed_pod = borrowed_map
             .get(&pod_id)
             .expect("Could not retrieve podcast info.");
+        return borrowed_pod.any_downloaded();
+    }
 
-        let borrowed_ep_list = borrowed_pod.episodes.borrow_map();
-
-        for (_ep_id, ep) in borrowed_ep_list.iter() {
-            if ep.path.is_some() {
-                any_downloaded = true;
-                break;
-            }
+    /// Asks the main controller to load the episodes of the selected
+    /// podcast, if they have not been loaded yet. Each podcast is only
+    /// asked for once in a row, while the request is handled.
+    pub fn request_episodes(&mut self) -> Option<UiMsg> {
+        let pod_id = self.get_current_ids().0?;
+        let loaded = self
+            .podcast_menu
+            .items
+            .map_single(pod_id, |pod| pod.loaded)?;
+        if loaded || self.load_requested == Some(pod_id) {
+            return None;
         }
-        return any_downloaded;
+        self.load_requested = Some(pod_id);
+        return Some(UiMsg::LoadEpisodes(pod_id));
     }
 
     /// Spawns a "(y/n)" notification with the specified input
@@ -861,6 +1302,7 @@ impl<'a> Ui<'a> {
 
     /// Updates the details panel with information about the current
     /// podcast and episode, and redraws to the screen.
+    #[allow(clippy::unnecessary_unwrap)]
     pub fn update_details_panel(&mut self) {
         if self.details_panel.is_some() {
             let (curr_pod_id, curr_ep_id) = self.get_current_ids();
@@ -870,6 +1312,7 @@ impl<'a> Ui<'a> {
                     // get a couple details from the current podcast
                     let mut pod_title = None;
                     let mut pod_explicit = None;
+                    let mut pod_tags = None;
                     if let Some(pod) = self.podcast_menu.items.borrow_map().get(&pod_id) {
                         pod_title = if pod.title.is_empty() {
                             None
@@ -877,6 +1320,9 @@ impl<'a> Ui<'a> {
                             Some(pod.title.clone())
                         };
                         pod_explicit = pod.explicit;
+                        if !pod.tags.is_empty() {
+                            pod_tags = Some(pod.tags.join(", "));
+                        }
                     };
 
                     // the rest of the details come from the current episode
@@ -914,6 +1360,8 @@ impl<'a> Ui<'a> {
                             pubdate: ep.pubdate,
                             duration: Some(ep.format_duration()),
                             explicit: pod_explicit,
+                            tags: pod_tags,
+                            notes: ep.notes.iter().map(|note| note.format()).collect(),
                             description: desc,
                         };
                         det.change_details(details);
@@ -923,3 +1371,34 @@ impl<'a> Ui<'a> {
         }
     }
 }
+
+/// Reads how long the sleep timer should run for, as a number of
+/// minutes, or as hours and minutes like "1:30". Anything else,
+/// including a time with seconds, is rejected.
+fn parse_sleep_minutes(input: &str) -> Option<u64> {
+    if let Ok(mins) = input.parse::<u64>() {
+        return Some(mins);
+    }
+    let parts: Vec<&str> = input.split(':').collect();
+    if parts.len() != 2 || parts[1].len() != 2 {
+        return None;
+    }
+    return play_file::parse_timestamp(input).map(|mins| mins as u64);
+}
+
+
+// TESTS -----------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_timer_durations() {
+        assert_eq!(parse_sleep_minutes("45"), Some(45));
+        assert_eq!(parse_sleep_minutes("1:30"), Some(90));
+        assert_eq!(parse_sleep_minutes("0:05"), Some(5));
+        assert_eq!(parse_sleep_minutes("1:30:00"), None);
+        assert_eq!(parse_sleep_minutes("1:5"), None);
+        assert_eq!(parse_sleep_minutes("soon"), None);
+    }
+}
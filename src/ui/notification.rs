@@ -10,6 +10,8 @@ use crossterm::{
 };
 
 use super::AppColors;
+use crate::player::SleepTimer;
+use crate::types::StringUtils;
 
 /// Holds details of a notification message. The `expiry` is optional,
 /// and is used to create timed notifications -- `Instant` should refer
@@ -44,6 +46,10 @@ impl Notification {
 /// stack based on their expiry times. As such, it will generally be a
 /// FIFO approach (older notifications will generally expire first), but
 /// not necessarily.
+///
+/// The `status` is a short piece of text shown on the right-hand side
+/// of the window, alongside any notifications. It is made up of what is
+/// currently playing and the time left on the sleep timer, if set.
 #[derive(Debug)]
 pub struct NotifWin {
     colors: Rc<AppColors>,
@@ -53,6 +59,9 @@ pub struct NotifWin {
     msg_stack: Vec<Notification>,
     persistent_msg: Option<Notification>,
     current_msg: Option<Notification>,
+    status: Option<String>,
+    playing: Option<String>,
+    sleep_timer: Option<SleepTimer>,
 }
 
 impl NotifWin {
@@ -66,6 +75,9 @@ impl NotifWin {
             msg_stack: Vec::new(),
             persistent_msg: None,
             current_msg: None,
+            status: None,
+            playing: None,
+            sleep_timer: None,
         };
     }
 
@@ -85,11 +97,35 @@ impl NotifWin {
             ),
         )
         .unwrap();
+        self.draw_status();
+    }
+
+    /// Prints the status text on the right-hand side of the window.
+    fn draw_status(&self) {
+        if let Some(status) = &self.status {
+            let max_len = (self.total_cols as usize) / 2;
+            let status = status.substr(0, max_len);
+            let start_x = (self.total_cols as usize).saturating_sub(status.grapheme_len() + 1);
+            queue!(
+                io::stdout(),
+                cursor::MoveTo(start_x as u16, self.start_y),
+                style::PrintStyledContent(
+                    style::style(status)
+                        .with(self.colors.bold.0)
+                        .on(self.colors.bold.1)
+                ),
+            )
+            .unwrap();
+        }
     }
 
     /// Checks if the current notification needs to be changed, and
     /// updates the message window accordingly.
     pub fn check_notifs(&mut self) {
+        // the time left on the sleep timer counts down by itself
+        if self.sleep_timer.is_some() {
+            self.refresh_status();
+        }
         if !self.msg_stack.is_empty() {
             // compare expiry times of all notifications to current
             // time, remove expired ones
@@ -136,6 +172,7 @@ impl NotifWin {
     /// be specified as a prompt for the user at the beginning of the
     /// input line. This returns the user's input; if the user cancels
     /// their input, the String will be empty.
+    #[allow(clippy::collapsible_match)]
     pub fn input_notif(&self, prefix: &str) -> String {
         execute!(
             io::stdout(),
@@ -255,6 +292,7 @@ impl NotifWin {
             style::PrintStyledContent(styled)
         )
         .unwrap();
+        self.draw_status();
     }
 
     /// Adds a notification to the user. `duration` indicates how long
@@ -289,6 +327,43 @@ impl NotifWin {
         }
     }
 
+    /// Sets the text about what is currently playing, shown on the
+    /// right-hand side of the window, or removes it if `status` is None.
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.playing = status;
+        self.refresh_status();
+    }
+
+    /// Sets the sleep timer whose time left is shown on the right-hand
+    /// side of the window, or removes it if `timer` is None.
+    pub fn set_sleep_timer(&mut self, timer: Option<SleepTimer>) {
+        self.sleep_timer = timer;
+        self.refresh_status();
+    }
+
+    /// Puts together the status text from what is playing and the
+    /// sleep timer, and redraws the window if it has changed.
+    fn refresh_status(&mut self) {
+        let parts: Vec<String> = self
+            .playing
+            .iter()
+            .cloned()
+            .chain(self.sleep_timer.map(|timer| timer.format()))
+            .collect();
+        let status = if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(" | "))
+        };
+        if status != self.status {
+            self.status = status;
+            self.redraw();
+            if let Some(curr) = &self.current_msg {
+                self.display_notif(curr);
+            }
+        }
+    }
+
     /// Updates window size/location
     pub fn resize(&mut self, total_rows: u16, total_cols: u16) {
         self.total_rows = total_rows;
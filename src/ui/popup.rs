@@ -18,6 +18,10 @@ pub enum ActivePopup {
     WelcomeWin(Panel),
     HelpWin(Panel),
     DownloadWin(Menu<NewEpisode>),
+    QueueWin(Menu<QueueItem>),
+    StatsWin(Panel),
+    SearchWin(Menu<SearchResult>),
+    NotesWin(Menu<Note>),
     None,
 }
 
@@ -34,6 +38,22 @@ impl ActivePopup {
         return matches!(self, ActivePopup::DownloadWin(_));
     }
 
+    pub fn is_queue_win(&self) -> bool {
+        return matches!(self, ActivePopup::QueueWin(_));
+    }
+
+    pub fn is_stats_win(&self) -> bool {
+        return matches!(self, ActivePopup::StatsWin(_));
+    }
+
+    pub fn is_search_win(&self) -> bool {
+        return matches!(self, ActivePopup::SearchWin(_));
+    }
+
+    pub fn is_notes_win(&self) -> bool {
+        return matches!(self, ActivePopup::NotesWin(_));
+    }
+
     pub fn is_none(&self) -> bool {
         return matches!(self, ActivePopup::None);
     }
@@ -49,6 +69,13 @@ impl ActivePopup {
 pub struct PopupWin<'a> {
     popup: ActivePopup,
     new_episodes: Vec<NewEpisode>,
+    queue: Vec<QueueItem>,
+    stats: Vec<String>,
+    search_query: String,
+    search_results: Vec<SearchResult>,
+    notes_episode: (i64, i64),
+    notes_title: String,
+    notes: Vec<Note>,
     keymap: &'a Keybindings,
     colors: Rc<AppColors>,
     total_rows: u16,
@@ -56,6 +83,10 @@ pub struct PopupWin<'a> {
     pub welcome_win: bool,
     pub help_win: bool,
     pub download_win: bool,
+    pub queue_win: bool,
+    pub stats_win: bool,
+    pub search_win: bool,
+    pub notes_win: bool,
 }
 
 impl<'a> PopupWin<'a> {
@@ -69,6 +100,13 @@ impl<'a> PopupWin<'a> {
         return Self {
             popup: ActivePopup::None,
             new_episodes: Vec::new(),
+            queue: Vec::new(),
+            stats: Vec::new(),
+            search_query: String::new(),
+            search_results: Vec::new(),
+            notes_episode: (0, 0),
+            notes_title: String::new(),
+            notes: Vec::new(),
             keymap: keymap,
             colors: colors,
             total_rows: total_rows,
@@ -76,19 +114,34 @@ impl<'a> PopupWin<'a> {
             welcome_win: false,
             help_win: false,
             download_win: false,
+            queue_win: false,
+            stats_win: false,
+            search_win: false,
+            notes_win: false,
         };
     }
 
     /// Indicates whether any sort of popup window is currently on the
     /// screen.
     pub fn is_popup_active(&self) -> bool {
-        return self.welcome_win || self.help_win || self.download_win;
+        return self.welcome_win
+            || self.help_win
+            || self.download_win
+            || self.queue_win
+            || self.stats_win
+            || self.search_win
+            || self.notes_win;
     }
 
     /// Indicates whether a popup window *other than the welcome window*
     /// is currently on the screen.
     pub fn is_non_welcome_popup_active(&self) -> bool {
-        return self.help_win || self.download_win;
+        return self.help_win
+            || self.download_win
+            || self.queue_win
+            || self.stats_win
+            || self.search_win
+            || self.notes_win;
     }
 
     /// Resize the currently active popup window if one exists.
@@ -109,6 +162,34 @@ impl<'a> PopupWin<'a> {
                 download_win.activate();
                 self.popup = ActivePopup::DownloadWin(download_win);
             }
+            ActivePopup::QueueWin(ref win) => {
+                let selected = win.selected;
+                let mut queue_win = self.make_queue_win();
+                queue_win.selected = selected;
+                queue_win.redraw();
+                queue_win.activate();
+                self.popup = ActivePopup::QueueWin(queue_win);
+            }
+            ActivePopup::StatsWin(_win) => {
+                let stats_win = self.make_stats_win();
+                self.popup = ActivePopup::StatsWin(stats_win);
+            }
+            ActivePopup::SearchWin(ref win) => {
+                let selected = win.selected;
+                let mut search_win = self.make_search_win();
+                search_win.selected = selected;
+                search_win.redraw();
+                search_win.activate();
+                self.popup = ActivePopup::SearchWin(search_win);
+            }
+            ActivePopup::NotesWin(ref win) => {
+                let selected = win.selected;
+                let mut notes_win = self.make_notes_win();
+                notes_win.selected = selected;
+                notes_win.redraw();
+                notes_win.activate();
+                self.popup = ActivePopup::NotesWin(notes_win);
+            }
             ActivePopup::None => (),
         }
     }
@@ -192,6 +273,25 @@ impl<'a> PopupWin<'a> {
             (Some(UserAction::Play), "Play:"),
             (Some(UserAction::MarkPlayed), "Mark as played:"),
             (Some(UserAction::MarkAllPlayed), "Mark all as played:"),
+            (Some(UserAction::Star), "Star episode:"),
+            (Some(UserAction::Archive), "Archive podcast:"),
+            (Some(UserAction::PlayPause), "Play/pause:"),
+            (Some(UserAction::SeekForward), "Seek forward:"),
+            (Some(UserAction::SeekBackward), "Seek backward:"),
+            (Some(UserAction::SpeedUp), "Speed up:"),
+            (Some(UserAction::SpeedDown), "Slow down:"),
+            (Some(UserAction::StopPlayback), "Stop playback:"),
+            (Some(UserAction::SleepTimer), "Sleep timer:"),
+            (Some(UserAction::SleepTimerCustom), "Custom sleep timer:"),
+            (Some(UserAction::Enqueue), "Add to queue:"),
+            (Some(UserAction::EnqueueNext), "Add to front of queue:"),
+            (Some(UserAction::ShowQueue), "Show queue:"),
+            (Some(UserAction::PodcastSettings), "Podcast settings:"),
+            (Some(UserAction::EditTags), "Edit tags:"),
+            (Some(UserAction::ShowStats), "Listening stats:"),
+            (Some(UserAction::Search), "Search episodes:"),
+            (Some(UserAction::AddNote), "Add note:"),
+            (Some(UserAction::ShowNotes), "Show notes:"),
             // (None, ""),
             (Some(UserAction::Download), "Download:"),
             (Some(UserAction::DownloadAll), "Download all:"),
@@ -199,6 +299,8 @@ impl<'a> PopupWin<'a> {
             (Some(UserAction::DeleteAll), "Delete all files:"),
             (Some(UserAction::Remove), "Remove from list:"),
             (Some(UserAction::RemoveAll), "Remove all from list:"),
+            (Some(UserAction::Undo), "Undo:"),
+            (Some(UserAction::Unhide), "Unhide episode:"),
             // (None, ""),
             (Some(UserAction::Help), "Help:"),
             (Some(UserAction::Quit), "Quit:"),
@@ -261,7 +363,7 @@ impl<'a> PopupWin<'a> {
         } else {
             1
         };
-        let keys_per_row = key_strs.len() as u16 / n_cols;
+        let keys_per_row = (key_strs.len() as u16).div_ceil(n_cols);
 
         // write each line of keys -- the list will be presented "down"
         // rather than "across", but we print to the screen a line at a
@@ -330,6 +432,218 @@ impl<'a> PopupWin<'a> {
         return download_win;
     }
 
+    /// Create a new queue window and draw it to the screen.
+    pub fn spawn_queue_win(&mut self) {
+        self.queue_win = true;
+        self.change_win();
+    }
+
+    /// Create a new Menu holding the play queue.
+    pub fn make_queue_win(&self) -> Menu<QueueItem> {
+        // the warning on the unused mut is a function of Rust getting
+        // confused between panel.rs and mock_panel.rs
+        #[allow(unused_mut)]
+        let mut queue_panel = Panel::new(
+            "Play queue".to_string(),
+            0,
+            self.colors.clone(),
+            self.total_rows - 1,
+            self.total_cols,
+            0,
+            (1, 0, 0, 0),
+        );
+
+        let header = if self.queue.is_empty() {
+            format!(
+                "The play queue is empty. Add episodes to the end of the queue with {}, or to the front with {}. Press {} to exit the menu.",
+                self.list_keys(UserAction::Enqueue, Some(2)),
+                self.list_keys(UserAction::EnqueueNext, Some(2)),
+                self.list_keys(UserAction::Quit, Some(2)))
+        } else {
+            format!(
+                "Play an episode with {}. Move episodes up or down with {} and {}, or remove them from the queue with {}. Press {} to exit the menu.",
+                self.list_keys(UserAction::Play, Some(2)),
+                self.list_keys(UserAction::MoveUp, Some(2)),
+                self.list_keys(UserAction::MoveDown, Some(2)),
+                self.list_keys(UserAction::Remove, Some(2)),
+                self.list_keys(UserAction::Quit, Some(2)))
+        };
+        let mut queue_win = Menu::new(queue_panel, Some(header), LockVec::new(self.queue.clone()));
+        queue_win.redraw();
+
+        return queue_win;
+    }
+
+    /// Replaces the contents of the play queue, and redraws the queue
+    /// window if it is currently open.
+    pub fn update_queue(&mut self, queue: Vec<QueueItem>) {
+        let header_changed = self.queue.is_empty() != queue.is_empty();
+        self.queue = queue;
+        if header_changed && self.popup.is_queue_win() {
+            let mut queue_win = self.make_queue_win();
+            queue_win.activate();
+            self.popup = ActivePopup::QueueWin(queue_win);
+        } else if let ActivePopup::QueueWin(ref mut menu) = self.popup {
+            menu.items.replace_all(self.queue.clone());
+            menu.redraw();
+            menu.highlight_selected();
+        }
+    }
+
+    /// Create a new window showing listening statistics, given as lines
+    /// of text, and draw it to the screen.
+    pub fn spawn_stats_win(&mut self, stats: Vec<String>) {
+        self.stats = stats;
+        self.stats_win = true;
+        self.change_win();
+    }
+
+    /// Create a new Panel holding the listening statistics.
+    pub fn make_stats_win(&self) -> Panel {
+        // the warning on the unused mut is a function of Rust getting
+        // confused between panel.rs and mock_panel.rs
+        #[allow(unused_mut)]
+        let mut stats_win = Panel::new(
+            "Listening statistics".to_string(),
+            0,
+            self.colors.clone(),
+            self.total_rows - 1,
+            self.total_cols,
+            0,
+            (1, 1, 1, 1),
+        );
+        stats_win.redraw();
+
+        let mut row = 0;
+        for line in self.stats.iter() {
+            row = stats_win.write_wrap_line(row, line, None) + 1;
+        }
+        let _ = stats_win.write_wrap_line(row + 1, "Press \"q\" to close this window.", None);
+        return stats_win;
+    }
+
+    /// Create a new window listing the episodes found by searching for
+    /// `query`, and draw it to the screen.
+    pub fn spawn_search_win(&mut self, query: String, results: Vec<SearchResult>) {
+        self.search_query = query;
+        self.search_results = results;
+        self.search_win = true;
+        // replace the results if the window is already open
+        if self.popup.is_search_win() {
+            let mut win = self.make_search_win();
+            win.activate();
+            self.popup = ActivePopup::SearchWin(win);
+        } else {
+            self.change_win();
+        }
+    }
+
+    /// Create a new Menu holding the search results.
+    pub fn make_search_win(&self) -> Menu<SearchResult> {
+        // the warning on the unused mut is a function of Rust getting
+        // confused between panel.rs and mock_panel.rs
+        #[allow(unused_mut)]
+        let mut search_panel = Panel::new(
+            "Search".to_string(),
+            0,
+            self.colors.clone(),
+            self.total_rows - 1,
+            self.total_cols,
+            0,
+            (1, 0, 0, 0),
+        );
+
+        let header = if self.search_results.is_empty() {
+            format!(
+                "No episodes found for \"{}\". Press {} to search again, or {} to exit the menu.",
+                self.search_query,
+                self.list_keys(UserAction::Search, Some(2)),
+                self.list_keys(UserAction::Quit, Some(2)))
+        } else {
+            format!(
+                "Episodes matching \"{}\". Go to an episode with {}, or search again with {}. Press {} to exit the menu.",
+                self.search_query,
+                self.list_keys(UserAction::Play, Some(2)),
+                self.list_keys(UserAction::Search, Some(2)),
+                self.list_keys(UserAction::Quit, Some(2)))
+        };
+        let mut search_win = Menu::new(
+            search_panel,
+            Some(header),
+            LockVec::new(self.search_results.clone()),
+        );
+        search_win.redraw();
+
+        return search_win;
+    }
+
+    /// Create a new window listing the notes on an episode, and draw it
+    /// to the screen.
+    pub fn spawn_notes_win(&mut self, pod_id: i64, ep_id: i64, title: String, notes: Vec<Note>) {
+        self.notes_episode = (pod_id, ep_id);
+        self.notes_title = title;
+        self.notes = notes;
+        self.notes_win = true;
+        self.change_win();
+    }
+
+    /// Returns the podcast and episode whose notes are shown in the
+    /// notes window, if it is open.
+    pub fn notes_episode(&self) -> Option<(i64, i64)> {
+        if self.notes_win {
+            return Some(self.notes_episode);
+        }
+        return None;
+    }
+
+    /// Create a new Menu holding the notes on an episode.
+    pub fn make_notes_win(&self) -> Menu<Note> {
+        // the warning on the unused mut is a function of Rust getting
+        // confused between panel.rs and mock_panel.rs
+        #[allow(unused_mut)]
+        let mut notes_panel = Panel::new(
+            format!("Notes: {}", self.notes_title),
+            0,
+            self.colors.clone(),
+            self.total_rows - 1,
+            self.total_cols,
+            0,
+            (1, 0, 0, 0),
+        );
+
+        let header = if self.notes.is_empty() {
+            format!(
+                "There are no notes on this episode. Add one with {}; start it with a time like 42:10 to make it a bookmark. Press {} to exit the menu.",
+                self.list_keys(UserAction::AddNote, Some(2)),
+                self.list_keys(UserAction::Quit, Some(2)))
+        } else {
+            format!(
+                "Jump to a bookmark with {}. Add a note with {}, or remove one with {}. Press {} to exit the menu.",
+                self.list_keys(UserAction::Play, Some(2)),
+                self.list_keys(UserAction::AddNote, Some(2)),
+                self.list_keys(UserAction::Remove, Some(2)),
+                self.list_keys(UserAction::Quit, Some(2)))
+        };
+        let mut notes_win = Menu::new(notes_panel, Some(header), LockVec::new(self.notes.clone()));
+        notes_win.redraw();
+
+        return notes_win;
+    }
+
+    /// Replaces the notes shown in the notes window, and redraws it.
+    pub fn update_notes(&mut self, notes: Vec<Note>) {
+        self.notes = notes;
+        if let ActivePopup::NotesWin(ref win) = self.popup {
+            // keep the cursor in place, unless the last note was removed
+            let index = win.get_menu_idx(win.selected);
+            let mut notes_win = self.make_notes_win();
+            notes_win.select_index(min(index, self.notes.len().saturating_sub(1)));
+            notes_win.redraw();
+            notes_win.activate();
+            self.popup = ActivePopup::NotesWin(notes_win);
+        }
+    }
+
     /// Appends a new episode to the list of new episodes.
     pub fn _add_episodes(&mut self, mut episodes: Vec<NewEpisode>) {
         self.new_episodes.append(&mut episodes);
@@ -353,6 +667,30 @@ impl<'a> PopupWin<'a> {
         self.change_win();
     }
 
+    /// Gets rid of the queue window.
+    pub fn turn_off_queue_win(&mut self) {
+        self.queue_win = false;
+        self.change_win();
+    }
+
+    /// Gets rid of the stats window.
+    pub fn turn_off_stats_win(&mut self) {
+        self.stats_win = false;
+        self.change_win();
+    }
+
+    /// Gets rid of the search window.
+    pub fn turn_off_search_win(&mut self) {
+        self.search_win = false;
+        self.change_win();
+    }
+
+    /// Gets rid of the notes window.
+    pub fn turn_off_notes_win(&mut self) {
+        self.notes_win = false;
+        self.change_win();
+    }
+
     /// When there is a change to the active popup window, this should
     /// be called to check for other popup windows that are "in the
     /// queue" -- this lets one popup window appear over top of another
@@ -366,14 +704,36 @@ impl<'a> PopupWin<'a> {
         if self.help_win && !self.popup.is_help_win() {
             let win = self.make_help_win();
             self.popup = ActivePopup::HelpWin(win);
+        } else if self.stats_win && !self.popup.is_stats_win() {
+            let win = self.make_stats_win();
+            self.popup = ActivePopup::StatsWin(win);
+        } else if self.search_win && !self.popup.is_search_win() {
+            let mut win = self.make_search_win();
+            win.activate();
+            self.popup = ActivePopup::SearchWin(win);
+        } else if self.notes_win && !self.popup.is_notes_win() {
+            let mut win = self.make_notes_win();
+            win.activate();
+            self.popup = ActivePopup::NotesWin(win);
         } else if self.download_win && !self.popup.is_download_win() {
             let mut win = self.make_download_win();
             win.activate();
             self.popup = ActivePopup::DownloadWin(win);
+        } else if self.queue_win && !self.download_win && !self.popup.is_queue_win() {
+            let mut win = self.make_queue_win();
+            win.activate();
+            self.popup = ActivePopup::QueueWin(win);
         } else if self.welcome_win && !self.popup.is_welcome_win() {
             let win = self.make_welcome_win();
             self.popup = ActivePopup::WelcomeWin(win);
-        } else if !self.help_win && !self.download_win && !self.welcome_win && !self.popup.is_none()
+        } else if !self.help_win
+            && !self.download_win
+            && !self.queue_win
+            && !self.stats_win
+            && !self.search_win
+            && !self.notes_win
+            && !self.welcome_win
+            && !self.popup.is_none()
         {
             self.popup = ActivePopup::None;
         }
@@ -395,6 +755,13 @@ impl<'a> PopupWin<'a> {
                     _ => (),
                 }
             }
+            ActivePopup::StatsWin(ref mut _win) => match self.keymap.get_from_input(input) {
+                Some(UserAction::Quit) | Some(UserAction::ShowStats) => self.turn_off_stats_win(),
+                Some(_) | None => match input.code {
+                    KeyCode::Esc | KeyCode::Char('\u{1b}') => self.turn_off_stats_win(),
+                    _ => (),
+                },
+            },
             ActivePopup::DownloadWin(ref mut menu) => match self.keymap.get_from_input(input) {
                 Some(UserAction::Down) => menu.scroll(Scroll::Down(1)),
                 Some(UserAction::Up) => menu.scroll(Scroll::Up(1)),
@@ -425,6 +792,100 @@ impl<'a> PopupWin<'a> {
 
                 Some(_) | None => (),
             },
+            ActivePopup::QueueWin(ref mut menu) => match self.keymap.get_from_input(input) {
+                Some(UserAction::Down) => menu.scroll(Scroll::Down(1)),
+                Some(UserAction::Up) => menu.scroll(Scroll::Up(1)),
+
+                Some(UserAction::Play) => {
+                    if let Some(item) = menu.get_selected() {
+                        msg = UiMsg::Play(item.pod_id, item.id);
+                    }
+                }
+
+                Some(UserAction::Remove) => {
+                    if let Some(item) = menu.get_selected() {
+                        msg = UiMsg::QueueRemove(item.id);
+                    }
+                }
+
+                // the cursor follows the episode as it moves
+                Some(UserAction::MoveUp) => {
+                    if let Some(item) = menu.get_selected() {
+                        msg = UiMsg::QueueMove(item.id, -1);
+                        menu.scroll(Scroll::Up(1));
+                    }
+                }
+                Some(UserAction::MoveDown) => {
+                    if let Some(item) = menu.get_selected() {
+                        msg = UiMsg::QueueMove(item.id, 1);
+                        menu.scroll(Scroll::Down(1));
+                    }
+                }
+
+                Some(UserAction::Quit) | Some(UserAction::ShowQueue) => {
+                    self.turn_off_queue_win();
+                }
+
+                Some(_) | None => match input.code {
+                    KeyCode::Esc | KeyCode::Char('\u{1b}') => self.turn_off_queue_win(),
+                    _ => (),
+                },
+            },
+            ActivePopup::SearchWin(ref mut menu) => match self.keymap.get_from_input(input) {
+                Some(UserAction::Down) => menu.scroll(Scroll::Down(1)),
+                Some(UserAction::Up) => menu.scroll(Scroll::Up(1)),
+
+                Some(UserAction::Play) | Some(UserAction::Right) => {
+                    if let Some(result) = menu.get_selected() {
+                        msg = UiMsg::GoToEpisode(result.pod_id, result.id);
+                        self.turn_off_search_win();
+                    }
+                }
+
+                Some(UserAction::Search) => {
+                    msg = UiMsg::SearchPrompt;
+                }
+
+                Some(UserAction::Quit) => self.turn_off_search_win(),
+
+                Some(_) | None => match input.code {
+                    KeyCode::Esc | KeyCode::Char('\u{1b}') => self.turn_off_search_win(),
+                    _ => (),
+                },
+            },
+            ActivePopup::NotesWin(ref mut menu) => match self.keymap.get_from_input(input) {
+                Some(UserAction::Down) => menu.scroll(Scroll::Down(1)),
+                Some(UserAction::Up) => menu.scroll(Scroll::Up(1)),
+
+                Some(UserAction::Play) => {
+                    let (pod_id, ep_id) = self.notes_episode;
+                    if let Some(position) = menu.get_selected().and_then(|note| note.position) {
+                        msg = UiMsg::JumpToNote(pod_id, ep_id, position);
+                        self.turn_off_notes_win();
+                    }
+                }
+
+                Some(UserAction::AddNote) => {
+                    let (pod_id, ep_id) = self.notes_episode;
+                    msg = UiMsg::NotePrompt(pod_id, ep_id);
+                }
+
+                Some(UserAction::Remove) => {
+                    let (pod_id, ep_id) = self.notes_episode;
+                    if let Some(note) = menu.get_selected() {
+                        msg = UiMsg::RemoveNote(pod_id, ep_id, note.id);
+                    }
+                }
+
+                Some(UserAction::Quit) | Some(UserAction::ShowNotes) => {
+                    self.turn_off_notes_win();
+                }
+
+                Some(_) | None => match input.code {
+                    KeyCode::Esc | KeyCode::Char('\u{1b}') => self.turn_off_notes_win(),
+                    _ => (),
+                },
+            },
             _ => (),
         }
         return msg;
@@ -0,0 +1,183 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+
+/// Number of actions that can be undone. Older actions are forgotten,
+/// and anything they moved to the trash is deleted for good.
+pub const UNDO_LIMIT: usize = 10;
+
+/// Name of the folder where deleted episode files are kept until they
+/// can no longer be undone. It is inside the download directory, so
+/// that moving files there is a quick rename rather than a copy.
+pub const TRASH_DIR_NAME: &str = ".shellcaster-trash";
+
+/// A downloaded episode file that was moved to the trash folder instead
+/// of being deleted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashedFile {
+    pub ep_id: i64,
+    pub path: PathBuf,
+    pub trash_path: PathBuf,
+}
+
+/// An action that can be undone, along with what is needed to reverse
+/// it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UndoAction {
+    /// Downloaded files deleted from a podcast.
+    DeleteFiles { pod_id: i64, files: Vec<TrashedFile> },
+    /// Episodes removed (hidden) from a podcast's list, and any of their
+    /// files that were deleted.
+    RemoveEpisodes {
+        pod_id: i64,
+        ep_ids: Vec<i64>,
+        files: Vec<TrashedFile>,
+    },
+    /// A podcast removed from the list, which is kept in the trash table
+    /// of the database under `trash_id`, and any of its files that were
+    /// deleted.
+    RemovePodcast {
+        trash_id: i64,
+        title: String,
+        files: Vec<TrashedFile>,
+    },
+    /// All episodes of a podcast marked as played or unplayed, with the
+    /// played status each episode had before, and the episode actions
+    /// recorded for syncing, as pairs of episode id and action id.
+    MarkAllPlayed {
+        pod_id: i64,
+        played: Vec<(i64, bool)>,
+        actions: Vec<(i64, i64)>,
+    },
+}
+
+impl UndoAction {
+    /// Returns the files that the action moved to the trash folder.
+    pub fn files(&self) -> &[TrashedFile] {
+        return match self {
+            UndoAction::DeleteFiles { files, .. } => files,
+            UndoAction::RemoveEpisodes { files, .. } => files,
+            UndoAction::RemovePodcast { files, .. } => files,
+            UndoAction::MarkAllPlayed { .. } => &[],
+        };
+    }
+}
+
+/// Returns the trash folder for episodes downloaded to `download_path`.
+pub fn trash_dir(download_path: &Path) -> PathBuf {
+    return download_path.join(TRASH_DIR_NAME);
+}
+
+/// Returns whether `path` is inside a trash folder. Files in there are
+/// not downloads, and should be left alone.
+pub fn in_trash(path: &Path) -> bool {
+    return path.components().any(|part| part.as_os_str() == TRASH_DIR_NAME);
+}
+
+/// Moves the downloaded file at `path` for an episode into the trash
+/// folder `trash_dir`, so that it can be put back later.
+pub fn trash_file(trash_dir: &Path, ep_id: i64, path: &Path) -> Result<TrashedFile> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // the time keeps names unique if the same episode is downloaded and
+    // deleted more than once
+    let trash_path = trash_dir.join(format!(
+        "{ep_id}-{}-{name}",
+        Utc::now().format("%Y%m%d%H%M%S%6f")
+    ));
+    move_file(path, &trash_path)?;
+    return Ok(TrashedFile {
+        ep_id: ep_id,
+        path: path.to_path_buf(),
+        trash_path: trash_path,
+    });
+}
+
+/// Moves a file from the trash folder back to where it was.
+pub fn restore_file(file: &TrashedFile) -> Result<()> {
+    if file.path.exists() {
+        return Err(anyhow!("{} already exists", file.path.to_string_lossy()));
+    }
+    return move_file(&file.trash_path, &file.path);
+}
+
+/// Deletes files in the trash folder for good.
+pub fn purge_files(files: &[TrashedFile]) {
+    for file in files.iter() {
+        let _ = fs::remove_file(&file.trash_path);
+    }
+}
+
+/// Deletes the trash folder and everything in it.
+pub fn empty_trash_dir(trash_dir: &Path) -> Result<()> {
+    if trash_dir.exists() {
+        fs::remove_dir_all(trash_dir)
+            .with_context(|| format!("Could not empty {}", trash_dir.to_string_lossy()))?;
+    }
+    return Ok(());
+}
+
+/// Moves a file to a new location, creating any parent directories as
+/// needed. Falls back to copying and deleting the original if the file
+/// cannot simply be renamed (e.g., when moving across filesystems).
+pub fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Could not create {}", parent.to_string_lossy()))?;
+    }
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to).with_context(|| {
+            format!(
+                "Could not move {} to {}",
+                from.to_string_lossy(),
+                to.to_string_lossy()
+            )
+        })?;
+        fs::remove_file(from)?;
+    }
+    return Ok(());
+}
+
+
+// TESTS -----------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::temp_dir;
+
+    #[test]
+    fn trash_and_restore_file() {
+        let dir = temp_dir("trash");
+        let path = dir.join("Some Podcast").join("episode.mp3");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, b"audio").unwrap();
+
+        let trash_dir = trash_dir(&dir);
+        let file = trash_file(&trash_dir, 7, &path).unwrap();
+        assert!(!path.exists());
+        assert!(file.trash_path.starts_with(&trash_dir));
+        assert!(in_trash(&file.trash_path));
+        assert!(!in_trash(&path));
+        assert_eq!(fs::read(&file.trash_path).unwrap(), b"audio");
+
+        // the podcast's folder may have been removed in the meantime
+        fs::remove_dir(path.parent().unwrap()).unwrap();
+        restore_file(&file).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"audio");
+        assert!(!file.trash_path.exists());
+
+        // a file that has come back in the meantime is not overwritten
+        let file = trash_file(&trash_dir, 7, &path).unwrap();
+        fs::write(&path, b"new download").unwrap();
+        assert!(restore_file(&file).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"new download");
+
+        empty_trash_dir(&trash_dir).unwrap();
+        assert!(!trash_dir.exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}